@@ -1,22 +1,30 @@
 use anyhow::{bail, Context};
+use chrono::{DateTime, Utc};
 use eframe::egui;
 use futures::lock::Mutex;
-use log::error;
+use log::{error, warn};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::env;
-use std::ffi::{OsStr, OsString};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt;
 use std::fs;
 use std::fs::File;
 use std::io::{ErrorKind as IoErrorKind, Result as IoResult, Write};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+use crate::config::{ContextConfig, UserConfig};
+use crate::contexts::{self, ContextOverride};
+use crate::history::HistoryEntry;
+use crate::lint::{self, LintWarning};
+use crate::paths::{self, app_data_dir};
 use crate::registry_utils;
 use crate::registry_utils::Browser;
-
-const LOCALAPPDATA: &str = "LOCALAPPDATA";
-const PROGRAM_NAME: &str = "ChromeValet";
+use crate::rules::{self, Link, RoutingRule, RuleLayer};
+use crate::suggestions::{self, RuleSuggestion};
+use crate::usage::ProfileUsage;
 
 pub struct ChromeProfilePicture {
     picture_filename: OsString,
@@ -26,15 +34,25 @@ pub struct ChromeProfilePicture {
 }
 
 impl ChromeProfilePicture {
-    pub fn new(profile_dir: &String, img_filename: &OsString, profile_color: &[u8; 4]) -> Self {
+    /// picture from an image file chosen by the user
+    pub fn from_path(picture_path: &Path, profile_color: &[u8; 4]) -> Self {
+        ChromeProfilePicture {
+            picture_filename: picture_path.as_os_str().to_os_string(),
+            img: None,
+            profile_texture: None,
+            profile_color: *profile_color,
+        }
+    }
+
+    pub fn new(
+        user_data_dir: &Path,
+        profile_dir: &String,
+        img_filename: &OsString,
+        profile_color: &[u8; 4],
+    ) -> Self {
         ChromeProfilePicture {
             picture_filename: OsString::from(match img_filename.is_empty() {
-                false => PathBuf::from(env::var(LOCALAPPDATA).unwrap())
-                    .join("Google")
-                    .join("Chrome")
-                    .join("User Data")
-                    .join(profile_dir)
-                    .join(img_filename),
+                false => user_data_dir.join(profile_dir).join(img_filename),
                 true => PathBuf::default(),
             }),
             img: None,
@@ -44,11 +62,6 @@ impl ChromeProfilePicture {
     }
 }
 
-pub fn app_data_dir() -> PathBuf {
-    let local_app_data = env::var(LOCALAPPDATA).unwrap();
-    PathBuf::from(local_app_data).join(PROGRAM_NAME)
-}
-
 impl ChromeProfilePicture {
     fn apply_circle_mask(&mut self) {
         if self.img.is_none() {
@@ -85,7 +98,12 @@ impl ChromeProfilePicture {
             fn load_image_from_path(
                 path: &std::path::Path,
             ) -> Result<Option<egui::ColorImage>, image::ImageError> {
-                let image = image::io::Reader::open(path)?.decode()?;
+                let mut image = image::io::Reader::open(path)?.decode()?;
+                if image.width() != image.height() {
+                    // custom icons can be any shape, the circle mask wants a square
+                    let dim = image.width().min(image.height());
+                    image = image.resize_to_fill(dim, dim, image::imageops::FilterType::Triangle);
+                }
                 let size = [image.width() as _, image.height() as _];
                 let image_buffer = image.to_rgba8();
                 let pixels = image_buffer.as_flat_samples();
@@ -114,22 +132,139 @@ impl ChromeProfilePicture {
     }
 }
 
+/// where a profile's picture comes from, compared to spot avatar changes
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileAvatar {
+    pub picture_filename: OsString,
+    pub color: [u8; 4],
+    pub custom_icon: Option<PathBuf>,
+}
+
 #[derive(Debug)]
 pub struct ChromeProfileEntry {
     pub profile_directory: String,
     pub profile_name: String,
     pub profile_picture: Arc<Mutex<ChromeProfilePicture>>,
+    pub avatar: ProfileAvatar,
+    /// when the browser last used the profile, seconds since the epoch
+    pub active_time: f64,
+    /// email of the account signed in to the profile, empty if none
+    pub user_name: String,
+}
+
+/// profile directories touched by a Local State refresh
+#[derive(Debug, Default)]
+pub struct ProfileChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub renamed: Vec<String>,
+    pub avatar_changed: Vec<String>,
+}
+
+impl ProfileChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.renamed.is_empty()
+            && self.avatar_changed.is_empty()
+    }
+
+    /// entries whose picture needs loading
+    pub fn needs_picture(&self, profile_directory: &String) -> bool {
+        self.added.contains(profile_directory) || self.avatar_changed.contains(profile_directory)
+    }
 }
 
 unsafe impl Send for ChromeProfilePicture {}
 
+///
+/// how a profile is shown in the picker, set by the user
+///
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileDisplaySettings {
+    /// position in the picker, profiles without one go last, sorted by name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<u32>,
+
+    #[serde(default)]
+    pub hidden: bool,
+
+    /// shown instead of the profile's name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+
+    /// image shown instead of the profile's picture
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon_path: Option<String>,
+}
+
 #[derive(Default, Serialize, Deserialize)]
 pub struct ProgramPrefs {
     #[serde(default)]
     pub preferred_profile: String,
 
+    /// account the preferred profile was signed in to, for finding it again if it's deleted
+    #[serde(default)]
+    pub preferred_profile_user_name: String,
+
     #[serde(default)]
     pub default_browser: String,
+
+    /// rules remembered from choices made in chrome valet, checked after chromevalet.toml
+    #[serde(default)]
+    pub rules: Vec<RoutingRule>,
+
+    /// hosts whose suggested rule was turned down
+    #[serde(default)]
+    pub dismissed_suggestions: Vec<String>,
+
+    /// name of the context in use, from [contexts] in chromevalet.toml
+    #[serde(default)]
+    pub active_context: Option<String>,
+
+    /// a context used for a while instead of `active_context`
+    #[serde(default)]
+    pub context_override: Option<ContextOverride>,
+
+    /// keyed by profile directory
+    #[serde(default)]
+    pub profile_settings: BTreeMap<String, ProfileDisplaySettings>,
+
+    #[serde(default)]
+    pub profile_sort: ProfileSortMode,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileSortMode {
+    /// the order the user dragged profiles into
+    #[default]
+    Manual,
+    /// most recently active in the browser first
+    RecentlyActive,
+    /// most links opened through chrome valet first
+    MostUsed,
+    Alphabetical,
+}
+
+impl ProfileSortMode {
+    pub const ALL: [ProfileSortMode; 4] = [
+        ProfileSortMode::Manual,
+        ProfileSortMode::RecentlyActive,
+        ProfileSortMode::MostUsed,
+        ProfileSortMode::Alphabetical,
+    ];
+}
+
+impl fmt::Display for ProfileSortMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileSortMode::Manual => write!(f, "Manual"),
+            ProfileSortMode::RecentlyActive => write!(f, "Recently active"),
+            ProfileSortMode::MostUsed => write!(f, "Most used"),
+            ProfileSortMode::Alphabetical => write!(f, "Alphabetical"),
+        }
+    }
 }
 
 impl ProgramPrefs {
@@ -141,9 +276,63 @@ impl ProgramPrefs {
         self.preferred_profile = profile_dir.to_string();
     }
 
+    ///
+    /// adds a remembered rule, replacing one with the same conditions
+    /// it goes ahead of any other rule for the same host, so it's the one that's used
+    ///
+    pub fn remember_rule(&mut self, rule: RoutingRule) {
+        if let Some(existing) = self
+            .rules
+            .iter_mut()
+            .find(|existing| existing.same_conditions(&rule))
+        {
+            *existing = rule;
+            return;
+        }
+
+        let same_host = self
+            .rules
+            .iter()
+            .position(|existing| existing.regex.is_none() && existing.host == rule.host);
+        match same_host {
+            Some(index) => self.rules.insert(index, rule),
+            None => self.rules.push(rule),
+        }
+    }
+
+    /// switches context, ending any override, None stops using contexts
+    pub fn use_context(&mut self, name: Option<String>) {
+        self.active_context = name;
+        self.context_override = None;
+    }
+
+    /// uses a context until `until`, then goes back to `active_context`
+    pub fn override_context(&mut self, name: String, until: u64) {
+        self.context_override = Some(ContextOverride {
+            context: name,
+            until,
+        });
+    }
+
     pub fn prefs_path() -> PathBuf {
         app_data_dir().join("prefs.json")
     }
+
+    pub fn profile_settings(&self, profile_dir: &str) -> ProfileDisplaySettings {
+        self.profile_settings
+            .get(profile_dir)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn set_profile_settings(&mut self, profile_dir: &str, settings: ProfileDisplaySettings) {
+        if settings == ProfileDisplaySettings::default() {
+            self.profile_settings.remove(profile_dir);
+        } else {
+            self.profile_settings
+                .insert(profile_dir.to_string(), settings);
+        }
+    }
 }
 
 #[derive(Default)]
@@ -151,27 +340,306 @@ pub struct ChromeInterface {
     pub profile_entries: Vec<ChromeProfileEntry>,
     statefile_path: OsString,
     prefs: ProgramPrefs,
+    config: UserConfig,
+    usage: ProfileUsage,
+    load_errors: Vec<String>,
+    rule_warnings: Vec<LintWarning>,
 }
 
 impl ChromeInterface {
+    ///
+    /// `config` and `prefs` with a profile for each of `profile_dirs`, without reading any files
+    ///
+    #[cfg(test)]
+    pub fn with_profiles(config: UserConfig, prefs: ProgramPrefs, profile_dirs: &[&str]) -> Self {
+        let profile_entries = profile_dirs
+            .iter()
+            .map(|dir| {
+                let avatar = ProfileAvatar {
+                    picture_filename: OsString::new(),
+                    color: [128, 128, 128, 0],
+                    custom_icon: None,
+                };
+                ChromeProfileEntry {
+                    profile_directory: dir.to_string(),
+                    profile_name: dir.to_string(),
+                    profile_picture: Arc::new(Mutex::new(ChromeProfilePicture::new(
+                        Path::new(""),
+                        &dir.to_string(),
+                        &avatar.picture_filename,
+                        &avatar.color,
+                    ))),
+                    avatar,
+                    active_time: 0.0,
+                    user_name: String::new(),
+                }
+            })
+            .collect();
+
+        ChromeInterface {
+            profile_entries,
+            config,
+            prefs,
+            ..Default::default()
+        }
+    }
+
     pub fn new() -> Self {
-        let local_app_data = env::var(LOCALAPPDATA).unwrap();
-        let browser_appdata_dir = Browser::Chrome.get_definition().unwrap().app_data_dir;
         let mut chrome_interface = ChromeInterface {
             profile_entries: Vec::new(),
-            statefile_path: PathBuf::from(local_app_data)
-                .join(browser_appdata_dir)
-                .join("User Data")
+            statefile_path: paths::browser_user_data_dir(&Browser::Chrome)
                 .join("Local State")
                 .into_os_string(),
             prefs: ProgramPrefs::default(),
+            config: UserConfig::default(),
+            usage: ProfileUsage::default(),
+            load_errors: Vec::new(),
+            rule_warnings: Vec::new(),
         };
 
-        if let Err(err) = chrome_interface.read_prefs() {
+        chrome_interface.reload_config();
+        chrome_interface
+    }
+
+    ///
+    /// re-reads chromevalet.toml and prefs.json
+    /// a file that fails to parse keeps its previous values, the error is kept for the ui
+    ///
+    pub fn reload_config(&mut self) {
+        self.load_errors.clear();
+
+        if let Err(err) = self.read_config() {
+            error!("failed to read config: {err:#}");
+            self.load_errors.push(format!("{err:#}"));
+        }
+
+        if let Err(err) = self.read_prefs() {
             error!("failed to read prefs: {err}");
+            self.load_errors.push(format!(
+                "couldn't read {}: {err}",
+                ProgramPrefs::prefs_path().display()
+            ));
         }
 
-        chrome_interface
+        match ProfileUsage::read() {
+            Ok(usage) => self.usage = usage,
+            Err(err) => error!("failed to read usage: {err}"),
+        }
+    }
+
+    /// re-reads the browser's Local State, dropping everything if the browser changed
+    pub fn reload_profiles(
+        &mut self,
+        browser_changed: bool,
+    ) -> Result<ProfileChanges, anyhow::Error> {
+        if browser_changed {
+            self.profile_entries.clear();
+        }
+        self.populate_profile_entries()
+    }
+
+    /// name shown in the picker, the user's alias if they set one
+    pub fn display_name(&self, profile_entry: &ChromeProfileEntry) -> String {
+        self.prefs
+            .profile_settings(&profile_entry.profile_directory)
+            .alias
+            .filter(|alias| !alias.is_empty())
+            .unwrap_or_else(|| profile_entry.profile_name.clone())
+    }
+
+    ///
+    /// profile entries in the order the picker shows them
+    ///
+    pub fn display_entries(&self, include_hidden: bool) -> Vec<&ChromeProfileEntry> {
+        let mut entries: Vec<&ChromeProfileEntry> = self
+            .profile_entries
+            .iter()
+            .filter(|entry| {
+                include_hidden || !self.prefs.profile_settings(&entry.profile_directory).hidden
+            })
+            .collect();
+
+        let name_key = |entry: &ChromeProfileEntry| self.display_name(entry).to_lowercase();
+        match self.prefs.profile_sort {
+            ProfileSortMode::Manual => entries.sort_by_cached_key(|entry| {
+                let order = self.prefs.profile_settings(&entry.profile_directory).order;
+                (order.is_none(), order, name_key(entry))
+            }),
+            ProfileSortMode::RecentlyActive => entries.sort_by_cached_key(|entry| {
+                // active_time has sub-second precision, milliseconds are plenty to sort on
+                let active_millis = (entry.active_time * 1000.0) as i64;
+                (Reverse(active_millis), name_key(entry))
+            }),
+            ProfileSortMode::MostUsed => entries.sort_by_cached_key(|entry| {
+                let open_count = self.usage.open_count(&entry.profile_directory);
+                (Reverse(open_count), name_key(entry))
+            }),
+            ProfileSortMode::Alphabetical => entries.sort_by_cached_key(|entry| name_key(entry)),
+        }
+
+        entries
+    }
+
+    pub fn hidden_profile_count(&self) -> usize {
+        self.profile_entries
+            .iter()
+            .filter(|entry| self.prefs.profile_settings(&entry.profile_directory).hidden)
+            .count()
+    }
+
+    ///
+    /// moves `profile_dir` to `position` in the display order, numbering every shown profile
+    /// dragging a profile switches to the manual order, starting from what's on screen
+    ///
+    pub fn move_profile(&mut self, profile_dir: &str, position: usize, include_hidden: bool) {
+        let mut order: Vec<String> = self
+            .display_entries(include_hidden)
+            .iter()
+            .map(|entry| entry.profile_directory.clone())
+            .filter(|dir| dir != profile_dir)
+            .collect();
+        order.insert(position.min(order.len()), profile_dir.to_string());
+
+        for (index, dir) in order.iter().enumerate() {
+            let mut settings = self.prefs.profile_settings(dir);
+            settings.order = Some(index as u32);
+            self.prefs.set_profile_settings(dir, settings);
+        }
+
+        self.prefs.profile_sort = ProfileSortMode::Manual;
+    }
+
+    pub fn has_profile(&self, profile_dir: &str) -> bool {
+        self.profile_entries
+            .iter()
+            .any(|entry| entry.profile_directory == profile_dir)
+    }
+
+    ///
+    /// warnings about the preferred profile or rules pointing at profiles the browser doesn't have
+    ///
+    pub fn profile_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.profile_entries.is_empty() {
+            // couldn't read the profiles, nothing to check against
+            return warnings;
+        }
+
+        let preferred_profile = &self.prefs.preferred_profile;
+        if !preferred_profile.is_empty() && !self.has_profile(preferred_profile) {
+            warnings.push(format!(
+                "Preferred profile \"{preferred_profile}\" no longer exists, pick a new one."
+            ));
+        }
+
+        for rule in self.config.rules.iter().chain(self.prefs.rules.iter()) {
+            if !rule.ask && !self.has_profile(&rule.profile) {
+                warnings.push(format!(
+                    "A rule opens links in missing profile \"{}\".",
+                    rule.profile
+                ));
+            }
+        }
+
+        for (name, context) in &self.config.contexts {
+            let profiles = context.profile.iter().chain(
+                context
+                    .rules
+                    .iter()
+                    .filter(|rule| !rule.ask)
+                    .map(|rule| &rule.profile),
+            );
+            for profile in profiles {
+                if !self.has_profile(profile) {
+                    warnings.push(format!(
+                        "Context \"{name}\" opens links in missing profile \"{profile}\"."
+                    ));
+                }
+            }
+        }
+
+        let active_context = self.prefs.active_context.as_ref();
+        let override_context = self
+            .context_override()
+            .map(|context_override| &context_override.context);
+        for name in active_context.into_iter().chain(override_context) {
+            if !self.config.contexts.contains_key(name) {
+                warnings.push(format!(
+                    "Context \"{name}\" isn't in chromevalet.toml any more."
+                ));
+            }
+        }
+
+        for scheduled in &self.config.preferred {
+            if !self.has_profile(&scheduled.profile) {
+                warnings.push(format!(
+                    "Scheduled preferred profile \"{}\" doesn't exist.",
+                    scheduled.profile
+                ));
+            }
+        }
+
+        warnings
+    }
+
+    ///
+    /// a profile signed in to the same account as the missing preferred profile
+    ///
+    pub fn preferred_profile_remap(&self) -> Option<&ChromeProfileEntry> {
+        let preferred_profile = &self.prefs.preferred_profile;
+        let user_name = &self.prefs.preferred_profile_user_name;
+        if preferred_profile.is_empty()
+            || user_name.is_empty()
+            || self.has_profile(preferred_profile)
+        {
+            return None;
+        }
+
+        self.profile_entries
+            .iter()
+            .find(|entry| entry.user_name.eq_ignore_ascii_case(user_name))
+    }
+
+    /// stores the preferred profile's account alongside it in prefs
+    pub fn remember_preferred_profile_account(&mut self) {
+        let user_name = self
+            .profile_entries
+            .iter()
+            .find(|entry| entry.profile_directory == self.prefs.preferred_profile)
+            .map(|entry| entry.user_name.clone())
+            .unwrap_or_default();
+        self.prefs.preferred_profile_user_name = user_name;
+    }
+
+    pub fn load_errors(&self) -> &Vec<String> {
+        &self.load_errors
+    }
+
+    pub fn push_load_error(&mut self, load_error: String) {
+        self.load_errors.push(load_error);
+    }
+
+    pub fn statefile_path(&self) -> PathBuf {
+        PathBuf::from(&self.statefile_path)
+    }
+
+    /// files that change what chrome valet shows
+    pub fn watched_paths(&self) -> Vec<PathBuf> {
+        vec![
+            ProgramPrefs::prefs_path(),
+            UserConfig::config_path(),
+            ProfileUsage::usage_path(),
+            self.statefile_path(),
+        ]
+    }
+
+    /// the browser links open in, like get_default_browser without remembering it in prefs.json
+    pub fn default_browser(&self) -> registry_utils::Browser {
+        match registry_utils::get_default_browser() {
+            Ok(Some(browser)) => browser,
+            _ => registry_utils::Browser::try_from(&self.prefs().default_browser)
+                .unwrap_or(registry_utils::Browser::Chrome),
+        }
     }
 
     pub fn get_default_browser(&mut self) -> registry_utils::Browser {
@@ -196,11 +664,94 @@ impl ChromeInterface {
         &mut self.prefs
     }
 
+    pub fn config(&self) -> &UserConfig {
+        &self.config
+    }
+
+    /// the rule deciding where `url` opens, the context's rules win, then chromevalet.toml, then remembered ones
+    pub fn find_rule(&self, link: &Link) -> Option<&RoutingRule> {
+        self.find_rule_at(link, Utc::now())
+    }
+
+    /// `find_rule`, with `now` for the schedules
+    pub fn find_rule_at(&self, link: &Link, now: DateTime<Utc>) -> Option<&RoutingRule> {
+        rules::find_matching_rule(self.layered_rules().map(|(_, rule)| rule), link, now)
+    }
+
+    /// every rule in the order they're checked, with the file it came from
+    pub fn layered_rules(&self) -> impl Iterator<Item = (RuleLayer, &RoutingRule)> {
+        let context_rules = self
+            .current_context()
+            .into_iter()
+            .flat_map(|(_, context)| context.rules.iter())
+            .map(|rule| (RuleLayer::Context, rule));
+        let config_rules = self
+            .config
+            .rules
+            .iter()
+            .map(|rule| (RuleLayer::Config, rule));
+        let prefs_rules = self.prefs.rules.iter().map(|rule| (RuleLayer::Prefs, rule));
+        context_rules.chain(config_rules).chain(prefs_rules)
+    }
+
+    /// whether a rule that's checked has a network condition, the network is only read if so
+    pub fn needs_network(&self) -> bool {
+        self.layered_rules().any(|(_, rule)| rule.network.is_some())
+    }
+
+    ///
+    /// the context in use, with its name
+    /// an expired override is ignored, a name chromevalet.toml doesn't have is None
+    ///
+    pub fn current_context(&self) -> Option<(&str, &ContextConfig)> {
+        self.current_context_at(Utc::now())
+    }
+
+    /// `current_context`, with `now` for the override's expiry
+    pub fn current_context_at(&self, now: DateTime<Utc>) -> Option<(&str, &ContextConfig)> {
+        let name = contexts::current(
+            self.prefs.active_context.as_deref(),
+            self.prefs.context_override.as_ref(),
+            now.timestamp().max(0) as u64,
+        )?;
+        self.config
+            .contexts
+            .get_key_value(name)
+            .map(|(name, context)| (name.as_str(), context))
+    }
+
+    /// the override in use, None once it's expired
+    pub fn context_override(&self) -> Option<&ContextOverride> {
+        self.context_override_at(Utc::now())
+    }
+
+    /// `context_override`, with `now` for its expiry
+    pub fn context_override_at(&self, now: DateTime<Utc>) -> Option<&ContextOverride> {
+        let now = now.timestamp().max(0) as u64;
+        self.prefs
+            .context_override
+            .as_ref()
+            .filter(|context_override| !context_override.is_expired(now))
+    }
+
+    ///
+    /// rules worth adding for hosts that keep being picked by hand, see [suggestions]
+    ///
+    pub fn rule_suggestions(&self, history: &[HistoryEntry]) -> Vec<RuleSuggestion> {
+        if !self.config.suggestions.enabled {
+            return Vec::new();
+        }
+
+        suggestions::suggest(history, &self.config.suggestions, |url| {
+            self.find_rule(&Link::new(url)).is_some()
+        })
+        .into_iter()
+        .filter(|suggestion| !self.prefs.dismissed_suggestions.contains(&suggestion.host))
+        .collect()
+    }
+
     fn chrome_prefs_path(profile_dir: &String) -> PathBuf {
-        let local_app_data = env::var(LOCALAPPDATA).unwrap();
-        PathBuf::from(local_app_data)
-            .join("Google")
-            .join("Chrome")
+        paths::browser_user_data_dir(&Browser::Chrome)
             .join(profile_dir)
             .join("Preferences")
     }
@@ -216,7 +767,7 @@ impl ChromeInterface {
 
     fn open_prefs_as_object(profile_dir: &String) -> IoResult<Value> {
         let prefs_path = Self::chrome_prefs_path(profile_dir);
-        return Self::open_file_as_object(&prefs_path.as_os_str().to_os_string());
+        Self::open_file_as_object(&prefs_path.as_os_str().to_os_string())
     }
 
     fn write_to_file(file_path: &Path, file_contents: &[u8]) -> IoResult<()> {
@@ -238,52 +789,131 @@ impl ChromeInterface {
         Self::write_to_file(file_path, file_contents.to_string().as_bytes())
     }
 
-    pub fn populate_profile_entries(&mut self) -> Result<(), anyhow::Error> {
+    ///
+    /// reads the profiles from Local State, updating profile_entries in place
+    /// entries whose avatar changed get a fresh picture, dropping their cached texture
+    ///
+    pub fn populate_profile_entries(&mut self) -> Result<ProfileChanges, anyhow::Error> {
         let json_profiles = &self
             .open_local_statefile_as_object()
-            .with_context(|| format!("couldn't open statefile"))?;
-
-        if json_profiles.is_object() {
-            let json_profiles = &json_profiles["profile"]["info_cache"];
-            for profile_entry in json_profiles.as_object().unwrap() {
-                let entry_data = profile_entry.1.as_object().unwrap();
-                let profile_filename = match entry_data.get_key_value("gaia_picture_file_name") {
-                    Some(e) => OsString::from(e.1.as_str().unwrap_or_default()),
-                    None => OsString::default(),
-                };
+            .context("couldn't open statefile")?;
 
-                let mut profile_color: [u8; 4] = [128, 128, 128, 0];
-                if let Some(e) = entry_data.get_key_value("default_avatar_fill_color") {
-                    let colour = e.1.as_i64().unwrap_or_default();
-                    // color is argb
-                    profile_color[3] = ((colour >> 24) & 0xff) as u8;
-                    profile_color[0] = ((colour >> 16) & 0xff) as u8;
-                    profile_color[1] = ((colour >> 8) & 0xff) as u8;
-                    profile_color[2] = (colour & 0xff) as u8;
-                };
-
-                let shortcut_name = match entry_data.get_key_value("shortcut_name") {
-                    Some(e) => e.1.as_str().unwrap_or_default(),
-                    None => "",
-                };
+        if !json_profiles.is_object() {
+            bail!("json_profiles not object: {}", json_profiles.to_string());
+        }
 
-                let chrome_profile_entry: ChromeProfileEntry = ChromeProfileEntry {
-                    profile_directory: profile_entry.0.to_string(),
-                    profile_name: shortcut_name.to_string(),
-                    profile_picture: Arc::new(Mutex::new(ChromeProfilePicture::new(
-                        &profile_entry.0.to_string(),
-                        &profile_filename,
-                        &profile_color,
-                    ))),
-                };
+        let mut changes = ProfileChanges::default();
+        let statefile_path = self.statefile_path();
+        let user_data_dir = statefile_path.parent().unwrap_or(Path::new(""));
+        let json_profiles = &json_profiles["profile"]["info_cache"];
+        let json_profiles = match json_profiles.as_object() {
+            Some(x) => x,
+            None => bail!("info_cache not object: {}", json_profiles.to_string()),
+        };
 
-                self.profile_entries.push(chrome_profile_entry);
+        self.profile_entries.retain(|entry| {
+            let keep = json_profiles.contains_key(&entry.profile_directory);
+            if !keep {
+                changes.removed.push(entry.profile_directory.clone());
             }
+            keep
+        });
 
-            return Ok(());
+        for profile_entry in json_profiles {
+            let entry_data = match profile_entry.1.as_object() {
+                Some(x) => x,
+                None => continue,
+            };
+
+            let profile_filename = match entry_data.get_key_value("gaia_picture_file_name") {
+                Some(e) => OsString::from(e.1.as_str().unwrap_or_default()),
+                None => OsString::default(),
+            };
+
+            let mut profile_color: [u8; 4] = [128, 128, 128, 0];
+            if let Some(e) = entry_data.get_key_value("default_avatar_fill_color") {
+                let colour = e.1.as_i64().unwrap_or_default();
+                // color is argb
+                profile_color[3] = ((colour >> 24) & 0xff) as u8;
+                profile_color[0] = ((colour >> 16) & 0xff) as u8;
+                profile_color[1] = ((colour >> 8) & 0xff) as u8;
+                profile_color[2] = (colour & 0xff) as u8;
+            };
+
+            let shortcut_name = match entry_data.get_key_value("shortcut_name") {
+                Some(e) => e.1.as_str().unwrap_or_default(),
+                None => "",
+            };
+
+            let user_name = match entry_data.get_key_value("user_name") {
+                Some(e) => e.1.as_str().unwrap_or_default(),
+                None => "",
+            };
+
+            let active_time = match entry_data.get_key_value("active_time") {
+                Some(e) => e.1.as_f64().unwrap_or_default(),
+                None => 0.0,
+            };
+
+            let profile_directory = profile_entry.0.to_string();
+            let avatar = ProfileAvatar {
+                picture_filename: profile_filename,
+                color: profile_color,
+                custom_icon: self
+                    .prefs
+                    .profile_settings(&profile_directory)
+                    .icon_path
+                    .map(PathBuf::from),
+            };
+            let new_picture = || {
+                Arc::new(Mutex::new(match &avatar.custom_icon {
+                    Some(icon_path) => ChromeProfilePicture::from_path(icon_path, &avatar.color),
+                    None => ChromeProfilePicture::new(
+                        user_data_dir,
+                        &profile_directory,
+                        &avatar.picture_filename,
+                        &avatar.color,
+                    ),
+                }))
+            };
+
+            match self
+                .profile_entries
+                .iter_mut()
+                .find(|entry| entry.profile_directory == profile_directory)
+            {
+                Some(existing) => {
+                    existing.active_time = active_time;
+                    existing.user_name = user_name.to_string();
+
+                    if existing.profile_name != shortcut_name {
+                        existing.profile_name = shortcut_name.to_string();
+                        changes.renamed.push(profile_directory.clone());
+                    }
+
+                    if existing.avatar != avatar {
+                        existing.profile_picture = new_picture();
+                        existing.avatar = avatar.clone();
+                        changes.avatar_changed.push(profile_directory.clone());
+                    }
+                }
+                None => {
+                    let chrome_profile_entry: ChromeProfileEntry = ChromeProfileEntry {
+                        profile_directory: profile_directory.clone(),
+                        profile_name: shortcut_name.to_string(),
+                        profile_picture: new_picture(),
+                        avatar: avatar.clone(),
+                        active_time,
+                        user_name: user_name.to_string(),
+                    };
+
+                    self.profile_entries.push(chrome_profile_entry);
+                    changes.added.push(profile_directory.clone());
+                }
+            }
         }
 
-        bail!("json_profiles not object: {}", json_profiles.to_string());
+        Ok(changes)
     }
 
     pub fn read_prefs(&mut self) -> IoResult<()> {
@@ -296,23 +926,62 @@ impl ChromeInterface {
             },
         }
 
+        self.update_statefile_path();
+        self.lint_loaded_rules();
+
+        Ok(())
+    }
+
+    pub fn read_config(&mut self) -> Result<(), anyhow::Error> {
+        self.config = UserConfig::read()?;
+        self.update_statefile_path();
+        self.lint_loaded_rules();
+        Ok(())
+    }
+
+    ///
+    /// problems with the rules, in every context, `profiles` are checked if given
+    ///
+    pub fn lint_rules(&self, profiles: Option<&[String]>) -> Vec<LintWarning> {
+        lint::lint_config(&self.config, &self.prefs.rules, profiles)
+    }
+
+    /// rule problems found when the rules were last read, for the picker
+    /// missing profiles are left to `profile_warnings`, the profiles aren't known when prefs are read
+    pub fn rule_warnings(&self) -> &Vec<LintWarning> {
+        &self.rule_warnings
+    }
+
+    fn lint_loaded_rules(&mut self) {
+        let rule_warnings = self.lint_rules(None);
+        if rule_warnings != self.rule_warnings {
+            for rule_warning in &rule_warnings {
+                warn!("{rule_warning}");
+            }
+            self.rule_warnings = rule_warnings;
+        }
+    }
+
+    fn update_statefile_path(&mut self) {
         if let Ok(browser) = Browser::try_from(&self.prefs.default_browser) {
             if browser != Browser::Unknown {
-                let local_app_data = env::var(LOCALAPPDATA).unwrap();
-                let browser_appdata_dir = browser.get_definition().unwrap().app_data_dir;
-                self.statefile_path = Path::join(
-                    Path::new(OsStr::new(&local_app_data)),
-                    format!("{browser_appdata_dir}/User Data/Local State"),
-                )
-                .into_os_string();
+                let user_data_dir = self
+                    .config
+                    .browser_config(&browser)
+                    .and_then(|browser_config| browser_config.user_data_dir.clone());
+
+                self.statefile_path = match user_data_dir {
+                    Some(dir) => PathBuf::from(dir).join("Local State").into_os_string(),
+                    None => paths::browser_user_data_dir(&browser)
+                        .join("Local State")
+                        .into_os_string(),
+                };
             }
         }
-
-        Ok(())
     }
 
     pub fn write_prefs(&self) -> IoResult<()> {
-        let prefs_string = serde_json::to_string(&self.prefs).unwrap();
+        let prefs_string = serde_json::to_string_pretty(&self.prefs).unwrap();
         let prefs_bytes = prefs_string.as_bytes();
         Self::write_to_file(ProgramPrefs::prefs_path().as_path(), prefs_bytes)?;
         Ok(())
@@ -320,12 +989,7 @@ impl ChromeInterface {
 
     #[allow(dead_code)]
     pub fn set_lastused_profile(&self, profile_name: &str) {
-        let local_app_data = env::var(LOCALAPPDATA).unwrap();
-        let statefile_path = Path::join(
-            Path::new(OsStr::new(&local_app_data)),
-            "Google/Chrome/User Data/Local State",
-        )
-        .into_os_string();
+        let statefile_path = &self.statefile_path;
 
         let local_statefile_obj = self.open_local_statefile_as_object();
         let mut local_statefile_changed = local_statefile_obj.unwrap();
@@ -351,3 +1015,267 @@ impl ChromeInterface {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::host_rule;
+
+    /// a Local State holding `profiles`, (directory, name, gaia picture, argb fill colour)
+    fn write_local_state(path: &Path, profiles: &[(&str, &str, &str, i64)]) {
+        let info_cache: serde_json::Map<String, Value> = profiles
+            .iter()
+            .map(|(dir, name, picture, color)| {
+                let info = serde_json::json!({
+                    "shortcut_name": name,
+                    "gaia_picture_file_name": picture,
+                    "default_avatar_fill_color": color,
+                    "active_time": 1700000000.5,
+                    "user_name": format!("{}@example.com", name.to_lowercase()),
+                });
+                (dir.to_string(), info)
+            })
+            .collect();
+        let local_state = serde_json::json!({ "profile": { "info_cache": info_cache } });
+        fs::write(path, local_state.to_string()).unwrap();
+    }
+
+    fn entry<'a>(chrome: &'a ChromeInterface, dir: &str) -> &'a ChromeProfileEntry {
+        chrome
+            .profile_entries
+            .iter()
+            .find(|entry| entry.profile_directory == dir)
+            .unwrap()
+    }
+
+    #[test]
+    fn reading_local_state_again_updates_profiles_in_place() {
+        let user_data_dir = tempfile::tempdir().unwrap();
+        let local_state = user_data_dir.path().join("Local State");
+        let mut chrome = ChromeInterface {
+            statefile_path: local_state.clone().into_os_string(),
+            ..Default::default()
+        };
+
+        write_local_state(
+            &local_state,
+            &[
+                ("Default", "Personal", "", 0xff112233),
+                ("Profile 1", "Work", "Google Profile Picture.png", 0),
+                ("Profile 2", "Old", "", 0),
+            ],
+        );
+        let changes = chrome.populate_profile_entries().unwrap();
+        let mut added = changes.added.clone();
+        added.sort();
+        assert_eq!(added, ["Default", "Profile 1", "Profile 2"]);
+        assert!(changes.removed.is_empty() && changes.renamed.is_empty());
+        assert!(changes.avatar_changed.is_empty());
+        assert!(changes.needs_picture(&"Default".to_string()));
+        assert_eq!(
+            entry(&chrome, "Default").avatar.color,
+            [0x11, 0x22, 0x33, 0xff]
+        );
+        assert_eq!(entry(&chrome, "Profile 1").user_name, "work@example.com");
+        let work_picture = entry(&chrome, "Profile 1").profile_picture.clone();
+        let personal_picture = entry(&chrome, "Default").profile_picture.clone();
+
+        // Profile 2 deleted, Profile 1 renamed, Default's colour changed and Profile 3 created
+        write_local_state(
+            &local_state,
+            &[
+                ("Default", "Personal", "", 0xff445566),
+                ("Profile 1", "Day job", "Google Profile Picture.png", 0),
+                ("Profile 3", "New", "", 0),
+            ],
+        );
+        let changes = chrome.populate_profile_entries().unwrap();
+        assert_eq!(changes.added, ["Profile 3"]);
+        assert_eq!(changes.removed, ["Profile 2"]);
+        assert_eq!(changes.renamed, ["Profile 1"]);
+        assert_eq!(changes.avatar_changed, ["Default"]);
+        assert!(!changes.needs_picture(&"Profile 1".to_string()));
+
+        assert_eq!(chrome.profile_entries.len(), 3);
+        assert!(!chrome.has_profile("Profile 2"));
+        assert_eq!(entry(&chrome, "Profile 1").profile_name, "Day job");
+        // a rename keeps the picture, a new colour replaces it
+        assert!(Arc::ptr_eq(
+            &entry(&chrome, "Profile 1").profile_picture,
+            &work_picture
+        ));
+        assert!(!Arc::ptr_eq(
+            &entry(&chrome, "Default").profile_picture,
+            &personal_picture
+        ));
+        assert_eq!(
+            entry(&chrome, "Default").avatar.color,
+            [0x44, 0x55, 0x66, 0xff]
+        );
+
+        // nothing changed
+        assert!(chrome.populate_profile_entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn new_gaia_picture_is_an_avatar_change() {
+        let user_data_dir = tempfile::tempdir().unwrap();
+        let local_state = user_data_dir.path().join("Local State");
+        let mut chrome = ChromeInterface {
+            statefile_path: local_state.clone().into_os_string(),
+            ..Default::default()
+        };
+
+        write_local_state(&local_state, &[("Default", "Personal", "", 0)]);
+        chrome.populate_profile_entries().unwrap();
+        write_local_state(&local_state, &[("Default", "Personal", "photo.png", 0)]);
+        let changes = chrome.populate_profile_entries().unwrap();
+        assert_eq!(changes.avatar_changed, ["Default"]);
+        assert!(changes.added.is_empty() && changes.renamed.is_empty());
+
+        let picture = entry(&chrome, "Default")
+            .profile_picture
+            .try_lock()
+            .unwrap();
+        assert_eq!(
+            PathBuf::from(&picture.picture_filename),
+            user_data_dir.path().join("Default").join("photo.png")
+        );
+    }
+
+    #[test]
+    fn unreadable_local_state_leaves_profiles_alone() {
+        let user_data_dir = tempfile::tempdir().unwrap();
+        let local_state = user_data_dir.path().join("Local State");
+        let mut chrome = ChromeInterface {
+            statefile_path: local_state.clone().into_os_string(),
+            ..Default::default()
+        };
+
+        write_local_state(&local_state, &[("Default", "Personal", "", 0)]);
+        chrome.populate_profile_entries().unwrap();
+        fs::write(&local_state, "{ \"profile\": {} }").unwrap();
+        assert!(chrome.populate_profile_entries().is_err());
+        assert!(chrome.has_profile("Default"));
+    }
+
+    /// Work and work tie on name, Personal and Work on activity, Personal and Old on use
+    fn sorted_profiles() -> ChromeInterface {
+        let mut chrome = ChromeInterface::with_profiles(
+            UserConfig::default(),
+            ProgramPrefs::default(),
+            &["Default", "Profile 1", "Profile 2", "Profile 3"],
+        );
+        let profiles = [
+            ("Personal", 200.0, 5),
+            ("Work", 200.0, 9),
+            ("Old", 100.0, 5),
+            ("work", 300.0, 0),
+        ];
+        for (entry, (name, active_time, opens)) in chrome.profile_entries.iter_mut().zip(profiles) {
+            entry.profile_name = name.to_string();
+            entry.active_time = active_time;
+            if opens > 0 {
+                chrome
+                    .usage
+                    .open_counts
+                    .insert(entry.profile_directory.clone(), opens);
+            }
+        }
+        chrome
+    }
+
+    fn displayed(chrome: &mut ChromeInterface, sort: ProfileSortMode) -> Vec<String> {
+        chrome.prefs.profile_sort = sort;
+        chrome
+            .display_entries(false)
+            .iter()
+            .map(|entry| chrome.display_name(entry))
+            .collect()
+    }
+
+    #[test]
+    fn alphabetical_sort_ignores_case() {
+        let mut chrome = sorted_profiles();
+        let names = displayed(&mut chrome, ProfileSortMode::Alphabetical);
+        assert_eq!(names[..2], ["Old", "Personal"]);
+        // a tie keeps Local State's order
+        assert_eq!(names[2..], ["Work", "work"]);
+    }
+
+    #[test]
+    fn recently_active_sort_breaks_ties_by_name() {
+        let mut chrome = sorted_profiles();
+        assert_eq!(
+            displayed(&mut chrome, ProfileSortMode::RecentlyActive),
+            ["work", "Personal", "Work", "Old"]
+        );
+    }
+
+    #[test]
+    fn most_used_sort_breaks_ties_by_name() {
+        let mut chrome = sorted_profiles();
+        assert_eq!(
+            displayed(&mut chrome, ProfileSortMode::MostUsed),
+            ["Work", "Old", "Personal", "work"]
+        );
+    }
+
+    #[test]
+    fn manual_sort_puts_unordered_profiles_last_by_name() {
+        let mut chrome = sorted_profiles();
+        chrome.move_profile("Profile 2", 0, false);
+        assert_eq!(chrome.prefs.profile_sort, ProfileSortMode::Manual);
+        assert_eq!(
+            displayed(&mut chrome, ProfileSortMode::Manual),
+            ["Old", "Personal", "Work", "work"]
+        );
+
+        chrome.prefs.set_profile_settings(
+            "Profile 3",
+            ProfileDisplaySettings {
+                alias: Some("Aardvark".to_string()),
+                ..Default::default()
+            },
+        );
+        chrome.prefs.set_profile_settings(
+            "Default",
+            ProfileDisplaySettings {
+                hidden: true,
+                ..Default::default()
+            },
+        );
+        // Profile 3's order went with its settings, it now sorts by its alias
+        assert_eq!(
+            displayed(&mut chrome, ProfileSortMode::Manual),
+            ["Old", "Work", "Aardvark"]
+        );
+        assert_eq!(
+            displayed(&mut chrome, ProfileSortMode::Alphabetical),
+            ["Aardvark", "Old", "Work"]
+        );
+    }
+
+    #[test]
+    fn remembering_a_rule_replaces_only_one_with_the_same_conditions() {
+        let mut prefs = ProgramPrefs::default();
+        prefs.remember_rule(host_rule("gitlab.corp", "Default"));
+        prefs.remember_rule(host_rule("gitlab.corp", "Profile 1"));
+        assert_eq!(prefs.rules, [host_rule("gitlab.corp", "Profile 1")]);
+
+        let https_only = RoutingRule {
+            scheme: Some("https".to_string()),
+            ..host_rule("wiki.corp", "Default")
+        };
+        prefs.remember_rule(https_only.clone());
+        prefs.remember_rule(host_rule("wiki.corp", "Profile 1"));
+        assert_eq!(
+            prefs.rules,
+            [
+                host_rule("gitlab.corp", "Profile 1"),
+                host_rule("wiki.corp", "Profile 1"),
+                https_only,
+            ]
+        );
+    }
+}
@@ -0,0 +1,317 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind as IoErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::chrome_interface::ProgramPrefs;
+use crate::logging::LogFormat;
+use crate::paths::app_data_dir;
+use crate::registry_utils;
+use crate::registry_utils::Browser;
+use crate::rules::RoutingRule;
+use crate::schedule::Schedule;
+
+///
+/// human authored configuration, read from chromevalet.toml
+/// chrome valet never writes this file, machine state lives in prefs.json
+///
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserConfig {
+    /// routing rules, evaluated in order before the remembered rules in prefs.json
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<RoutingRule>,
+
+    /// preferred profiles for parts of the week, checked in order after the rules
+    /// the preferred profile picked in the picker is used outside all of them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub preferred: Vec<ScheduledProfile>,
+
+    /// named contexts, eg. "Work" or "Personal", one is picked in the picker or with `context use`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub contexts: BTreeMap<String, ContextConfig>,
+
+    /// per browser overrides, keyed by browser name ("chrome", "brave")
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub browsers: BTreeMap<String, BrowserConfig>,
+
+    #[serde(default, skip_serializing_if = "LoggingConfig::is_default")]
+    pub logging: LoggingConfig,
+
+    #[serde(default, skip_serializing_if = "HistoryConfig::is_default")]
+    pub history: HistoryConfig,
+
+    #[serde(default, skip_serializing_if = "SuggestionsConfig::is_default")]
+    pub suggestions: SuggestionsConfig,
+}
+
+///
+/// a preferred profile for part of the week, [[preferred]] in chromevalet.toml
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduledProfile {
+    pub profile: String,
+    pub schedule: Schedule,
+}
+
+///
+/// a named context, [contexts.<name>] in chromevalet.toml
+/// while it's in use its rules are checked first, and its profile is used when nothing matches
+///
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ContextConfig {
+    /// profile directory for links no rule matches, instead of the preferred profiles
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<RoutingRule>,
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BrowserConfig {
+    /// browser executable, skips the App Paths lookup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exe: Option<String>,
+
+    /// directory holding the browser's "Local State" file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_data_dir: Option<String>,
+}
+
+///
+/// chrome valet is started by windows, so logging is set here rather than on the command line
+/// --log-level and --log-format still win
+///
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// a level or per module directives, eg. "info,chrome_valet::rules=trace"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<LogFormat>,
+
+    /// daily log files kept, older ones are deleted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_files: Option<usize>,
+}
+
+impl LoggingConfig {
+    fn is_default(&self) -> bool {
+        *self == LoggingConfig::default()
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+///
+/// history.jsonl limits, [history] in chromevalet.toml
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HistoryConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// newest entries kept when history is pruned
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize,
+
+    /// entries older than this are pruned, 0 keeps them forever
+    #[serde(default = "default_max_age_days")]
+    pub max_age_days: u64,
+}
+
+fn default_max_entries() -> usize {
+    5000
+}
+
+fn default_max_age_days() -> u64 {
+    90
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            enabled: true,
+            max_entries: default_max_entries(),
+            max_age_days: default_max_age_days(),
+        }
+    }
+}
+
+impl HistoryConfig {
+    fn is_default(&self) -> bool {
+        *self == HistoryConfig::default()
+    }
+}
+
+///
+/// when a host picked by hand is worth a rule, [suggestions] in chromevalet.toml
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SuggestionsConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// links to a host opened in the same profile before a rule is suggested
+    #[serde(default = "default_min_links")]
+    pub min_links: usize,
+
+    /// share of the host's picks that went to that profile, 0.0 to 1.0
+    #[serde(default = "default_min_confidence")]
+    pub min_confidence: f64,
+}
+
+fn default_min_links() -> usize {
+    5
+}
+
+fn default_min_confidence() -> f64 {
+    0.8
+}
+
+impl Default for SuggestionsConfig {
+    fn default() -> Self {
+        SuggestionsConfig {
+            enabled: true,
+            min_links: default_min_links(),
+            min_confidence: default_min_confidence(),
+        }
+    }
+}
+
+impl SuggestionsConfig {
+    fn is_default(&self) -> bool {
+        *self == SuggestionsConfig::default()
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+}
+
+///
+/// `config export --format json`, chromevalet.toml with the state from prefs.json alongside
+/// the toml export leaves the state out, so it can be read back as a chromevalet.toml
+///
+#[derive(Serialize)]
+struct ExportedConfig<'a> {
+    #[serde(flatten)]
+    config: &'a UserConfig,
+
+    state: &'a ProgramPrefs,
+}
+
+impl UserConfig {
+    pub fn config_path() -> PathBuf {
+        app_data_dir().join("chromevalet.toml")
+    }
+
+    pub fn read() -> Result<Self, anyhow::Error> {
+        let config_path = Self::config_path();
+        match fs::read_to_string(&config_path) {
+            Ok(contents) => toml::from_str(&contents)
+                .with_context(|| format!("couldn't parse {}", config_path.display())),
+            Err(e) => match e.kind() {
+                IoErrorKind::NotFound => Ok(UserConfig::default()),
+                _ => Err(e).with_context(|| format!("couldn't read {}", config_path.display())),
+            },
+        }
+    }
+
+    /// a chromevalet.toml somewhere else, eg. a team's shared rules
+    pub fn read_file(path: &Path) -> Result<Self, anyhow::Error> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("couldn't read {}", path.display()))?;
+        toml::from_str(&contents).with_context(|| format!("couldn't parse {}", path.display()))
+    }
+
+    pub fn browser_config(&self, browser: &Browser) -> Option<&BrowserConfig> {
+        self.browsers.get(&browser.to_string())
+    }
+
+    pub fn browser_exe(&self, browser: &Browser) -> Option<String> {
+        self.browser_config(browser)?.exe.clone()
+    }
+
+    ///
+    /// the executable to launch for `browser`, the override in chromevalet.toml or the one in App Paths
+    ///
+    pub fn resolve_browser_exe(&self, browser: &Browser) -> Result<String, anyhow::Error> {
+        match self.browser_exe(browser) {
+            Some(exe) => Ok(exe),
+            None => registry_utils::get_browser_exe(browser)
+                .with_context(|| format!("couldn't find {browser} in App Paths")),
+        }
+    }
+
+    pub fn export(
+        &self,
+        prefs: &ProgramPrefs,
+        format: ConfigFormat,
+    ) -> Result<String, anyhow::Error> {
+        Ok(match format {
+            ConfigFormat::Toml => toml::to_string_pretty(self)?,
+            ConfigFormat::Json => serde_json::to_string_pretty(&ExportedConfig {
+                config: self,
+                state: prefs,
+            })?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn misspelt_keys_are_errors() {
+        let err = toml::from_str::<UserConfig>(
+            "[[rules]]\nhost = \"*.example.com\"\nprofle = \"Default\"\n",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("unknown field `profle`"), "{err}");
+
+        assert!(toml::from_str::<UserConfig>("[browser.chrome]\nexe = \"chrome\"\n").is_err());
+        assert!(toml::from_str::<UserConfig>("[browsers.chrome]\nexe = \"chrome\"\n").is_ok());
+    }
+
+    #[test]
+    fn toml_export_reads_back() {
+        let config: UserConfig = toml::from_str(
+            "[[rules]]\nhost = \"*.example.com\"\nprofile = \"Profile 1\"\n\n\
+             [browsers.chrome]\nexe = \"chrome\"\n\n\
+             [history]\nmax_entries = 10\n",
+        )
+        .unwrap();
+        let prefs = ProgramPrefs::default();
+
+        let exported = config.export(&prefs, ConfigFormat::Toml).unwrap();
+        assert!(!exported.contains("[state]"), "{exported}");
+        let read_back: UserConfig = toml::from_str(&exported).unwrap();
+        assert_eq!(read_back.rules, config.rules);
+        assert_eq!(read_back.history, config.history);
+        assert_eq!(
+            read_back.browser_exe(&Browser::Chrome).as_deref(),
+            Some("chrome")
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&config.export(&prefs, ConfigFormat::Json).unwrap()).unwrap();
+        assert!(json.get("state").is_some());
+        assert!(json.get("rules").is_some());
+    }
+}
@@ -0,0 +1,178 @@
+use chrono::{DateTime, Datelike, Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+///
+/// a context used instead of the active one until `until`, then it lapses on its own
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContextOverride {
+    pub context: String,
+    /// seconds since the unix epoch
+    pub until: u64,
+}
+
+impl ContextOverride {
+    pub fn is_expired(&self, now: u64) -> bool {
+        now >= self.until
+    }
+
+    /// eg. "14:30", or "Fri 6 Mar 14:30" if it isn't today, in the computer's timezone
+    pub fn until_local(&self) -> String {
+        self.until_from(&Local::now())
+    }
+
+    /// `until_local` as seen at `now`, in `now`'s timezone
+    pub fn until_from<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> String
+    where
+        Tz::Offset: Display,
+    {
+        let until = match now.timezone().timestamp_opt(self.until as i64, 0).single() {
+            Some(until) => until,
+            None => return self.until.to_string(),
+        };
+
+        let format = if until.date_naive() == now.date_naive() {
+            "%H:%M"
+        } else if until.year() == now.year() {
+            "%a %-d %b %H:%M"
+        } else {
+            "%a %-d %b %Y %H:%M"
+        };
+        until.format(format).to_string()
+    }
+}
+
+///
+/// the context in use at `now`, an override that hasn't expired wins over the active context
+///
+pub fn current<'a>(
+    active_context: Option<&'a str>,
+    context_override: Option<&'a ContextOverride>,
+    now: u64,
+) -> Option<&'a str> {
+    match context_override {
+        Some(context_override) if !context_override.is_expired(now) => {
+            Some(&context_override.context)
+        }
+        _ => active_context,
+    }
+}
+
+///
+/// seconds in a duration like "1h", "30m" or "1h30m", a bare number is minutes
+///
+pub fn parse_duration(duration: &str) -> Result<u64, String> {
+    let invalid = || format!("duration \"{duration}\" isn't like \"1h\", \"30m\" or \"1h30m\"");
+    let too_long = || "duration too long".to_string();
+    let duration = duration.trim().to_lowercase();
+    if !duration.is_empty() && duration.chars().all(|c| c.is_ascii_digit()) {
+        let minutes: u64 = duration.parse().map_err(|_| too_long())?;
+        return minutes.checked_mul(60).ok_or_else(too_long);
+    }
+
+    let mut seconds: u64 = 0;
+    let mut number = String::new();
+    for c in duration.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let unit = match c {
+            'd' => 24 * 60 * 60,
+            'h' => 60 * 60,
+            'm' => 60,
+            _ => return Err(invalid()),
+        };
+        if number.is_empty() {
+            return Err(invalid());
+        }
+        // only digits are left, so it can only fail by being too big
+        let count: u64 = number.parse().map_err(|_| too_long())?;
+        seconds = count
+            .checked_mul(unit)
+            .and_then(|unit_seconds| seconds.checked_add(unit_seconds))
+            .ok_or_else(too_long)?;
+        number.clear();
+    }
+
+    if !number.is_empty() || seconds == 0 {
+        return Err(invalid());
+    }
+    Ok(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+
+    #[test]
+    fn override_wins_until_it_expires() {
+        let context_override = ContextOverride {
+            context: "Personal".to_string(),
+            until: 1000,
+        };
+        assert_eq!(
+            current(Some("Work"), Some(&context_override), 999),
+            Some("Personal")
+        );
+        assert_eq!(
+            current(Some("Work"), Some(&context_override), 1000),
+            Some("Work")
+        );
+        assert_eq!(current(None, Some(&context_override), 1000), None);
+        assert_eq!(current(Some("Work"), None, 0), Some("Work"));
+    }
+
+    #[test]
+    fn expiry_shows_the_date_when_it_isnt_today() {
+        // wednesday 4 march 2026, 22:00 in utc+2
+        let zone = FixedOffset::east_opt(2 * 60 * 60).unwrap();
+        let now = zone.with_ymd_and_hms(2026, 3, 4, 22, 0, 0).unwrap();
+        let until = |seconds_from_now: i64| ContextOverride {
+            context: "Personal".to_string(),
+            until: (now.timestamp() + seconds_from_now) as u64,
+        };
+
+        assert_eq!(until(90 * 60).until_from(&now), "23:30");
+        // past midnight in utc+2, though not in utc
+        assert_eq!(until(2 * 60 * 60).until_from(&now), "Thu 5 Mar 00:00");
+        assert_eq!(
+            until(parse_duration("2d").unwrap() as i64).until_from(&now),
+            "Fri 6 Mar 22:00"
+        );
+        assert_eq!(
+            until(parse_duration("300d").unwrap() as i64).until_from(&now),
+            "Tue 29 Dec 22:00"
+        );
+        assert_eq!(
+            until(parse_duration("365d").unwrap() as i64).until_from(&now),
+            "Thu 4 Mar 2027 22:00"
+        );
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("1h"), Ok(3600));
+        assert_eq!(parse_duration("30m"), Ok(1800));
+        assert_eq!(parse_duration("1h30m"), Ok(5400));
+        assert_eq!(parse_duration("2D"), Ok(172800));
+        assert_eq!(parse_duration("45"), Ok(2700));
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("1h30").is_err());
+        assert!(parse_duration("an hour").is_err());
+        assert!(parse_duration("0m").is_err());
+    }
+
+    #[test]
+    fn overlong_durations_are_errors() {
+        let too_long = Err("duration too long".to_string());
+        assert_eq!(parse_duration("999999999999999999m"), too_long);
+        assert_eq!(parse_duration("999999999999999999d"), too_long);
+        assert_eq!(parse_duration("99999999999999999999"), too_long);
+        assert_eq!(parse_duration("99999999999999999999h"), too_long);
+        assert_eq!(parse_duration("18446744073709551615h1m"), too_long);
+    }
+}
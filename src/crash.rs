@@ -0,0 +1,241 @@
+use log::{error, warn};
+use std::backtrace::Backtrace;
+use std::fs;
+use std::panic;
+use std::panic::PanicHookInfo;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::UserConfig;
+use crate::launcher::{LaunchRequest, Launcher, ProcessLauncher};
+use crate::paths::app_data_dir;
+
+///
+/// what to open if chrome valet panics before the link gets to a browser
+///
+struct PanicFallback {
+    request: LaunchRequest,
+    config: UserConfig,
+}
+
+static PANIC_FALLBACK: Mutex<Option<PanicFallback>> = Mutex::new(None);
+
+const CRASH_REPORT: &str = "crash_report.txt";
+
+/// where a report goes once it's been shown, so it's only shown once but kept for bug reports
+const SEEN_CRASH_REPORT: &str = "crash_report.old.txt";
+
+/// how std's print macros start their panic message when stdout or stderr has been closed
+const CLOSED_OUTPUT_MESSAGES: [&str; 2] =
+    ["failed printing to stdout", "failed printing to stderr"];
+
+///
+/// a crash report left by a previous run
+///
+#[derive(Debug, Clone)]
+pub struct LastCrash {
+    pub path: PathBuf,
+    /// the panic message
+    pub summary: String,
+}
+
+pub fn crash_report_path() -> PathBuf {
+    app_data_dir().join(CRASH_REPORT)
+}
+
+///
+/// writes a crash report on panic, and opens the fallback link if there is one
+///
+pub fn install_panic_hook() {
+    panic::set_hook(Box::new(|info| {
+        // eg. `chrome_valet config export | head` closing the pipe early, nothing crashed
+        let message = panic_message(info);
+        if is_closed_output(&message) {
+            return;
+        }
+
+        let report = crash_report(&message);
+        error!("{report}");
+        if let Err(e) = write_crash_report(&report) {
+            error!("couldn't write crash report: {e}");
+        }
+
+        // a panic while the fallback is being set leaves the lock poisoned, the fallback's still usable
+        let fallback = match PANIC_FALLBACK.try_lock() {
+            Ok(fallback) => fallback,
+            Err(std::sync::TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(std::sync::TryLockError::WouldBlock) => return,
+        };
+
+        if let Some(fallback) = fallback.as_ref() {
+            ProcessLauncher.launch(&fallback.config, &fallback.request);
+        }
+    }));
+}
+
+///
+/// replaces what the panic hook opens, set again once the preferred profile and browser are known
+///
+pub fn set_fallback(request: LaunchRequest, config: UserConfig) {
+    let mut fallback = PANIC_FALLBACK.lock().unwrap_or_else(|e| e.into_inner());
+    *fallback = Some(PanicFallback {
+        request: LaunchRequest {
+            exit_when_done: false,
+            ..request
+        },
+        config,
+    });
+}
+
+///
+/// opens the fallback link and exits, for when there's no picker to show it in
+///
+pub fn open_fallback() {
+    let fallback = PANIC_FALLBACK
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take();
+    if let Some(fallback) = fallback {
+        let request = LaunchRequest {
+            exit_when_done: true,
+            ..fallback.request
+        };
+        ProcessLauncher.launch(&fallback.config, &request);
+    }
+}
+
+fn panic_message(info: &PanicHookInfo) -> String {
+    let payload = info.payload();
+    let message = match payload.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => match payload.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "(no message)".to_string(),
+        },
+    };
+
+    match info.location() {
+        Some(location) => format!("{message} at {location}"),
+        None => message,
+    }
+}
+
+/// println! panics when stdout or stderr is closed before the command has printed everything
+fn is_closed_output(message: &str) -> bool {
+    CLOSED_OUTPUT_MESSAGES
+        .iter()
+        .any(|closed| message.starts_with(closed))
+}
+
+fn crash_report(message: &str) -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+
+    format!(
+        "{message}\nChrome Valet {} crashed in thread \"{}\", {seconds} seconds after the epoch\n\n{}\n",
+        env!("CARGO_PKG_VERSION"),
+        thread::current().name().unwrap_or("unnamed"),
+        Backtrace::force_capture()
+    )
+}
+
+fn write_crash_report(report: &str) -> std::io::Result<()> {
+    let path = crash_report_path();
+    if let Some(p) = path.parent() {
+        fs::create_dir_all(p)?;
+    }
+    fs::write(path, report)
+}
+
+///
+/// the report from the last crash, if it hasn't been dismissed
+///
+pub fn last_crash() -> Option<LastCrash> {
+    last_crash_at(crash_report_path())
+}
+
+fn last_crash_at(path: PathBuf) -> Option<LastCrash> {
+    let report = fs::read_to_string(&path).ok()?;
+    let summary = report.lines().next().unwrap_or_default().to_string();
+    warn!("chrome valet crashed last time: {summary}");
+    Some(LastCrash { path, summary })
+}
+
+///
+/// keeps the report as crash_report.old.txt, so it isn't shown again
+///
+pub fn dismiss(last_crash: &LastCrash) -> std::io::Result<()> {
+    fs::rename(
+        &last_crash.path,
+        last_crash.path.with_file_name(SEEN_CRASH_REPORT),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closed_output_is_not_a_crash() {
+        assert!(is_closed_output(
+            "failed printing to stdout: Broken pipe (os error 32) at library/std/src/io/stdio.rs:1123:9"
+        ));
+        assert!(is_closed_output(
+            "failed printing to stderr: The pipe is being closed. (os error 232)"
+        ));
+        assert!(!is_closed_output(
+            "called `Option::unwrap()` on a `None` value at src/main.rs:1:1"
+        ));
+        assert!(!is_closed_output("(no message)"));
+    }
+
+    #[test]
+    fn report_starts_with_the_panic_message() {
+        let report = crash_report("index out of bounds at src/main.rs:10:5");
+        let mut lines = report.lines();
+        assert_eq!(
+            lines.next(),
+            Some("index out of bounds at src/main.rs:10:5")
+        );
+
+        let about = lines.next().unwrap();
+        assert!(about.starts_with(&format!(
+            "Chrome Valet {} crashed in thread \"crash::tests::report_starts_with_the_panic_message\", ",
+            env!("CARGO_PKG_VERSION")
+        )));
+        assert!(about.ends_with(" seconds after the epoch"));
+        assert_eq!(lines.next(), Some(""));
+        assert!(report.contains("report_starts_with_the_panic_message"));
+    }
+
+    #[test]
+    fn dismissed_report_is_kept_but_not_shown_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CRASH_REPORT);
+        let old_path = dir.path().join(SEEN_CRASH_REPORT);
+        assert!(last_crash_at(path.clone()).is_none());
+
+        fs::write(&path, crash_report("first at src/main.rs:1:1")).unwrap();
+        let last_crash = last_crash_at(path.clone()).unwrap();
+        assert_eq!(last_crash.path, path);
+        assert_eq!(last_crash.summary, "first at src/main.rs:1:1");
+
+        dismiss(&last_crash).unwrap();
+        assert!(last_crash_at(path.clone()).is_none());
+        assert!(fs::read_to_string(&old_path)
+            .unwrap()
+            .starts_with("first at"));
+
+        // the next crash's report replaces the old one when it's dismissed
+        fs::write(&path, crash_report("second at src/main.rs:2:2")).unwrap();
+        dismiss(&last_crash_at(path.clone()).unwrap()).unwrap();
+        assert!(!path.exists());
+        assert!(fs::read_to_string(&old_path)
+            .unwrap()
+            .starts_with("second at"));
+    }
+}
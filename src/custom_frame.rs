@@ -1,8 +1,13 @@
 use eframe::egui::{self, ViewportCommand};
 
+///
+/// a borderless window with our own title bar
+/// `add_title_bar_contents` draws at the left of the title bar, opposite the close button
+///
 pub fn custom_window_frame(
     ctx: &egui::Context,
     title: &str,
+    add_title_bar_contents: impl FnOnce(&mut egui::Ui),
     add_contents: impl FnOnce(&mut egui::Ui),
 ) {
     use egui::*;
@@ -24,7 +29,7 @@ pub fn custom_window_frame(
             rect.max.y = rect.min.y + title_bar_height;
             rect
         };
-        title_bar_ui(ui, title_bar_rect, title);
+        title_bar_ui(ui, title_bar_rect, title, add_title_bar_contents);
 
         // Add the contents:
         let content_rect = {
@@ -38,7 +43,12 @@ pub fn custom_window_frame(
     });
 }
 
-fn title_bar_ui(ui: &mut egui::Ui, title_bar_rect: eframe::epaint::Rect, title: &str) {
+fn title_bar_ui(
+    ui: &mut egui::Ui,
+    title_bar_rect: eframe::epaint::Rect,
+    title: &str,
+    add_title_bar_contents: impl FnOnce(&mut egui::Ui),
+) {
     use egui::*;
 
     let painter = ui.painter();
@@ -82,6 +92,13 @@ fn title_bar_ui(ui: &mut egui::Ui, title_bar_rect: eframe::epaint::Rect, title:
             close_maximize_minimize(ui);
         });
     });
+
+    ui.allocate_ui_at_rect(title_bar_rect, |ui| {
+        ui.with_layout(egui::Layout::left_to_right(egui::Align::Center), |ui| {
+            ui.add_space(8.0);
+            add_title_bar_contents(ui);
+        });
+    });
 }
 
 /// Show some close/maximize/minimize buttons for the native window.
@@ -124,4 +141,4 @@ fn close_maximize_minimize(ui: &mut egui::Ui) {
     if minimized_response.clicked() {
         ui.ctx().send_viewport_cmd(ViewportCommand::Minimized(true));
     }
-}
\ No newline at end of file
+}
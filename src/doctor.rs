@@ -0,0 +1,535 @@
+use serde::Serialize;
+use std::env;
+use std::path::Path;
+
+use crate::chrome_interface::ChromeInterface;
+use crate::registry_utils;
+use crate::registry_utils::{RegistryReader, URL_PROG_ID};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fix: Option<String>,
+}
+
+impl CheckResult {
+    fn pass(name: &'static str, detail: String) -> Self {
+        CheckResult {
+            name,
+            status: CheckStatus::Pass,
+            detail,
+            fix: None,
+        }
+    }
+
+    fn warn(name: &'static str, detail: String, fix: &str) -> Self {
+        CheckResult {
+            name,
+            status: CheckStatus::Warn,
+            detail,
+            fix: Some(fix.to_string()),
+        }
+    }
+
+    fn fail(name: &'static str, detail: String, fix: &str) -> Self {
+        CheckResult {
+            name,
+            status: CheckStatus::Fail,
+            detail,
+            fix: Some(fix.to_string()),
+        }
+    }
+}
+
+///
+/// results of `chrome_valet doctor`, serialized as is for --json
+///
+#[derive(Serialize, Debug)]
+pub struct DoctorReport {
+    pub version: &'static str,
+    pub checks: Vec<CheckResult>,
+}
+
+impl DoctorReport {
+    pub fn has_failures(&self) -> bool {
+        self.checks
+            .iter()
+            .any(|check| check.status == CheckStatus::Fail)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("Chrome Valet {}\n", self.version);
+        for check in &self.checks {
+            let status = match check.status {
+                CheckStatus::Pass => "PASS",
+                CheckStatus::Warn => "WARN",
+                CheckStatus::Fail => "FAIL",
+            };
+            text += &format!("[{status}] {}: {}\n", check.name, check.detail);
+            if let Some(fix) = &check.fix {
+                text += &format!("       fix: {fix}\n");
+            }
+        }
+        text
+    }
+}
+
+///
+/// checks everything chrome valet needs to route a link, without opening anything
+///
+pub fn run(chrome: &mut ChromeInterface) -> DoctorReport {
+    let browser = chrome.default_browser();
+    let profiles_result = chrome.populate_profile_entries();
+
+    let checks = vec![
+        check_default_browser(),
+        check_url_handler(),
+        check_browser_exe(chrome, &browser),
+        check_local_state(chrome, profiles_result.err()),
+        check_prefs(chrome),
+        check_rule_targets(chrome),
+        check_rules(chrome),
+    ];
+
+    DoctorReport {
+        version: env!("CARGO_PKG_VERSION"),
+        checks,
+    }
+}
+
+#[cfg(windows)]
+fn check_default_browser() -> CheckResult {
+    check_default_browser_from(&registry_utils::SystemRegistry)
+}
+
+#[cfg(target_os = "linux")]
+fn check_default_browser() -> CheckResult {
+    check_xdg_default(registry_utils::get_xdg_default("x-scheme-handler/https"))
+}
+
+#[cfg(windows)]
+fn check_url_handler() -> CheckResult {
+    let current_exe = env::current_exe().ok();
+    check_url_handler_from(&registry_utils::SystemRegistry, current_exe.as_deref())
+}
+
+#[cfg(target_os = "linux")]
+fn check_url_handler() -> CheckResult {
+    let current_exe = env::current_exe().ok();
+    check_desktop_entry(&registry_utils::applications_dir(), current_exe.as_deref())
+}
+
+#[cfg_attr(not(windows), allow(dead_code))]
+fn check_default_browser_from(registry: &impl RegistryReader) -> CheckResult {
+    let name = "Default browser";
+    match registry_utils::is_default_browser_from(registry) {
+        Ok(true) => CheckResult::pass(name, "Chrome Valet opens http links".to_string()),
+        Ok(false) => CheckResult::fail(
+            name,
+            "another browser opens http links".to_string(),
+            "choose Chrome Valet as the web browser in Settings > Apps > Default apps",
+        ),
+        Err(e) => CheckResult::fail(
+            name,
+            format!("couldn't read the http UserChoice: {e}"),
+            "choose Chrome Valet as the web browser in Settings > Apps > Default apps",
+        ),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn check_xdg_default(default: Result<String, anyhow::Error>) -> CheckResult {
+    let name = "Default browser";
+    let fix = "run `chrome_valet register`, or choose Chrome Valet as the web browser in your desktop's settings";
+    match default {
+        Ok(desktop_file) if desktop_file == registry_utils::DESKTOP_FILE_NAME => {
+            CheckResult::pass(name, "Chrome Valet opens https links".to_string())
+        }
+        Ok(desktop_file) if desktop_file.is_empty() => {
+            CheckResult::fail(name, "no browser opens https links".to_string(), fix)
+        }
+        Ok(desktop_file) => {
+            CheckResult::fail(name, format!("{desktop_file} opens https links"), fix)
+        }
+        Err(e) => CheckResult::fail(
+            name,
+            format!("couldn't query the https handler: {e:#}"),
+            fix,
+        ),
+    }
+}
+
+/// the fix when links go to a different chrome_valet executable
+const OTHER_COPY_FIX: &str = "ignore this if you have more than one copy of Chrome Valet, otherwise run `chrome_valet register`";
+
+/// `command` runs a different executable than `current_exe`, paths only differ by case on windows
+fn runs_other_copy(command: &str, current_exe: Option<&Path>) -> bool {
+    let Some(current_exe) = current_exe else {
+        return false;
+    };
+    let command_exe = registry_utils::command_exe(command);
+    if cfg!(windows) {
+        command_exe.to_lowercase() != current_exe.to_string_lossy().to_lowercase()
+    } else {
+        Path::new(command_exe) != current_exe
+    }
+}
+
+#[cfg_attr(not(windows), allow(dead_code))]
+fn check_url_handler_from(
+    registry: &impl RegistryReader,
+    current_exe: Option<&Path>,
+) -> CheckResult {
+    let name = "URL handler";
+    let reinstall = "run `chrome_valet register`, or reinstall Chrome Valet";
+
+    for scheme in ["http", "https"] {
+        match registry_utils::get_url_association_from(registry, scheme) {
+            Ok(prog_id) if prog_id == URL_PROG_ID => (),
+            Ok(prog_id) => {
+                return CheckResult::fail(
+                    name,
+                    format!("{scheme} is associated with \"{prog_id}\", not {URL_PROG_ID}"),
+                    reinstall,
+                )
+            }
+            Err(e) => {
+                return CheckResult::fail(
+                    name,
+                    format!("no {scheme} URL association: {e}"),
+                    reinstall,
+                )
+            }
+        }
+    }
+
+    let command = match registry_utils::get_url_handler_command_from(registry) {
+        Ok(command) => command,
+        Err(e) => {
+            return CheckResult::fail(
+                name,
+                format!("{URL_PROG_ID} has no open command: {e}"),
+                reinstall,
+            )
+        }
+    };
+
+    if !command.contains("%1") {
+        return CheckResult::fail(
+            name,
+            format!("\"{command}\" doesn't pass the url along"),
+            reinstall,
+        );
+    }
+
+    if runs_other_copy(&command, current_exe) {
+        return CheckResult::warn(
+            name,
+            format!("links open \"{command}\", not this copy of Chrome Valet"),
+            OTHER_COPY_FIX,
+        );
+    }
+
+    CheckResult::pass(name, format!("links run \"{command}\""))
+}
+
+#[cfg(target_os = "linux")]
+fn check_desktop_entry(applications_dir: &Path, current_exe: Option<&Path>) -> CheckResult {
+    let name = "URL handler";
+    let register = "run `chrome_valet register`";
+    let desktop_file = applications_dir.join(registry_utils::DESKTOP_FILE_NAME);
+
+    let Some(command) = registry_utils::get_desktop_entry_command(applications_dir) else {
+        return CheckResult::fail(
+            name,
+            format!("{} is missing or has no Exec line", desktop_file.display()),
+            register,
+        );
+    };
+
+    if !command.contains("%u") && !command.contains("%U") {
+        return CheckResult::fail(
+            name,
+            format!("\"{command}\" doesn't pass the url along"),
+            register,
+        );
+    }
+
+    if runs_other_copy(&command, current_exe) {
+        return CheckResult::warn(
+            name,
+            format!("links open \"{command}\", not this copy of Chrome Valet"),
+            OTHER_COPY_FIX,
+        );
+    }
+
+    CheckResult::pass(name, format!("links run \"{command}\""))
+}
+
+fn check_browser_exe(chrome: &ChromeInterface, browser: &registry_utils::Browser) -> CheckResult {
+    let name = "Browser executable";
+    match chrome.config().resolve_browser_exe(browser) {
+        Ok(exe) if Path::new(&exe).exists() => CheckResult::pass(name, format!("{browser}: {exe}")),
+        Ok(exe) => CheckResult::fail(
+            name,
+            format!("{browser}: {exe} doesn't exist"),
+            "reinstall the browser, or fix its exe in chromevalet.toml",
+        ),
+        Err(e) => CheckResult::fail(
+            name,
+            format!("{e:#}"),
+            "reinstall the browser, or set its exe in chromevalet.toml",
+        ),
+    }
+}
+
+fn check_local_state(chrome: &ChromeInterface, error: Option<anyhow::Error>) -> CheckResult {
+    let name = "Local State";
+    let statefile_path = chrome.statefile_path();
+    match error {
+        Some(e) => CheckResult::fail(
+            name,
+            format!("{}: {e:#}", statefile_path.display()),
+            "open the browser once so it creates its profiles, or set user_data_dir in chromevalet.toml",
+        ),
+        None if chrome.profile_entries.is_empty() => CheckResult::warn(
+            name,
+            format!("{} has no profiles", statefile_path.display()),
+            "open the browser once so it creates its profiles",
+        ),
+        None => CheckResult::pass(
+            name,
+            format!(
+                "{} profile(s) in {}",
+                chrome.profile_entries.len(),
+                statefile_path.display()
+            ),
+        ),
+    }
+}
+
+fn check_prefs(chrome: &ChromeInterface) -> CheckResult {
+    let name = "Preferences";
+    if chrome.load_errors().is_empty() {
+        CheckResult::pass(
+            name,
+            "prefs.json and chromevalet.toml are valid".to_string(),
+        )
+    } else {
+        CheckResult::fail(
+            name,
+            chrome.load_errors().join("; "),
+            "fix the file mentioned, or delete it to start over",
+        )
+    }
+}
+
+fn check_rule_targets(chrome: &ChromeInterface) -> CheckResult {
+    let name = "Profiles in use";
+    if chrome.profile_entries.is_empty() {
+        return CheckResult::warn(
+            name,
+            "no profiles were read, so the preferred profile and rules weren't checked".to_string(),
+            "fix the Local State check above, then run doctor again",
+        );
+    }
+
+    let warnings = chrome.profile_warnings();
+    if warnings.is_empty() {
+        CheckResult::pass(
+            name,
+            "the preferred profile and every rule's profile exist".to_string(),
+        )
+    } else {
+        CheckResult::fail(
+            name,
+            warnings.join(" "),
+            "pick a new preferred profile in the picker, and fix the rule's profile",
+        )
+    }
+}
+
+fn check_rules(chrome: &ChromeInterface) -> CheckResult {
+    let name = "Rules";
+    // missing profiles are checked above
+    let warnings: Vec<String> = chrome
+        .lint_rules(None)
+        .iter()
+        .map(|warning| warning.to_string())
+        .collect();
+    if warnings.is_empty() {
+        CheckResult::pass(name, "no shadowed, duplicate or invalid rules".to_string())
+    } else {
+        CheckResult::warn(
+            name,
+            warnings.join("; "),
+            "run `chrome_valet rules lint` and fix or remove the rules it lists",
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry_utils::{register_url_handler, FakeRegistry, RegHive, RegistryWriter};
+
+    const EXE: &str = r"C:\valet\chrome_valet.exe";
+
+    fn with_user_choice(prog_id: &str) -> FakeRegistry {
+        FakeRegistry::default().with_string(
+            RegHive::CurrentUser,
+            r"Software\Microsoft\Windows\Shell\Associations\UrlAssociations\http\UserChoice",
+            "ProgID",
+            prog_id,
+        )
+    }
+
+    #[test]
+    fn default_browser() {
+        assert_eq!(
+            check_default_browser_from(&with_user_choice(URL_PROG_ID)).status,
+            CheckStatus::Pass
+        );
+        assert_eq!(
+            check_default_browser_from(&with_user_choice("ChromeHTML")).status,
+            CheckStatus::Fail
+        );
+        assert_eq!(
+            check_default_browser_from(&FakeRegistry::default()).status,
+            CheckStatus::Fail
+        );
+    }
+
+    #[test]
+    fn url_handler_registered() {
+        let mut registry = FakeRegistry::default();
+        register_url_handler(&mut registry, EXE, &[]).unwrap();
+
+        let check = check_url_handler_from(&registry, Some(Path::new(EXE)));
+        assert_eq!(check.status, CheckStatus::Pass, "{}", check.detail);
+
+        // case differences are the same exe on windows, and only there
+        let check =
+            check_url_handler_from(&registry, Some(Path::new(r"c:\VALET\chrome_valet.exe")));
+        let same_exe = if cfg!(windows) {
+            CheckStatus::Pass
+        } else {
+            CheckStatus::Warn
+        };
+        assert_eq!(check.status, same_exe, "{}", check.detail);
+    }
+
+    #[test]
+    fn url_handler_for_another_copy() {
+        let mut registry = FakeRegistry::default();
+        register_url_handler(&mut registry, EXE, &[]).unwrap();
+
+        let check =
+            check_url_handler_from(&registry, Some(Path::new(r"D:\portable\chrome_valet.exe")));
+        assert_eq!(check.status, CheckStatus::Warn, "{}", check.detail);
+
+        // the registered exe's path contains this one's, but isn't it
+        let check = check_url_handler_from(&registry, Some(Path::new(r"C:\valet\chrome_valet")));
+        assert_eq!(check.status, CheckStatus::Warn, "{}", check.detail);
+    }
+
+    #[test]
+    fn url_handler_missing_or_broken() {
+        let check = check_url_handler_from(&FakeRegistry::default(), Some(Path::new(EXE)));
+        assert_eq!(check.status, CheckStatus::Fail);
+
+        let mut registry = FakeRegistry::default();
+        register_url_handler(&mut registry, EXE, &[]).unwrap();
+        registry
+            .set_string(
+                RegHive::CurrentUser,
+                r"Software\Clients\StartMenuInternet\ChromeValet\Capabilities\URLAssociations",
+                "https",
+                "ChromeHTML",
+            )
+            .unwrap();
+        let check = check_url_handler_from(&registry, Some(Path::new(EXE)));
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert!(check.detail.contains("ChromeHTML"), "{}", check.detail);
+
+        let mut registry = FakeRegistry::default();
+        register_url_handler(&mut registry, EXE, &[]).unwrap();
+        registry
+            .set_string(
+                RegHive::CurrentUser,
+                r"Software\Classes\ChromeValetURL\shell\open\command",
+                "",
+                EXE,
+            )
+            .unwrap();
+        let check = check_url_handler_from(&registry, Some(Path::new(EXE)));
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert!(
+            check.detail.contains("doesn't pass the url"),
+            "{}",
+            check.detail
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn xdg_default() {
+        assert_eq!(
+            check_xdg_default(Ok(registry_utils::DESKTOP_FILE_NAME.to_string())).status,
+            CheckStatus::Pass
+        );
+        assert_eq!(
+            check_xdg_default(Ok("firefox.desktop".to_string())).status,
+            CheckStatus::Fail
+        );
+        assert_eq!(
+            check_xdg_default(Ok(String::new())).status,
+            CheckStatus::Fail
+        );
+        assert_eq!(
+            check_xdg_default(Err(anyhow::anyhow!("no xdg-mime"))).status,
+            CheckStatus::Fail
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn desktop_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let exe = "/opt/chrome_valet/chrome_valet";
+
+        let check = check_desktop_entry(dir.path(), Some(Path::new(exe)));
+        assert_eq!(check.status, CheckStatus::Fail);
+
+        std::fs::write(
+            dir.path().join(registry_utils::DESKTOP_FILE_NAME),
+            registry_utils::desktop_entry(exe, &[]),
+        )
+        .unwrap();
+        let check = check_desktop_entry(dir.path(), Some(Path::new(exe)));
+        assert_eq!(check.status, CheckStatus::Pass, "{}", check.detail);
+
+        let check = check_desktop_entry(dir.path(), Some(Path::new("/home/me/chrome_valet")));
+        assert_eq!(check.status, CheckStatus::Warn, "{}", check.detail);
+
+        // paths that only differ by case, or that one starts with, are other files on linux
+        for other in [
+            "/opt/Chrome_Valet/chrome_valet",
+            "/opt/chrome_valet/chrome_val",
+        ] {
+            let check = check_desktop_entry(dir.path(), Some(Path::new(other)));
+            assert_eq!(check.status, CheckStatus::Warn, "{other}: {}", check.detail);
+        }
+    }
+}
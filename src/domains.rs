@@ -0,0 +1,136 @@
+use publicsuffix::{List, Psl};
+use std::net::IpAddr;
+use std::sync::OnceLock;
+
+/// mozilla's public suffix list, bundled so matching works offline and doesn't change under the user
+/// refresh from https://publicsuffix.org/list/public_suffix_list.dat
+const PUBLIC_SUFFIX_LIST: &str = include_str!("../assets/public_suffix_list.dat");
+
+fn public_suffix_list() -> &'static List {
+    static LIST: OnceLock<List> = OnceLock::new();
+    LIST.get_or_init(|| {
+        PUBLIC_SUFFIX_LIST
+            .parse()
+            .expect("bundled public suffix list")
+    })
+}
+
+///
+/// lowercase, punycode, without a trailing dot
+/// labels holding a * are left alone, so globs can be normalized too
+///
+pub fn normalize_host(host: &str) -> String {
+    host.trim_end_matches('.')
+        .split('.')
+        .map(|label| {
+            if label.contains('*') {
+                label.to_lowercase()
+            } else {
+                idna::domain_to_ascii(label).unwrap_or_else(|_| label.to_lowercase())
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(".")
+}
+
+///
+/// the part of `host` someone could register, eg. "example.co.uk" for "www.example.co.uk"
+/// None for ip addresses and public suffixes
+///
+pub fn registrable_domain(host: &str) -> Option<String> {
+    let host = normalize_host(host);
+    if host.parse::<IpAddr>().is_ok() {
+        return None;
+    }
+
+    let domain = public_suffix_list().domain(host.as_bytes())?;
+    String::from_utf8(domain.as_bytes().to_vec()).ok()
+}
+
+///
+/// whether `domain` is on the public suffix list, eg. "co.uk" or "github.io"
+/// names the list doesn't know, like "localhost" or "intranet", aren't
+///
+pub fn is_public_suffix(domain: &str) -> bool {
+    let domain = normalize_host(domain);
+    match public_suffix_list().suffix(domain.as_bytes()) {
+        Some(suffix) => suffix.is_known() && suffix.as_bytes() == domain.as_bytes(),
+        None => false,
+    }
+}
+
+///
+/// `host` is `domain`, or with `subdomains` any host under it
+/// `domain` can't reach above the registrable domain of `host`, a rule for "co.uk" would take a whole country
+///
+pub fn domain_matches(domain: &str, host: &str, subdomains: bool) -> bool {
+    let domain = normalize_host(domain);
+    let host = normalize_host(host);
+    if domain.is_empty() {
+        return false;
+    }
+
+    let under = host == domain || (subdomains && host.ends_with(&format!(".{domain}")));
+    if !under {
+        return false;
+    }
+
+    match registrable_domain(&host) {
+        Some(registrable) => domain == registrable || domain.ends_with(&format!(".{registrable}")),
+        // ip addresses, and public suffixes themselves
+        None => !is_public_suffix(&domain),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registrable_domain_uses_the_public_suffix_list() {
+        assert_eq!(
+            registrable_domain("www.example.co.uk").as_deref(),
+            Some("example.co.uk")
+        );
+        assert_eq!(
+            registrable_domain("me.github.io").as_deref(),
+            Some("me.github.io")
+        );
+        assert_eq!(registrable_domain("co.uk"), None);
+        assert_eq!(registrable_domain("127.0.0.1"), None);
+    }
+
+    #[test]
+    fn domain_and_subdomains() {
+        assert!(domain_matches("example.co.uk", "example.co.uk", true));
+        assert!(domain_matches("example.co.uk", "mail.example.co.uk", true));
+        assert!(!domain_matches(
+            "example.co.uk",
+            "mail.example.co.uk",
+            false
+        ));
+        assert!(!domain_matches("example.com", "example.com.evil.net", true));
+        assert!(!domain_matches("example.com", "notexample.com", true));
+        assert!(!domain_matches("co.uk", "example.co.uk", true));
+        assert!(!domain_matches("co.uk", "co.uk", true));
+        assert!(!domain_matches("github.io", "me.github.io", true));
+        assert!(domain_matches("me.github.io", "docs.me.github.io", true));
+        assert!(domain_matches("localhost", "localhost", true));
+        assert!(domain_matches("corp.internal", "wiki.corp.internal", true));
+        assert!(domain_matches("10.0.0.1", "10.0.0.1", true));
+    }
+
+    #[test]
+    fn idn_hosts_are_compared_as_punycode() {
+        assert_eq!(normalize_host("Bücher.Example."), "xn--bcher-kva.example");
+        assert_eq!(
+            normalize_host("*.Bücher.example"),
+            "*.xn--bcher-kva.example"
+        );
+        assert!(domain_matches(
+            "bücher.example",
+            "www.xn--bcher-kva.example",
+            true
+        ));
+    }
+}
@@ -0,0 +1,500 @@
+use chrono::{DateTime, Local, Utc};
+use serde::Serialize;
+
+use crate::chrome_interface::ChromeInterface;
+use crate::history::Routing;
+use crate::launcher::LaunchRequest;
+use crate::network::{NetworkSnapshot, NetworkState};
+use crate::routing::{self, Decision};
+use crate::rules::{Link, RoutingRule, RuleEvaluation, RuleLayer};
+
+#[derive(Serialize, Debug)]
+pub struct RuleReport {
+    pub layer: RuleLayer,
+    /// position in its file, from 1
+    pub index: usize,
+    pub rule: RoutingRule,
+    #[serde(flatten)]
+    pub evaluation: RuleEvaluation,
+    /// the first rule to match decides where the link opens
+    pub winner: bool,
+}
+
+///
+/// a [[preferred]] profile, and whether its schedule is on now
+///
+#[derive(Serialize, Debug)]
+pub struct ScheduledReport {
+    pub profile: String,
+    pub schedule: String,
+    pub active: bool,
+    pub reason: String,
+}
+
+///
+/// why a link would open where it does, as printed by `chrome_valet explain`
+///
+#[derive(Serialize, Debug)]
+pub struct Explanation {
+    /// the link as given, links aren't cleaned so it's the same as url
+    pub original_url: String,
+    pub url: String,
+    /// the program the link is treated as clicked in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_app: Option<String>,
+    /// only read when a rule has a network condition
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkSnapshot>,
+    /// the context in use, from `context use` or the picker
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    /// when a timed context ends, eg. "14:30", or "Fri 6 Mar 14:30" if it isn't today
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_until: Option<String>,
+    pub rules: Vec<RuleReport>,
+    /// checked when no rule matches
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub preferred: Vec<ScheduledReport>,
+    /// "open" or "show_picker"
+    pub action: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub browser: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    pub reason: String,
+    /// the browser executable and its arguments
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command_line: Option<Vec<String>>,
+}
+
+///
+/// routes `url` like a click in `source_app` would, without opening anything
+///
+pub fn explain(
+    chrome: &mut ChromeInterface,
+    url: &str,
+    source_app: Option<&str>,
+    network_state: &dyn NetworkState,
+) -> Explanation {
+    explain_at(chrome, url, source_app, network_state, Utc::now())
+}
+
+///
+/// `explain` at `now`, the rules, schedules and context are all checked at the same time as the decision
+///
+fn explain_at(
+    chrome: &mut ChromeInterface,
+    url: &str,
+    source_app: Option<&str>,
+    network_state: &dyn NetworkState,
+    now: DateTime<Utc>,
+) -> Explanation {
+    let default_browser = chrome.default_browser();
+    let profiles_known = chrome.populate_profile_entries().is_ok();
+    let network = chrome
+        .needs_network()
+        .then(|| NetworkSnapshot::read(network_state));
+    let link = Link {
+        url,
+        source_app,
+        network: network.as_ref(),
+    };
+
+    let mut winner_found = false;
+    let mut seen_layers: Vec<RuleLayer> = Vec::new();
+    let rules = chrome
+        .layered_rules()
+        .map(|(layer, rule)| {
+            let evaluation = rule.evaluate_at(&link, now);
+            let winner = evaluation.matched && !winner_found;
+            winner_found |= winner;
+            seen_layers.push(layer);
+            RuleReport {
+                layer,
+                index: seen_layers
+                    .iter()
+                    .filter(|counted| **counted == layer)
+                    .count(),
+                rule: rule.clone(),
+                evaluation,
+                winner,
+            }
+        })
+        .collect();
+
+    let preferred = chrome
+        .config()
+        .preferred
+        .iter()
+        .map(|scheduled| {
+            let (active, reason) = match scheduled.schedule.check(now) {
+                Ok(check) if check.active => (true, format!("{} is within it", check.local_time)),
+                Ok(check) => (false, format!("{} is outside it", check.local_time)),
+                Err(err) => (false, format!("invalid schedule: {err}")),
+            };
+            ScheduledReport {
+                profile: scheduled.profile.clone(),
+                schedule: scheduled.schedule.to_string(),
+                active,
+                reason,
+            }
+        })
+        .collect();
+
+    let context = chrome
+        .current_context_at(now)
+        .map(|(name, _)| name.to_string());
+    let context_until = chrome
+        .context_override_at(now)
+        .filter(|_| context.is_some())
+        .map(|context_override| context_override.until_from(&now.with_timezone(&Local)));
+
+    let mut explanation = Explanation {
+        original_url: url.to_string(),
+        url: url.to_string(),
+        source_app: source_app.map(String::from),
+        network,
+        context,
+        context_until,
+        rules,
+        preferred,
+        action: "show_picker",
+        browser: None,
+        profile: None,
+        reason: String::new(),
+        command_line: None,
+    };
+
+    let link = Link {
+        url: &explanation.url,
+        source_app,
+        network: explanation.network.as_ref(),
+    };
+    match routing::decide_at(chrome, &link, default_browser, profiles_known, now) {
+        Decision::Open {
+            browser,
+            profile,
+            reason,
+        } => {
+            let request = LaunchRequest {
+                browser,
+                url: explanation.url.clone(),
+                profile_directory: profile.clone(),
+                routing: Routing::Automatic,
+                source_app: source_app.map(String::from),
+                exit_when_done: false,
+            };
+            let exe = chrome
+                .config()
+                .resolve_browser_exe(&browser)
+                .unwrap_or_else(|err| format!("<{err:#}>"));
+
+            let mut command_line = vec![exe];
+            command_line.extend(request.args());
+            explanation.action = "open";
+            explanation.browser = Some(browser.to_string());
+            explanation.profile = profile;
+            explanation.reason = reason;
+            explanation.command_line = Some(command_line);
+        }
+        Decision::ShowPicker { reason, .. } => explanation.reason = reason,
+    }
+
+    explanation
+}
+
+impl Explanation {
+    pub fn to_text(&self) -> String {
+        let mut text = format!("URL: {}\n", self.url);
+        if let Some(source_app) = &self.source_app {
+            text += &format!("  opened from {source_app}\n");
+        }
+
+        if self.original_url == self.url {
+            text += "\nCleaning: unchanged\n";
+        } else {
+            text += &format!("\nCleaning: {} -> {}\n", self.original_url, self.url);
+        }
+
+        if let Some(network) = &self.network {
+            text += "\nNetwork:\n";
+            for interface in &network.interfaces {
+                let addresses: Vec<String> = interface
+                    .addresses
+                    .iter()
+                    .map(|address| address.to_string())
+                    .collect();
+                text += &format!("  {}: {}\n", interface.name, addresses.join(", "));
+            }
+            if !network.dns_suffixes.is_empty() {
+                text += &format!("  dns suffixes: {}\n", network.dns_suffixes.join(", "));
+            }
+        }
+
+        match (&self.context, &self.context_until) {
+            (Some(context), Some(until)) => {
+                text += &format!("\nContext: {context}, until {until}\n")
+            }
+            (Some(context), None) => text += &format!("\nContext: {context}\n"),
+            _ => {}
+        }
+
+        text += "\nRules:\n";
+        for report in &self.rules {
+            let status = match (report.evaluation.matched, report.winner) {
+                (true, true) => "MATCH",
+                (true, false) => "match",
+                (false, _) => "-----",
+            };
+            text += &format!(
+                "  [{status}] {} #{}: {}\n          {}\n",
+                report.layer, report.index, report.rule, report.evaluation.reason
+            );
+        }
+        if self.rules.is_empty() {
+            text += "  no rules\n";
+        }
+
+        if !self.preferred.is_empty() {
+            text += "\nScheduled preferred profiles:\n";
+            for report in &self.preferred {
+                let status = if report.active { "ON " } else { "off" };
+                text += &format!(
+                    "  [{status}] \"{}\" during {}\n          {}\n",
+                    report.profile, report.schedule, report.reason
+                );
+            }
+        }
+
+        text += "\n";
+        match self.rules.iter().find(|report| report.winner) {
+            Some(report) => text += &format!("Winning rule: {} #{}\n", report.layer, report.index),
+            None => text += "Winning rule: none\n",
+        }
+
+        match (&self.browser, &self.command_line) {
+            (Some(browser), Some(command_line)) => {
+                let profile = self.profile.as_deref().unwrap_or("last used profile");
+                text += &format!("Action: open in {browser}, {profile} ({})\n", self.reason);
+                let quoted: Vec<String> = command_line.iter().map(|arg| quote_arg(arg)).collect();
+                text += &format!("Command: {}\n", quoted.join(" "));
+            }
+            _ => text += &format!("Action: show the picker ({})\n", self.reason),
+        }
+
+        text +=
+            "\nHolding Alt when the link is clicked, or --force-ui, shows the picker instead.\n";
+        text
+    }
+}
+
+fn quote_arg(arg: &str) -> String {
+    if arg.contains(' ') {
+        format!("\"{arg}\"")
+    } else {
+        arg.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chrome_interface::ProgramPrefs;
+    use crate::config::{ContextConfig, ScheduledProfile, UserConfig};
+    use crate::contexts::ContextOverride;
+    use crate::network::{FakeNetwork, NetworkCondition};
+    use crate::rules::host_rule;
+    use crate::schedule::Schedule;
+    use chrono::TimeZone;
+
+    const URL: &str = "https://jira.example.com/browse/X-1";
+
+    fn no_network() -> FakeNetwork {
+        FakeNetwork::default()
+    }
+
+    /// a wednesday, `hour`:`minute`:`second` utc
+    fn at(hour: u32, minute: u32, second: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 3, 4, hour, minute, second)
+            .unwrap()
+    }
+
+    fn until_five() -> Schedule {
+        Schedule {
+            until: Some("17:00".to_string()),
+            timezone: Some("UTC".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn first_matching_rule_wins_across_layers() {
+        let config = UserConfig {
+            rules: vec![
+                host_rule("docs.example.com", "Default"),
+                host_rule("*.example.com", "Profile 1"),
+            ],
+            ..Default::default()
+        };
+        let prefs = ProgramPrefs {
+            rules: vec![host_rule("jira.example.com", "Default")],
+            ..Default::default()
+        };
+        let mut chrome = ChromeInterface::with_profiles(config, prefs, &[]);
+        let explanation = explain_at(&mut chrome, URL, None, &no_network(), at(12, 0, 0));
+
+        let summary: Vec<(RuleLayer, usize, bool, bool)> = explanation
+            .rules
+            .iter()
+            .map(|report| {
+                (
+                    report.layer,
+                    report.index,
+                    report.evaluation.matched,
+                    report.winner,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (RuleLayer::Config, 1, false, false),
+                (RuleLayer::Config, 2, true, true),
+                (RuleLayer::Prefs, 1, true, false),
+            ]
+        );
+
+        assert_eq!(explanation.action, "open");
+        assert_eq!(explanation.profile.as_deref(), Some("Profile 1"));
+        let command_line = explanation.command_line.unwrap();
+        assert_eq!(
+            command_line[1..],
+            ["--profile-directory=Profile 1", "--single-argument", URL]
+        );
+        assert!(explanation.network.is_none());
+    }
+
+    #[test]
+    fn rules_and_decision_agree_at_a_schedule_boundary() {
+        let config = UserConfig {
+            rules: vec![RoutingRule {
+                schedule: Some(until_five()),
+                ..host_rule("*.example.com", "Profile 1")
+            }],
+            preferred: vec![ScheduledProfile {
+                profile: "Profile 2".to_string(),
+                schedule: Schedule {
+                    from: Some("17:00".to_string()),
+                    timezone: Some("UTC".to_string()),
+                    ..Default::default()
+                },
+            }],
+            ..Default::default()
+        };
+        let mut chrome = ChromeInterface::with_profiles(config, ProgramPrefs::default(), &[]);
+
+        let before = explain_at(&mut chrome, URL, None, &no_network(), at(16, 59, 59));
+        assert!(before.rules[0].winner);
+        assert!(!before.preferred[0].active);
+        assert_eq!(before.profile.as_deref(), Some("Profile 1"));
+
+        let after = explain_at(&mut chrome, URL, None, &no_network(), at(17, 0, 0));
+        assert!(!after.rules[0].winner);
+        assert_eq!(
+            after.rules[0].evaluation.reason,
+            "Wed 17:00 UTC is outside every day 00:00-17:00 UTC"
+        );
+        assert!(after.preferred[0].active);
+        assert_eq!(after.profile.as_deref(), Some("Profile 2"));
+    }
+
+    #[test]
+    fn context_is_checked_at_the_same_time() {
+        let until = at(17, 0, 0).timestamp() as u64;
+        let mut config = UserConfig::default();
+        config.contexts.insert(
+            "Work".to_string(),
+            ContextConfig {
+                profile: Some("Profile 1".to_string()),
+                rules: Vec::new(),
+            },
+        );
+        let prefs = ProgramPrefs {
+            context_override: Some(ContextOverride {
+                context: "Work".to_string(),
+                until,
+            }),
+            ..Default::default()
+        };
+        let mut chrome = ChromeInterface::with_profiles(config, prefs, &[]);
+
+        let during = explain_at(&mut chrome, URL, None, &no_network(), at(16, 59, 59));
+        assert_eq!(during.context.as_deref(), Some("Work"));
+        assert!(during.context_until.is_some());
+        assert_eq!(during.profile.as_deref(), Some("Profile 1"));
+
+        let after = explain_at(&mut chrome, URL, None, &no_network(), at(17, 0, 0));
+        assert_eq!(after.context, None);
+        assert_eq!(after.context_until, None);
+        assert_eq!(after.profile, None);
+    }
+
+    #[test]
+    fn network_is_only_read_for_network_rules() {
+        let config = UserConfig {
+            rules: vec![RoutingRule {
+                network: Some(NetworkCondition {
+                    interface: Some("tun*".to_string()),
+                    ..Default::default()
+                }),
+                ..host_rule("*.example.com", "Profile 1")
+            }],
+            ..Default::default()
+        };
+        let mut chrome = ChromeInterface::with_profiles(config, ProgramPrefs::default(), &[]);
+        let on_vpn = FakeNetwork {
+            interfaces: vec![("tun0", "10.20.3.4")],
+            ..Default::default()
+        };
+
+        let explanation = explain_at(&mut chrome, URL, Some("slack"), &on_vpn, at(12, 0, 0));
+        assert_eq!(explanation.network.as_ref().unwrap().interfaces.len(), 1);
+        assert!(explanation.rules[0].winner);
+
+        let text = explanation.to_text();
+        assert!(text.contains("  opened from slack\n"));
+        assert_eq!(explanation.original_url, URL);
+        assert!(text.contains("\nCleaning: unchanged\n"));
+        assert!(text.contains("  tun0: 10.20.3.4\n"));
+        assert!(text.contains("[MATCH] chromevalet.toml #1"));
+        assert!(text.contains("Winning rule: chromevalet.toml #1\n"));
+    }
+
+    #[test]
+    fn ask_rule_shows_the_picker() {
+        let config = UserConfig {
+            rules: vec![RoutingRule {
+                ask: true,
+                ..host_rule("*.example.com", "")
+            }],
+            ..Default::default()
+        };
+        let mut chrome = ChromeInterface::with_profiles(config, ProgramPrefs::default(), &[]);
+        let explanation = explain_at(&mut chrome, URL, None, &no_network(), at(12, 0, 0));
+
+        assert_eq!(explanation.action, "show_picker");
+        assert!(explanation.command_line.is_none());
+        assert!(explanation
+            .to_text()
+            .contains("Action: show the picker (matched rule host \"*.example.com\" -> picker)\n"));
+    }
+
+    #[test]
+    fn arguments_with_spaces_are_quoted() {
+        assert_eq!(quote_arg("--single-argument"), "--single-argument");
+        assert_eq!(
+            quote_arg("--profile-directory=Profile 1"),
+            "\"--profile-directory=Profile 1\""
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+struct WatchedFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+///
+/// polls files for changes to their modification time
+///
+pub struct FileWatcher {
+    files: Vec<WatchedFile>,
+    last_poll: Instant,
+}
+
+impl FileWatcher {
+    pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        FileWatcher {
+            files: paths
+                .into_iter()
+                .map(|path| WatchedFile {
+                    modified: Self::modified_time(&path),
+                    path,
+                })
+                .collect(),
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.files.iter().map(|file| file.path.clone()).collect()
+    }
+
+    fn modified_time(path: &PathBuf) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    ///
+    /// files created, modified or deleted since the last poll
+    /// returns nothing if called more often than POLL_INTERVAL
+    ///
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        if self.last_poll.elapsed() < Self::POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = Vec::new();
+        for file in &mut self.files {
+            let modified = Self::modified_time(&file.path);
+            if modified != file.modified {
+                file.modified = modified;
+                changed.push(file.path.clone());
+            }
+        }
+
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    /// lets the next poll through without waiting out POLL_INTERVAL
+    fn make_due(watcher: &mut FileWatcher) {
+        watcher.last_poll = Instant::now() - FileWatcher::POLL_INTERVAL;
+    }
+
+    /// sets the mtime explicitly, a write within the filesystem's timestamp granularity may not change it
+    fn touch(path: &PathBuf, seconds: u64) {
+        let file = File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    #[test]
+    fn write_is_seen_on_the_next_poll() {
+        let dir = tempfile::tempdir().unwrap();
+        let watched = dir.path().join("prefs.json");
+        let other = dir.path().join("usage.json");
+        fs::write(&watched, "{}").unwrap();
+        fs::write(&other, "{}").unwrap();
+        touch(&watched, 1_000);
+        touch(&other, 1_000);
+
+        let mut watcher = FileWatcher::new(vec![watched.clone(), other.clone()]);
+        make_due(&mut watcher);
+        assert!(watcher.poll().is_empty());
+
+        fs::write(&watched, "{ \"preferred_profile\": \"Default\" }").unwrap();
+        touch(&watched, 2_000);
+        make_due(&mut watcher);
+        assert_eq!(watcher.poll(), [watched]);
+
+        // seen once
+        make_due(&mut watcher);
+        assert!(watcher.poll().is_empty());
+    }
+
+    #[test]
+    fn polls_within_the_interval_see_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let watched = dir.path().join("prefs.json");
+        fs::write(&watched, "{}").unwrap();
+        touch(&watched, 1_000);
+
+        let mut watcher = FileWatcher::new(vec![watched.clone()]);
+        touch(&watched, 2_000);
+        assert!(watcher.poll().is_empty());
+
+        make_due(&mut watcher);
+        assert_eq!(watcher.poll(), [watched]);
+    }
+
+    #[test]
+    fn creating_and_deleting_are_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let watched = dir.path().join("chromevalet.toml");
+        let mut watcher = FileWatcher::new(vec![watched.clone()]);
+
+        make_due(&mut watcher);
+        assert!(watcher.poll().is_empty());
+
+        fs::write(&watched, "").unwrap();
+        make_due(&mut watcher);
+        assert_eq!(watcher.poll(), vec![watched.clone()]);
+
+        fs::remove_file(&watched).unwrap();
+        make_due(&mut watcher);
+        assert_eq!(watcher.poll(), [watched]);
+    }
+}
@@ -0,0 +1,349 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{ErrorKind as IoErrorKind, Result as IoResult};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::HistoryConfig;
+use crate::jsonl;
+use crate::launcher::LaunchRequest;
+use crate::paths::app_data_dir;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// history.jsonl is only read to prune when recording a link once it's past max_entries of these,
+/// an entry is 150 to 400 bytes, so until then recording is a plain append
+const ENTRY_BYTES: u64 = 1024;
+
+///
+/// how the browser and profile were chosen
+///
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Routing {
+    /// a rule or the preferred profile, without showing the picker
+    #[default]
+    Automatic,
+    /// picked in the picker, or reopened from history
+    Manual,
+    /// chrome valet crashed or couldn't read profiles, and opened the link anyway
+    Fallback,
+}
+
+///
+/// a link chrome valet opened, a line of history.jsonl
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    /// seconds since the epoch
+    pub time: u64,
+
+    /// the link as it was clicked, links aren't cleaned so it's the same as url,
+    /// empty in entries recorded before it was
+    #[serde(default)]
+    pub original_url: String,
+
+    pub url: String,
+
+    pub browser: String,
+
+    /// profile directory, None for the browser's last used profile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+
+    #[serde(default)]
+    pub routing: Routing,
+
+    /// the program the link was clicked in, if it's known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_app: Option<String>,
+}
+
+impl HistoryEntry {
+    pub fn from_request(request: &LaunchRequest) -> Self {
+        HistoryEntry {
+            time: now(),
+            original_url: request.url.clone(),
+            url: request.url.clone(),
+            browser: request.browser.to_string(),
+            profile: request.profile_directory.clone(),
+            routing: request.routing,
+            source_app: request.source_app.clone(),
+        }
+    }
+
+    ///
+    /// case insensitive search of the url, profile and source app
+    ///
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        [
+            Some(&self.url),
+            self.profile.as_ref(),
+            self.source_app.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .any(|field| field.to_lowercase().contains(&query))
+    }
+}
+
+pub fn history_path() -> PathBuf {
+    app_data_dir().join("history.jsonl")
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+///
+/// appends `entry`, and prunes if history.jsonl has grown well past max_entries
+/// the exact limits in [history] are applied at picker start and by `history prune`
+///
+pub fn record(entry: &HistoryEntry, config: &HistoryConfig) -> IoResult<()> {
+    record_at(&history_path(), entry, config, now())
+}
+
+fn record_at(
+    history_path: &Path,
+    entry: &HistoryEntry,
+    config: &HistoryConfig,
+    now: u64,
+) -> IoResult<()> {
+    jsonl::append(history_path, entry)?;
+
+    if jsonl::larger_than(history_path, config.max_entries as u64 * ENTRY_BYTES) {
+        prune_at(history_path, config, now)?;
+    }
+    Ok(())
+}
+
+fn oldest_kept(config: &HistoryConfig, now: u64) -> u64 {
+    match config.max_age_days {
+        0 => 0,
+        days => now.saturating_sub(days * SECONDS_PER_DAY),
+    }
+}
+
+///
+/// every entry, oldest first, lines that don't parse are skipped
+///
+pub fn read() -> IoResult<Vec<HistoryEntry>> {
+    read_at(&history_path())
+}
+
+fn read_at(history_path: &Path) -> IoResult<Vec<HistoryEntry>> {
+    jsonl::read(history_path)
+}
+
+///
+/// the most recently opened link, for rerouting
+///
+pub fn last() -> IoResult<Option<HistoryEntry>> {
+    last_at(&history_path())
+}
+
+fn last_at(history_path: &Path) -> IoResult<Option<HistoryEntry>> {
+    Ok(read_at(history_path)?.pop())
+}
+
+///
+/// entries matching `query`, newest first
+///
+pub fn search<'a>(entries: &'a [HistoryEntry], query: &str, limit: usize) -> Vec<&'a HistoryEntry> {
+    entries
+        .iter()
+        .rev()
+        .filter(|entry| query.is_empty() || entry.matches(query))
+        .take(limit)
+        .collect()
+}
+
+///
+/// drops entries past the limits in [history], returns how many were dropped
+///
+pub fn prune(config: &HistoryConfig) -> IoResult<usize> {
+    prune_at(&history_path(), config, now())
+}
+
+fn prune_at(history_path: &Path, config: &HistoryConfig, now: u64) -> IoResult<usize> {
+    let oldest = oldest_kept(config, now);
+    jsonl::retain(history_path, |entries: Vec<HistoryEntry>| {
+        let kept = entries
+            .into_iter()
+            .filter(|entry| entry.time >= oldest)
+            .collect();
+        jsonl::newest(kept, config.max_entries)
+    })
+}
+
+pub fn clear() -> IoResult<()> {
+    match fs::remove_file(history_path()) {
+        Err(e) if e.kind() != IoErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+///
+/// "just now", "5m ago", "3h ago", "2d ago"
+///
+pub fn format_age(time: u64, now: u64) -> String {
+    let seconds = now.saturating_sub(time);
+    match seconds {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{}m ago", seconds / 60),
+        3600..=86399 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / SECONDS_PER_DAY),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = SECONDS_PER_DAY;
+
+    fn entry(time: u64, url: &str, profile: &str) -> HistoryEntry {
+        HistoryEntry {
+            time,
+            original_url: url.to_string(),
+            url: url.to_string(),
+            browser: "chrome".to_string(),
+            profile: Some(profile.to_string()),
+            routing: Routing::Automatic,
+            source_app: None,
+        }
+    }
+
+    #[test]
+    fn appends_and_reads_back_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let history_path = dir.path().join("Chrome Valet").join("history.jsonl");
+        assert_eq!(read_at(&history_path).unwrap(), []);
+        assert_eq!(last_at(&history_path).unwrap(), None);
+
+        let first = entry(1, "https://example.com/", "Default");
+        let second = entry(2, "https://jira.corp/browse/X-1", "Profile 1");
+        jsonl::append(&history_path, &first).unwrap();
+        jsonl::append(&history_path, &second).unwrap();
+
+        // a line that doesn't parse doesn't lose the rest
+        let mut contents = fs::read_to_string(&history_path).unwrap();
+        contents.insert_str(0, "not json\n");
+        fs::write(&history_path, contents).unwrap();
+
+        assert_eq!(read_at(&history_path).unwrap(), [first, second.clone()]);
+        assert_eq!(last_at(&history_path).unwrap(), Some(second));
+    }
+
+    #[test]
+    fn search_is_newest_first() {
+        let entries = [
+            entry(1, "https://jira.corp/browse/X-1", "Profile 1"),
+            entry(2, "https://example.com/", "Default"),
+            entry(3, "https://JIRA.corp/browse/X-2", "Profile 1"),
+        ];
+        let times =
+            |found: Vec<&HistoryEntry>| found.iter().map(|entry| entry.time).collect::<Vec<u64>>();
+        assert_eq!(times(search(&entries, "jira", 10)), [3, 1]);
+        assert_eq!(times(search(&entries, "jira", 1)), [3]);
+        assert_eq!(times(search(&entries, "default", 10)), [2]);
+        assert_eq!(times(search(&entries, "", 10)), [3, 2, 1]);
+    }
+
+    #[test]
+    fn prune_drops_old_and_extra_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let history_path = dir.path().join("history.jsonl");
+        let now = 100 * DAY;
+        for time in [
+            now - 40 * DAY,
+            now - 20 * DAY,
+            now - 2 * DAY,
+            now - DAY,
+            now,
+        ] {
+            jsonl::append(
+                &history_path,
+                &entry(time, "https://example.com/", "Default"),
+            )
+            .unwrap();
+        }
+
+        let config = HistoryConfig {
+            enabled: true,
+            max_entries: 3,
+            max_age_days: 30,
+        };
+        assert_eq!(prune_at(&history_path, &config, now).unwrap(), 2);
+        let times: Vec<u64> = read_at(&history_path)
+            .unwrap()
+            .iter()
+            .map(|entry| entry.time)
+            .collect();
+        assert_eq!(times, [now - 2 * DAY, now - DAY, now]);
+
+        assert_eq!(prune_at(&history_path, &config, now).unwrap(), 0);
+    }
+
+    #[test]
+    fn record_appends_until_the_file_is_well_past_max_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let history_path = dir.path().join("history.jsonl");
+        let now = 100 * DAY;
+        let config = HistoryConfig {
+            enabled: true,
+            max_entries: 2,
+            max_age_days: 30,
+        };
+        let times = || -> Vec<u64> {
+            read_at(&history_path)
+                .unwrap()
+                .iter()
+                .map(|entry| entry.time)
+                .collect()
+        };
+
+        // past both limits, but far under max_entries * ENTRY_BYTES
+        jsonl::append(
+            &history_path,
+            &entry(now - 40 * DAY, "https://example.com/", "Default"),
+        )
+        .unwrap();
+        for time in 0..4 {
+            record_at(
+                &history_path,
+                &entry(now + time, "https://example.com/", "Default"),
+                &config,
+                now,
+            )
+            .unwrap();
+        }
+        assert_eq!(times(), [now - 40 * DAY, now, now + 1, now + 2, now + 3]);
+
+        let long_url = format!(
+            "https://example.com/{}",
+            "a".repeat(2 * ENTRY_BYTES as usize)
+        );
+        record_at(
+            &history_path,
+            &entry(now + 4, &long_url, "Default"),
+            &config,
+            now,
+        )
+        .unwrap();
+        assert_eq!(times(), [now + 3, now + 4]);
+    }
+
+    #[test]
+    fn entries_from_before_original_url_still_parse() {
+        let entry: HistoryEntry =
+            serde_json::from_str(r#"{"time":1,"url":"https://example.com/","browser":"chrome"}"#)
+                .unwrap();
+        assert_eq!(entry.original_url, "");
+        assert_eq!(entry.url, "https://example.com/");
+    }
+}
@@ -0,0 +1,162 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind as IoErrorKind, Result as IoResult, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+
+///
+/// `<log>.lock`, held while a log is appended to or rewritten
+/// so a rewrite can't drop a line another instance appends while it runs
+///
+fn lock(log_path: &Path) -> IoResult<File> {
+    let mut lock_path = log_path.as_os_str().to_os_string();
+    lock_path.push(".lock");
+    let lock_file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(PathBuf::from(lock_path))?;
+    lock_file.lock()?;
+    Ok(lock_file)
+}
+
+///
+/// adds `value` as a line at the end of the log, existing lines are never rewritten here
+///
+pub fn append<T: Serialize>(log_path: &Path, value: &T) -> IoResult<()> {
+    if let Some(p) = log_path.parent() {
+        fs::create_dir_all(p)?
+    };
+
+    let mut line = serde_json::to_string(value)?;
+    line.push('\n');
+    let _lock = lock(log_path)?;
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)?
+        .write_all(line.as_bytes())
+}
+
+///
+/// every line, oldest first, lines that don't parse are skipped
+///
+pub fn read<T: DeserializeOwned>(log_path: &Path) -> IoResult<Vec<T>> {
+    match fs::read_to_string(log_path) {
+        Ok(contents) => Ok(contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect()),
+        Err(e) if e.kind() == IoErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// whether the log has grown past `max_bytes`, without reading it
+pub fn larger_than(log_path: &Path, max_bytes: u64) -> bool {
+    fs::metadata(log_path).is_ok_and(|metadata| metadata.len() > max_bytes)
+}
+
+///
+/// rewrites the log with the lines `keep` returns, oldest first, returns how many were dropped
+/// it's written to a temp file that then replaces the log, so a crash part way through leaves the old one
+///
+pub fn retain<T: Serialize + DeserializeOwned>(
+    log_path: &Path,
+    keep: impl FnOnce(Vec<T>) -> Vec<T>,
+) -> IoResult<usize> {
+    let _lock = lock(log_path)?;
+    let lines: Vec<T> = read(log_path)?;
+    let count = lines.len();
+    let kept = keep(lines);
+    let removed = count.saturating_sub(kept.len());
+    if removed == 0 {
+        return Ok(0);
+    }
+
+    let mut contents = String::new();
+    for line in kept {
+        contents += &serde_json::to_string(&line)?;
+        contents.push('\n');
+    }
+
+    let mut temp_file = NamedTempFile::new_in(log_path.parent().unwrap_or(Path::new(".")))?;
+    temp_file.write_all(contents.as_bytes())?;
+    temp_file.persist(log_path).map_err(|e| e.error)?;
+    Ok(removed)
+}
+
+///
+/// the newest `count` of `lines`
+///
+pub fn newest<T>(mut lines: Vec<T>, count: usize) -> Vec<T> {
+    let skip = lines.len().saturating_sub(count);
+    lines.drain(..skip);
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn appends_and_reads_back_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let log_path = dir.path().join("ChromeValet").join("log.jsonl");
+        assert!(read::<u32>(&log_path).unwrap().is_empty());
+        assert!(!larger_than(&log_path, 0));
+
+        append(&log_path, &1).unwrap();
+        append(&log_path, &2).unwrap();
+        // a line that doesn't parse doesn't lose the rest
+        let mut contents = fs::read_to_string(&log_path).unwrap();
+        contents.insert_str(0, "not json\n");
+        fs::write(&log_path, contents).unwrap();
+        append(&log_path, &3).unwrap();
+
+        assert_eq!(read::<u32>(&log_path).unwrap(), [1, 2, 3]);
+        assert!(larger_than(&log_path, 14));
+        assert!(!larger_than(&log_path, 15));
+    }
+
+    #[test]
+    fn retain_rewrites_only_when_lines_are_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let log_path = dir.path().join("log.jsonl");
+        assert_eq!(retain::<u32>(&log_path, |lines| lines).unwrap(), 0);
+        assert!(!log_path.exists());
+
+        for value in 1..=5 {
+            append(&log_path, &value).unwrap();
+        }
+        let modified = fs::metadata(&log_path).unwrap().modified().unwrap();
+        assert_eq!(
+            retain::<u32>(&log_path, |lines| newest(lines, 5)).unwrap(),
+            0
+        );
+        assert_eq!(
+            fs::metadata(&log_path).unwrap().modified().unwrap(),
+            modified
+        );
+
+        assert_eq!(
+            retain::<u32>(&log_path, |lines| newest(lines, 3)).unwrap(),
+            2
+        );
+        assert_eq!(read::<u32>(&log_path).unwrap(), [3, 4, 5]);
+
+        // an append after the rewrite goes to the new log
+        append(&log_path, &6).unwrap();
+        assert_eq!(read::<u32>(&log_path).unwrap(), [3, 4, 5, 6]);
+
+        // the log and its lock, no temp files left behind
+        let mut files: Vec<String> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["log.jsonl", "log.jsonl.lock"]);
+    }
+}
@@ -0,0 +1,140 @@
+use log::{debug, error, warn};
+#[cfg(windows)]
+use std::os::windows::process::CommandExt;
+use std::process::exit;
+use std::process::Command;
+use tracing::info_span;
+
+use crate::config::UserConfig;
+use crate::history;
+use crate::history::{HistoryEntry, Routing};
+use crate::metrics;
+use crate::metrics::Phase;
+use crate::registry_utils::Browser;
+use crate::usage::ProfileUsage;
+
+#[cfg(windows)]
+const DETACHED_PROCESS: u32 = 0x00000008;
+
+///
+/// a url to open, and the browser and profile to open it in
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct LaunchRequest {
+    pub browser: Browser,
+    pub url: String,
+    /// None opens the browser's last used profile
+    pub profile_directory: Option<String>,
+    pub routing: Routing,
+    /// the program the link was clicked in, for history
+    pub source_app: Option<String>,
+    /// false keeps chrome valet open, eg. shift-clicking a profile
+    pub exit_when_done: bool,
+}
+
+impl LaunchRequest {
+    ///
+    /// arguments passed to the browser executable
+    /// for now we assume all chromium browsers play nice with these arguments
+    ///
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(profile_directory) = &self.profile_directory {
+            args.push(format!("--profile-directory={profile_directory}"));
+        }
+
+        args.push("--single-argument".to_string());
+        args.push(self.url.clone());
+        args
+    }
+}
+
+///
+/// opens urls, the picker takes one of these so tests can see what it would have launched
+///
+pub trait Launcher {
+    fn launch(&self, config: &UserConfig, request: &LaunchRequest);
+}
+
+///
+/// spawns the browser, records the profile's usage and history, and exits if the request says so
+///
+pub struct ProcessLauncher;
+
+impl Launcher for ProcessLauncher {
+    fn launch(&self, config: &UserConfig, request: &LaunchRequest) {
+        let span = info_span!(
+            "launch",
+            browser = %request.browser,
+            profile = request.profile_directory.as_deref().unwrap_or_default()
+        );
+        span.in_scope(|| spawn_browser(config, request));
+
+        if request.exit_when_done {
+            metrics::finish();
+            exit(0);
+        }
+    }
+}
+
+fn spawn_browser(config: &UserConfig, request: &LaunchRequest) {
+    debug!("url: {}", request.url);
+    let mut browser_exe: String = String::default();
+    match config.resolve_browser_exe(&request.browser) {
+        Err(e) => error!("failed to get browser exe location: {:#}", e),
+        Ok(v) => browser_exe = v,
+    }
+
+    // todo: break the open commands by argument, keep them in order, replace the one with %1 with the url
+    let mut browser_command = Command::new(browser_exe);
+    #[cfg(windows)]
+    browser_command.creation_flags(DETACHED_PROCESS);
+    browser_command.args(request.args());
+
+    if let Some(profile_directory) = &request.profile_directory {
+        if let Err(e) = ProfileUsage::record_open(profile_directory) {
+            warn!("couldn't record profile usage: {}", e);
+        }
+    }
+
+    let browser_command_child_result = browser_command.spawn();
+
+    debug!("browser command: {:?}", browser_command);
+
+    match browser_command_child_result {
+        Ok(_) => metrics::mark(Phase::LaunchSpawned),
+        Err(e) => {
+            error!("Error excecuting command: {}", e);
+            return;
+        }
+    };
+
+    if config.history.enabled {
+        if let Err(e) = history::record(&HistoryEntry::from_request(request), &config.history) {
+            warn!("couldn't record history: {}", e);
+        }
+    }
+}
+
+///
+/// keeps every request instead of launching anything
+///
+#[cfg(test)]
+#[derive(Default, Clone)]
+pub struct RecordingLauncher {
+    pub requests: std::sync::Arc<std::sync::Mutex<Vec<LaunchRequest>>>,
+}
+
+#[cfg(test)]
+impl RecordingLauncher {
+    pub fn requests(&self) -> Vec<LaunchRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl Launcher for RecordingLauncher {
+    fn launch(&self, _config: &UserConfig, request: &LaunchRequest) {
+        self.requests.lock().unwrap().push(request.clone());
+    }
+}
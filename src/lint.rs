@@ -0,0 +1,544 @@
+use regex::Regex;
+use serde::Serialize;
+use std::fmt;
+
+use crate::config::UserConfig;
+use crate::domains;
+use crate::rules::{glob_matches, DomainMatch, RoutingRule, RuleLayer};
+use crate::source_app;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LintKind {
+    /// no host, domain, regex or source app, the rule never matches
+    Empty,
+    InvalidRegex,
+    /// a domain like "co.uk", which never matches
+    PublicSuffix,
+    InvalidSchedule,
+    /// a network condition with nothing set, or a range that doesn't parse
+    InvalidNetwork,
+    MissingProfile,
+    /// no profile and doesn't ask, usually a typo in the profile key
+    NoProfile,
+    /// same patterns and conditions as an earlier rule
+    Duplicate,
+    /// every link it matches is taken by an earlier rule
+    Shadowed,
+    /// wildcards matching some of the same hosts as an earlier rule, with a different profile
+    OverlappingWildcard,
+}
+
+///
+/// a problem with one rule, as printed by `rules lint`
+///
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct LintWarning {
+    pub kind: LintKind,
+    pub layer: RuleLayer,
+    /// the context the rule is in, for context rules
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    /// position in its file, from 1
+    pub index: usize,
+    pub message: String,
+}
+
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.layer, &self.context) {
+            (RuleLayer::Preferred, _) => write!(f, "{} #{}", self.layer, self.index)?,
+            (_, Some(context)) => write!(f, "{} \"{context}\" rule #{}", self.layer, self.index)?,
+            (_, None) => write!(f, "{} rule #{}", self.layer, self.index)?,
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+struct Located<'a> {
+    layer: RuleLayer,
+    index: usize,
+    rule: &'a RoutingRule,
+    /// rules that can't match anything are linted, but never shadow others
+    can_match: bool,
+}
+
+impl fmt::Display for Located<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} rule #{}", self.layer, self.index)
+    }
+}
+
+///
+/// checks rules in the order they're evaluated
+/// `profiles` are the browser's profile directories, None skips the missing profile check
+///
+pub fn lint<'a>(
+    rules: impl IntoIterator<Item = (RuleLayer, &'a RoutingRule)>,
+    profiles: Option<&[String]>,
+) -> Vec<LintWarning> {
+    let mut located: Vec<Located> = Vec::new();
+    let mut warnings = Vec::new();
+    for (layer, rule) in rules {
+        let index = located
+            .iter()
+            .filter(|earlier| earlier.layer == layer)
+            .count()
+            + 1;
+        let mut warn = |kind: LintKind, message: String| {
+            warnings.push(LintWarning {
+                kind,
+                layer,
+                context: None,
+                index,
+                message,
+            })
+        };
+
+        if !rule.has_pattern() {
+            warn(
+                LintKind::Empty,
+                "has no host, domain, regex or source app, it never matches".to_string(),
+            );
+        }
+
+        let mut can_match = rule.has_pattern();
+        if let Some(pattern) = &rule.regex {
+            if let Err(err) = Regex::new(pattern) {
+                warn(
+                    LintKind::InvalidRegex,
+                    format!("invalid regex \"{pattern}\": {err}"),
+                );
+                can_match = false;
+            }
+        }
+
+        if let Some(domain) = &rule.domain {
+            if domains::is_public_suffix(domain) {
+                warn(
+                    LintKind::PublicSuffix,
+                    format!("domain \"{domain}\" is a public suffix, it never matches"),
+                );
+                can_match = false;
+            }
+        }
+
+        if let Some(schedule) = &rule.schedule {
+            if let Err(err) = schedule.validate() {
+                warn(
+                    LintKind::InvalidSchedule,
+                    format!("invalid schedule: {err}"),
+                );
+                can_match = false;
+            }
+        }
+
+        if let Some(network) = &rule.network {
+            if let Err(err) = network.validate() {
+                warn(
+                    LintKind::InvalidNetwork,
+                    format!("invalid network condition: {err}"),
+                );
+                can_match = false;
+            }
+        }
+
+        if !rule.ask && rule.profile.is_empty() {
+            warn(
+                LintKind::NoProfile,
+                "has no profile, set one or ask = true to show the picker".to_string(),
+            );
+        } else if let Some(profiles) = profiles {
+            if !rule.ask && !profiles.contains(&rule.profile) {
+                warn(
+                    LintKind::MissingProfile,
+                    format!("opens links in missing profile \"{}\"", rule.profile),
+                );
+            }
+        }
+
+        let earlier_rules = located
+            .iter()
+            .filter(|earlier| can_match && earlier.can_match);
+        for earlier in earlier_rules {
+            if same_pattern(earlier.rule, rule) {
+                warn(LintKind::Duplicate, format!("same pattern as {earlier}"));
+                break;
+            }
+
+            if covers(earlier.rule, rule) {
+                warn(
+                    LintKind::Shadowed,
+                    format!("never used, {earlier} matches every link it does"),
+                );
+                break;
+            }
+
+            if let (Some(earlier_host), Some(host)) = (&earlier.rule.host, &rule.host) {
+                let wildcard = earlier_host.contains('*') || host.contains('*');
+                if wildcard
+                    && earlier.rule.profile != rule.profile
+                    && globs_overlap(
+                        &domains::normalize_host(earlier_host),
+                        &domains::normalize_host(host),
+                    )
+                {
+                    warn(
+                        LintKind::OverlappingWildcard,
+                        format!(
+                            "host \"{host}\" overlaps \"{earlier_host}\" from {earlier}, \
+                             links to both open in \"{}\"",
+                            earlier.rule.profile
+                        ),
+                    );
+                }
+            }
+        }
+
+        located.push(Located {
+            layer,
+            index,
+            rule,
+            can_match,
+        });
+    }
+
+    warnings
+}
+
+///
+/// the rules in `config` and prefs.json, each context's rules as they're checked while it's in use,
+/// and the [[preferred]] profiles
+///
+pub fn lint_config(
+    config: &UserConfig,
+    prefs_rules: &[RoutingRule],
+    profiles: Option<&[String]>,
+) -> Vec<LintWarning> {
+    let rules = || {
+        let config_rules = config.rules.iter().map(|rule| (RuleLayer::Config, rule));
+        let prefs_rules = prefs_rules.iter().map(|rule| (RuleLayer::Prefs, rule));
+        config_rules.chain(prefs_rules)
+    };
+    let mut warnings = lint(rules(), profiles);
+
+    // the other rules are only warned about once, without any context
+    for (name, context) in &config.contexts {
+        let context_rules = context.rules.iter().map(|rule| (RuleLayer::Context, rule));
+        let context_warnings = lint(context_rules.chain(rules()), profiles)
+            .into_iter()
+            .filter(|warning| warning.layer == RuleLayer::Context)
+            .map(|warning| LintWarning {
+                context: Some(name.clone()),
+                ..warning
+            });
+        warnings.extend(context_warnings);
+    }
+
+    for (position, scheduled) in config.preferred.iter().enumerate() {
+        let mut warn = |kind: LintKind, message: String| {
+            warnings.push(LintWarning {
+                kind,
+                layer: RuleLayer::Preferred,
+                context: None,
+                index: position + 1,
+                message,
+            });
+        };
+
+        if let Err(err) = scheduled.schedule.validate() {
+            warn(
+                LintKind::InvalidSchedule,
+                format!("invalid schedule: {err}"),
+            );
+        }
+        if let Some(profiles) = profiles {
+            if !profiles.contains(&scheduled.profile) {
+                warn(
+                    LintKind::MissingProfile,
+                    format!("missing profile \"{}\"", scheduled.profile),
+                );
+            }
+        }
+    }
+
+    warnings
+}
+
+fn same_pattern(a: &RoutingRule, b: &RoutingRule) -> bool {
+    let normalize = |host: &Option<String>| host.as_deref().map(domains::normalize_host);
+    let normalize_app = |app: &Option<String>| app.as_deref().map(source_app::normalize);
+    let same_scheme = match (&a.scheme, &b.scheme) {
+        (Some(a_scheme), Some(b_scheme)) => a_scheme.eq_ignore_ascii_case(b_scheme),
+        (a_scheme, b_scheme) => a_scheme == b_scheme,
+    };
+    normalize(&a.host) == normalize(&b.host)
+        && normalize(&a.domain) == normalize(&b.domain)
+        && (a.domain.is_none() || a.domain_match == b.domain_match)
+        && a.regex == b.regex
+        && normalize_app(&a.source_app) == normalize_app(&b.source_app)
+        && same_scheme
+        && a.port == b.port
+        && a.schedule == b.schedule
+        && a.network == b.network
+}
+
+/// whether every url `later` matches is also matched by `earlier`
+/// errs towards false, a missed warning is better than a wrong one
+fn covers(earlier: &RoutingRule, later: &RoutingRule) -> bool {
+    let regex_covers = earlier.regex.is_none() || earlier.regex == later.regex;
+    let scheme_covers = match (&earlier.scheme, &later.scheme) {
+        (None, _) => true,
+        (Some(earlier_scheme), Some(scheme)) => earlier_scheme.eq_ignore_ascii_case(scheme),
+        (Some(_), None) => false,
+    };
+    let port_covers = earlier.port.is_none() || earlier.port == later.port;
+    let source_app_covers = match (&earlier.source_app, &later.source_app) {
+        (None, _) => true,
+        // a * in the later pattern is only covered by a * in the earlier one, like hosts
+        (Some(earlier_app), Some(app)) => source_app::app_matches(earlier_app, app),
+        (Some(_), None) => false,
+    };
+    let schedule_covers = earlier.schedule.is_none() || earlier.schedule == later.schedule;
+    let network_covers = earlier.network.is_none() || earlier.network == later.network;
+    let domain_covers = match (&earlier.domain, &later.domain) {
+        (None, _) => true,
+        (Some(earlier_domain), Some(domain)) => {
+            let subdomains = earlier.domain_match == DomainMatch::Subdomains;
+            let same = domains::normalize_host(earlier_domain) == domains::normalize_host(domain);
+            (same && (subdomains || later.domain_match == DomainMatch::Exact))
+                || (subdomains && domains::domain_matches(earlier_domain, domain, true))
+        }
+        (Some(_), None) => false,
+    };
+    if !(regex_covers
+        && scheme_covers
+        && port_covers
+        && source_app_covers
+        && schedule_covers
+        && network_covers
+        && domain_covers)
+    {
+        return false;
+    }
+
+    match (&earlier.host, &later.host) {
+        (None, _) => true,
+        (Some(earlier_host), _) if earlier_host == "*" => true,
+        // a * in the later glob is only covered by a * in the earlier one, so it's compared as text
+        (Some(earlier_host), Some(host)) => glob_matches(
+            &domains::normalize_host(earlier_host),
+            &domains::normalize_host(host),
+        ),
+        (Some(_), None) => false,
+    }
+}
+
+/// whether some host matches both globs
+fn globs_overlap(a: &str, b: &str) -> bool {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    chars_overlap(&a, &b)
+}
+
+fn chars_overlap(a: &[char], b: &[char]) -> bool {
+    match (a.first(), b.first()) {
+        (None, None) => true,
+        (Some('*'), _) => chars_overlap(&a[1..], b) || (!b.is_empty() && chars_overlap(a, &b[1..])),
+        (_, Some('*')) => chars_overlap(a, &b[1..]) || (!a.is_empty() && chars_overlap(&a[1..], b)),
+        (Some(a_char), Some(b_char)) => a_char == b_char && chars_overlap(&a[1..], &b[1..]),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::NetworkCondition;
+
+    fn rule(host: Option<&str>, regex: Option<&str>, profile: &str) -> RoutingRule {
+        RoutingRule {
+            host: host.map(String::from),
+            regex: regex.map(String::from),
+            profile: profile.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn kinds(rules: &[RoutingRule], profiles: Option<&[String]>) -> Vec<(LintKind, usize)> {
+        lint(rules.iter().map(|rule| (RuleLayer::Config, rule)), profiles)
+            .into_iter()
+            .map(|warning| (warning.kind, warning.index))
+            .collect()
+    }
+
+    #[test]
+    fn broader_earlier_rule_shadows_later_one() {
+        let rules = [
+            rule(Some("*.example.com"), None, "Default"),
+            rule(Some("docs.example.com"), Some("/page"), "Profile 1"),
+            rule(Some("*.corp.example.com"), None, "Profile 1"),
+        ];
+        assert_eq!(
+            kinds(&rules, None),
+            [(LintKind::Shadowed, 2), (LintKind::Shadowed, 3)]
+        );
+    }
+
+    #[test]
+    fn narrower_earlier_rule_doesnt_shadow() {
+        let rules = [
+            rule(Some("docs.example.com"), None, "Profile 1"),
+            rule(Some("*.example.com"), None, "Profile 1"),
+        ];
+        assert!(kinds(&rules, None).is_empty());
+    }
+
+    #[test]
+    fn duplicates_invalid_regex_and_missing_profiles() {
+        let rules = [
+            rule(Some("Example.com"), None, "Default"),
+            rule(Some("example.com"), None, "Profile 1"),
+            rule(None, Some("(unclosed"), "Default"),
+            rule(None, None, "Profile 9"),
+        ];
+        let profiles = ["Default".to_string(), "Profile 1".to_string()];
+        assert_eq!(
+            kinds(&rules, Some(&profiles)),
+            [
+                (LintKind::Duplicate, 2),
+                (LintKind::InvalidRegex, 3),
+                (LintKind::Empty, 4),
+                (LintKind::MissingProfile, 4)
+            ]
+        );
+    }
+
+    #[test]
+    fn domain_rules() {
+        let domain = |domain: &str, domain_match: DomainMatch| RoutingRule {
+            domain: Some(domain.to_string()),
+            domain_match,
+            profile: "Default".to_string(),
+            ..Default::default()
+        };
+        let rules = [
+            domain("example.co.uk", DomainMatch::Subdomains),
+            domain("mail.example.co.uk", DomainMatch::Exact),
+            domain("co.uk", DomainMatch::Subdomains),
+            domain("other.example", DomainMatch::Exact),
+            domain("other.example", DomainMatch::Subdomains),
+        ];
+        assert_eq!(
+            kinds(&rules, None),
+            [(LintKind::Shadowed, 2), (LintKind::PublicSuffix, 3)]
+        );
+    }
+
+    #[test]
+    fn source_app_rules() {
+        let app = |source_app: &str, host: Option<&str>| RoutingRule {
+            source_app: Some(source_app.to_string()),
+            host: host.map(String::from),
+            profile: "Profile 1".to_string(),
+            ..Default::default()
+        };
+        let rules = [
+            app("slack", None),
+            app("Slack.exe", Some("example.com")),
+            app("discord", None),
+            app("discord.exe", None),
+        ];
+        assert_eq!(
+            kinds(&rules, None),
+            [(LintKind::Shadowed, 2), (LintKind::Duplicate, 4)]
+        );
+    }
+
+    #[test]
+    fn network_conditions_and_asking() {
+        let rules = [
+            RoutingRule {
+                host: Some("intranet.corp".to_string()),
+                network: Some(NetworkCondition::default()),
+                profile: "Default".to_string(),
+                ..Default::default()
+            },
+            RoutingRule {
+                host: Some("intranet.corp".to_string()),
+                network: Some(NetworkCondition {
+                    cidr: Some("10.20.0.0/16".to_string()),
+                    ..Default::default()
+                }),
+                profile: "Default".to_string(),
+                ..Default::default()
+            },
+            RoutingRule {
+                host: Some("intranet.corp".to_string()),
+                ask: true,
+                ..Default::default()
+            },
+            rule(Some("wiki.corp"), None, ""),
+        ];
+        let profiles = ["Default".to_string()];
+        assert_eq!(
+            kinds(&rules, Some(&profiles)),
+            [(LintKind::InvalidNetwork, 1), (LintKind::NoProfile, 4)]
+        );
+        assert_eq!(
+            kinds(&rules, None),
+            [(LintKind::InvalidNetwork, 1), (LintKind::NoProfile, 4)]
+        );
+    }
+
+    #[test]
+    fn overlapping_wildcards_with_different_profiles() {
+        let rules = [
+            rule(Some("git*.corp"), None, "Default"),
+            rule(Some("*lab.corp"), None, "Profile 1"),
+            rule(Some("*.other"), None, "Profile 1"),
+        ];
+        assert_eq!(kinds(&rules, None), [(LintKind::OverlappingWildcard, 2)]);
+        assert!(globs_overlap("git*.corp", "*lab.corp"));
+        assert!(!globs_overlap("*.corp", "*.other"));
+    }
+
+    #[test]
+    fn every_context_and_preferred_profile() {
+        let config: UserConfig = toml::from_str(
+            r#"
+            [[rules]]
+            host = "github.com"
+            profile = "Default"
+
+            [[preferred]]
+            profile = "Gone"
+            schedule = { days = ["someday"] }
+
+            [contexts.Home]
+            rules = [{ host = "wiki.home" }]
+
+            [contexts.Work]
+            rules = [
+                { host = "*", profile = "Profile 1" },
+                { host = "jira.corp", profile = "Profile 1" },
+            ]
+            "#,
+        )
+        .unwrap();
+        let profiles = ["Default".to_string(), "Profile 1".to_string()];
+        let warnings: Vec<String> = lint_config(&config, &[], Some(&profiles))
+            .iter()
+            .map(|warning| warning.to_string())
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                "chromevalet.toml context \"Home\" rule #1: has no profile, set one or ask = true to show the picker",
+                "chromevalet.toml context \"Work\" rule #2: never used, chromevalet.toml context rule #1 matches every link it does",
+                "chromevalet.toml preferred #1: invalid schedule: unknown day \"someday\"",
+                "chromevalet.toml preferred #1: missing profile \"Gone\"",
+            ]
+        );
+    }
+}
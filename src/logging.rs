@@ -0,0 +1,98 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::io;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::EnvFilter;
+
+use crate::config::LoggingConfig;
+use crate::paths::app_data_dir;
+
+const LOG_FILE_PREFIX: &str = "chromevalet";
+const LOG_FILE_SUFFIX: &str = "log";
+const DEFAULT_FILTER: &str = "info";
+
+/// a week of daily logs
+const DEFAULT_MAX_LOG_FILES: usize = 7;
+
+#[derive(clap::ValueEnum, Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+///
+/// logs to stderr and to daily chromevalet.<date>.log files in the app data dir
+/// `filter` is a level or per module directives, eg. "info,chrome_valet::rules=trace"
+/// spans are logged as they close, with how long they took
+///
+pub fn init(filter: Option<&str>, format: Option<LogFormat>, config: &LoggingConfig) {
+    let filter = filter
+        .or(config.filter.as_deref())
+        .unwrap_or(DEFAULT_FILTER);
+    let format = format.or(config.format).unwrap_or_default();
+
+    let (env_filter, filter_error) = match EnvFilter::try_new(filter) {
+        Ok(env_filter) => (env_filter, None),
+        Err(e) => (EnvFilter::new(DEFAULT_FILTER), Some(e)),
+    };
+
+    let file_appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_FILE_PREFIX)
+        .filename_suffix(LOG_FILE_SUFFIX)
+        .max_log_files(config.max_files.unwrap_or(DEFAULT_MAX_LOG_FILES))
+        .build(app_data_dir());
+    let (file_appender, appender_error) = match file_appender {
+        Ok(file_appender) => (Some(file_appender), None),
+        Err(e) => (None, Some(e)),
+    };
+
+    let (text_layer, json_layer) = match (file_appender, format) {
+        (Some(file_appender), LogFormat::Text) => (
+            Some(
+                fmt::layer()
+                    .with_ansi(false)
+                    .with_span_events(FmtSpan::CLOSE)
+                    .with_writer(file_appender),
+            ),
+            None,
+        ),
+        (Some(file_appender), LogFormat::Json) => (
+            None,
+            Some(
+                fmt::layer()
+                    .json()
+                    .with_span_events(FmtSpan::CLOSE)
+                    .with_writer(file_appender),
+            ),
+        ),
+        (None, _) => (None, None),
+    };
+
+    // stderr, so commands' output on stdout stays clean, eg. --json
+    let console_layer = fmt::layer().with_writer(io::stderr);
+
+    // also picks up the log crate's macros
+    let result = tracing_subscriber::registry()
+        .with(env_filter)
+        .with(text_layer)
+        .with(json_layer)
+        .with(console_layer)
+        .try_init();
+    if let Err(e) = result {
+        warn!("logging already set up: {e}");
+    }
+
+    if let Some(e) = filter_error {
+        warn!("invalid log filter \"{filter}\", using \"{DEFAULT_FILTER}\": {e}");
+    }
+
+    if let Some(e) = appender_error {
+        warn!("couldn't log to {}: {e}", app_data_dir().display());
+    }
+}
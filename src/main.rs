@@ -1,38 +1,70 @@
 #![windows_subsystem = "windows"]
 
-// #[macro_use] extern crate quick_error;
+#[macro_use]
+extern crate quick_error;
 
 mod chrome_interface;
+mod config;
+mod contexts;
+mod crash;
 mod custom_frame;
+mod doctor;
+mod domains;
+mod explain;
+mod file_watcher;
+mod history;
+mod jsonl;
+mod launcher;
+mod lint;
+mod logging;
+mod metrics;
+mod network;
+mod paths;
 mod registry_utils;
-
-use clap::Parser;
+mod routing;
+mod rules;
+mod schedule;
+mod source_app;
+mod suggestions;
+mod usage;
+
+use clap::{Parser, Subcommand};
 use device_query::{DeviceQuery, DeviceState, Keycode};
 use eframe::egui;
 use eframe::egui::load::SizedTexture;
-use log::LevelFilter;
 use log::{debug, error, trace, warn};
-use std::io;
+use std::env;
+#[cfg(windows)]
 use std::os::windows::process::CommandExt;
-use std::panic;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::process::Command;
-use std::str;
-use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::Instant;
-
-use chrome_interface::{ChromeInterface, ChromeProfileEntry};
+use tracing::{info_span, Instrument};
+
+use chrome_interface::{
+    ChromeInterface, ChromeProfileEntry, ProfileDisplaySettings, ProfileSortMode,
+};
+use config::{ConfigFormat, UserConfig};
+use file_watcher::FileWatcher;
+use history::{HistoryEntry, Routing};
+use launcher::{LaunchRequest, Launcher, ProcessLauncher};
+use logging::LogFormat;
+use metrics::Phase;
+use network::{NetworkSnapshot, SystemNetwork};
 use registry_utils::Browser;
+use routing::Decision;
+use rules::{Link, RoutingRule};
+use suggestions::RuleSuggestion;
 
+#[cfg(windows)]
 const DETACHED_PROCESS: u32 = 0x00000008;
 
-fn soft_panic(url: &Option<String>) {
-    if url.is_some() {
-        open_url_in_chrome(&Browser::Chrome, &url.as_ref().unwrap(), None, true);
-    }
-}
+/// exit code when the picker is needed but --no-picker was passed
+const NO_PICKER_EXIT_CODE: i32 = 2;
 
 #[derive(Parser, Debug)]
 #[command(version, about)]
@@ -41,14 +73,34 @@ struct Args {
     #[arg(long)]
     url: Option<String>,
 
-    /// Log level (error, warn, info, debug, trace)
-    #[arg(long, default_value = "info")]
+    /// Log level (error, warn, info, debug, trace), or per module filters like "info,chrome_valet::rules=trace"
+    #[arg(long)]
     log_level: Option<String>,
 
+    /// Format of the log files, defaults to [logging] format in chromevalet.toml, then text
+    #[arg(long, value_enum)]
+    log_format: Option<LogFormat>,
+
     /// force ui to open
     #[arg(long, default_value = "false")]
     force_ui: bool,
 
+    /// Program the link was clicked in, instead of looking for it in the process tree
+    #[arg(long, value_name = "NAME")]
+    source_app: Option<String>,
+
+    /// Exit instead of showing the picker, for scripts and tests
+    #[arg(long, default_value = "false")]
+    no_picker: bool,
+
+    /// Directory used in place of %LOCALAPPDATA%, also set by CHROME_VALET_HOME
+    #[arg(long, global = true)]
+    data_root: Option<PathBuf>,
+
+    /// Keep prefs, config and logs next to the executable
+    #[arg(long, global = true, default_value = "false")]
+    portable: bool,
+
     #[cfg(debug_assertions)]
     #[arg(
         long,
@@ -56,21 +108,612 @@ struct Args {
         help = "disable the warning if chrome valet is not the default browser"
     )]
     disable_default_browser_warning: bool,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+impl Args {
+    /// flags a registered copy passes along with each link, so it finds the same files
+    fn handler_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(data_root) = &self.data_root {
+            let data_root = std::path::absolute(data_root).unwrap_or(data_root.clone());
+            args.push("--data-root".to_string());
+            args.push(data_root.to_string_lossy().to_string());
+        }
+        if self.portable {
+            args.push("--portable".to_string());
+        }
+        args
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Inspect chromevalet.toml and prefs.json
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+
+    /// Switch between the contexts in chromevalet.toml, eg. "Work" and "Personal"
+    Context {
+        #[command(subcommand)]
+        command: ContextCommands,
+    },
+
+    /// Check the installation and print what's wrong
+    Doctor {
+        /// print the report as json, for support tickets
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show how a link would be routed, without opening it
+    Explain {
+        url: String,
+
+        /// route it as if it was clicked in this program, eg. "slack"
+        #[arg(long, value_name = "NAME")]
+        source_app: Option<String>,
+
+        /// print the explanation as json
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Register as a browser for the current user, without the installer
+    Register {
+        /// executable links are sent to, defaults to this one
+        #[arg(long)]
+        exe: Option<PathBuf>,
+    },
+
+    /// Undo `register`
+    Unregister {
+        /// unregister even if links go to another copy of chrome valet, eg. the installed one
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// List links chrome valet has opened, newest first
+    History {
+        /// only entries whose url, profile or source app contains this
+        #[arg(long)]
+        search: Option<String>,
+
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+
+        /// print entries as json lines
+        #[arg(long)]
+        json: bool,
+
+        #[command(subcommand)]
+        command: Option<HistoryCommands>,
+    },
+
+    /// Open the picker with the last link, to send it to a different profile
+    Reroute,
+
+    /// Work with routing rules
+    Rules {
+        #[command(subcommand)]
+        command: RulesCommands,
+    },
+
+    /// Summarise timings recorded in metrics.jsonl
+    Stats {
+        #[command(subcommand)]
+        command: StatsCommands,
+    },
 }
 
-static mut PANIC_URL: [u8; 2048] = [0; 2048];
+#[derive(Subcommand, Debug)]
+enum ContextCommands {
+    /// List the contexts, marking the one in use
+    List,
 
-fn set_log_level(level: LevelFilter) {
-    simple_logging::log_to(io::stdout(), level);
-    match simple_logging::log_to_file(
-        chrome_interface::app_data_dir().join("chromevalet.log"),
-        level,
-    ) {
-        Err(err) => error!("couldn't log to chromevalet.log: {err}"),
-        _ => (),
+    /// Route links with a context's rules and profile
+    Use {
+        name: String,
+
+        /// only for this long, eg. "1h" or "30m", then back to the context before
+        #[arg(long = "for", value_name = "DURATION")]
+        duration: Option<String>,
+    },
+
+    /// End a timed context early, or stop using contexts
+    Off,
+}
+
+#[derive(Subcommand, Debug)]
+enum HistoryCommands {
+    /// Drop entries past the [history] limits in chromevalet.toml
+    Prune,
+
+    /// Delete all history
+    Clear,
+}
+
+#[derive(Subcommand, Debug)]
+enum RulesCommands {
+    /// Check the rules for mistakes, exits with 1 if there are any
+    Lint {
+        /// a chromevalet.toml to check instead of this user's rules, eg. a team's rule file
+        file: Option<PathBuf>,
+
+        /// print warnings as json lines
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Suggest rules for hosts that keep being opened in the same profile by hand
+    Suggest {
+        /// save the rule suggested for this host to prefs.json
+        #[arg(long, value_name = "HOST")]
+        accept: Option<String>,
+
+        /// stop suggesting a rule for this host
+        #[arg(long, value_name = "HOST")]
+        dismiss: Option<String>,
+
+        /// print suggestions as json lines
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum StatsCommands {
+    /// p50 and p95 of each startup phase
+    Startup {
+        /// how many of the most recent runs to include
+        #[arg(long, default_value_t = 500)]
+        last: usize,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommands {
+    /// Print chromevalet.toml as it was read, json also includes the state in prefs.json
+    Export {
+        #[arg(long, value_enum, default_value_t = ConfigFormat::Toml)]
+        format: ConfigFormat,
+    },
+}
+
+///
+/// runs a cli subcommand, returns the process exit code
+///
+fn run_command(args: &Args, command: &Commands, chrome: &mut ChromeInterface) -> i32 {
+    match command {
+        Commands::Config {
+            command: ConfigCommands::Export { format },
+        } => match UserConfig::read().and_then(|config| config.export(chrome.prefs(), *format)) {
+            Ok(exported) => {
+                println!("{exported}");
+                0
+            }
+            Err(err) => {
+                eprintln!("couldn't export config: {err:#}");
+                1
+            }
+        },
+        Commands::Doctor { json } => {
+            let report = doctor::run(chrome);
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&report).unwrap());
+            } else {
+                print!("{}", report.to_text());
+            }
+
+            if report.has_failures() {
+                1
+            } else {
+                0
+            }
+        }
+        Commands::Explain {
+            url,
+            source_app,
+            json,
+        } => {
+            let explanation = explain::explain(chrome, url, source_app.as_deref(), &SystemNetwork);
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&explanation).unwrap());
+            } else {
+                print!("{}", explanation.to_text());
+            }
+            0
+        }
+        Commands::Register { exe } => {
+            let exe = match exe {
+                Some(exe) => exe.clone(),
+                None => env::current_exe().unwrap(),
+            };
+
+            match register_url_handler(&exe.to_string_lossy(), &args.handler_args()) {
+                Ok(()) => {
+                    println!(
+                        "registered {}, now pick Chrome Valet as the default browser",
+                        exe.display()
+                    );
+                    0
+                }
+                Err(err) => {
+                    eprintln!("couldn't register: {err:#}");
+                    1
+                }
+            }
+        }
+        Commands::History {
+            command: Some(HistoryCommands::Prune),
+            ..
+        } => match history::prune(&chrome.config().history) {
+            Ok(removed) => {
+                println!("removed {removed} entries");
+                0
+            }
+            Err(err) => {
+                eprintln!("couldn't prune history: {err}");
+                1
+            }
+        },
+        Commands::History {
+            command: Some(HistoryCommands::Clear),
+            ..
+        } => match history::clear() {
+            Ok(()) => {
+                println!("history cleared");
+                0
+            }
+            Err(err) => {
+                eprintln!("couldn't clear history: {err}");
+                1
+            }
+        },
+        Commands::History {
+            search,
+            limit,
+            json,
+            command: None,
+        } => match history::read() {
+            Ok(entries) => {
+                let now = history::now();
+                let search = search.as_deref().unwrap_or_default();
+                for entry in history::search(&entries, search, *limit) {
+                    if *json {
+                        println!("{}", serde_json::to_string(entry).unwrap());
+                    } else {
+                        println!(
+                            "{:>9}  {:<16} {:<7} {:<9} {}",
+                            history::format_age(entry.time, now),
+                            entry.profile.as_deref().unwrap_or("-"),
+                            entry.browser,
+                            format!("{:?}", entry.routing).to_lowercase(),
+                            entry.url
+                        );
+                    }
+                }
+                0
+            }
+            Err(err) => {
+                eprintln!("couldn't read {}: {err}", history::history_path().display());
+                1
+            }
+        },
+        Commands::Context {
+            command: ContextCommands::List,
+        } => {
+            let current = chrome.current_context().map(|(name, _)| name.to_string());
+            for (name, context) in &chrome.config().contexts {
+                let marker = if current.as_ref() == Some(name) {
+                    "*"
+                } else {
+                    " "
+                };
+                let profile = context.profile.as_deref().unwrap_or("no fallback profile");
+                println!(
+                    "{marker} {name:<20} {profile:<16} {} rules",
+                    context.rules.len()
+                );
+            }
+
+            if chrome.config().contexts.is_empty() {
+                println!(
+                    "no contexts, add them to {} as [contexts.<name>]",
+                    UserConfig::config_path().display()
+                );
+            } else if let Some(context_override) = chrome.context_override() {
+                println!(
+                    "\n{} until {}",
+                    context_override.context,
+                    context_override.until_local()
+                );
+            }
+            0
+        }
+        Commands::Context {
+            command: ContextCommands::Use { name, duration },
+        } => {
+            let Some(name) = chrome
+                .config()
+                .contexts
+                .keys()
+                .find(|context| context.eq_ignore_ascii_case(name))
+                .cloned()
+            else {
+                eprintln!(
+                    "no context named \"{name}\" in {}",
+                    UserConfig::config_path().display()
+                );
+                return 1;
+            };
+
+            let message = match duration {
+                Some(duration) => {
+                    let until = contexts::parse_duration(duration).and_then(|seconds| {
+                        history::now()
+                            .checked_add(seconds)
+                            .ok_or_else(|| "duration too long".to_string())
+                    });
+                    let until = match until {
+                        Ok(until) => until,
+                        Err(err) => {
+                            eprintln!("{err}");
+                            return 1;
+                        }
+                    };
+                    chrome.prefs_mut().override_context(name.clone(), until);
+                    let until = chrome
+                        .context_override()
+                        .map(|context_override| context_override.until_local());
+                    format!("using {name} until {}", until.unwrap_or_default())
+                }
+                None => {
+                    chrome.prefs_mut().use_context(Some(name.clone()));
+                    format!("using {name}")
+                }
+            };
+
+            match chrome.write_prefs() {
+                Ok(()) => {
+                    println!("{message}");
+                    0
+                }
+                Err(err) => {
+                    eprintln!("couldn't write prefs: {err}");
+                    1
+                }
+            }
+        }
+        Commands::Context {
+            command: ContextCommands::Off,
+        } => {
+            let message = if chrome.context_override().is_some() {
+                chrome.prefs_mut().context_override = None;
+                match chrome.current_context() {
+                    Some((name, _)) => format!("back to {name}"),
+                    None => "not using a context".to_string(),
+                }
+            } else {
+                chrome.prefs_mut().use_context(None);
+                "not using a context".to_string()
+            };
+
+            match chrome.write_prefs() {
+                Ok(()) => {
+                    println!("{message}");
+                    0
+                }
+                Err(err) => {
+                    eprintln!("couldn't write prefs: {err}");
+                    1
+                }
+            }
+        }
+        Commands::Reroute => unreachable!("reroute opens the picker, main handles it"),
+        Commands::Rules {
+            command: RulesCommands::Lint { file, json },
+        } => {
+            let warnings = match file {
+                Some(file) => match UserConfig::read_file(file) {
+                    // a shared file has no prefs or profiles to check against
+                    Ok(config) => lint::lint_config(&config, &[], None),
+                    Err(err) => {
+                        eprintln!("{err:#}");
+                        return 1;
+                    }
+                },
+                None => {
+                    let profiles: Option<Vec<String>> =
+                        chrome.populate_profile_entries().ok().map(|_| {
+                            chrome
+                                .profile_entries
+                                .iter()
+                                .map(|entry| entry.profile_directory.clone())
+                                .collect()
+                        });
+                    chrome.lint_rules(profiles.as_deref())
+                }
+            };
+
+            for warning in &warnings {
+                if *json {
+                    println!("{}", serde_json::to_string(warning).unwrap());
+                } else {
+                    println!("{warning}");
+                }
+            }
+
+            if warnings.is_empty() {
+                if !*json {
+                    println!("no problems found");
+                }
+                0
+            } else {
+                1
+            }
+        }
+        Commands::Rules {
+            command:
+                RulesCommands::Suggest {
+                    accept,
+                    dismiss,
+                    json,
+                },
+        } => {
+            let history = match history::read() {
+                Ok(history) => history,
+                Err(err) => {
+                    eprintln!("couldn't read {}: {err}", history::history_path().display());
+                    return 1;
+                }
+            };
+            let suggestions = chrome.rule_suggestions(&history);
+
+            if let Some(host) = accept.as_ref().or(dismiss.as_ref()) {
+                let host = host.to_lowercase();
+                let Some(suggestion) = suggestions
+                    .iter()
+                    .find(|suggestion| suggestion.host == host)
+                else {
+                    eprintln!("no rule is suggested for {host}");
+                    return 1;
+                };
+
+                if accept.is_some() {
+                    chrome.prefs_mut().remember_rule(suggestion.rule());
+                } else {
+                    chrome.prefs_mut().dismissed_suggestions.push(host.clone());
+                }
+
+                return match chrome.write_prefs() {
+                    Ok(()) if accept.is_some() => {
+                        println!("{host} now opens in {}", suggestion.profile);
+                        0
+                    }
+                    Ok(()) => {
+                        println!("won't suggest a rule for {host} again");
+                        0
+                    }
+                    Err(err) => {
+                        eprintln!("couldn't write prefs: {err}");
+                        1
+                    }
+                };
+            }
+
+            for suggestion in &suggestions {
+                if *json {
+                    println!("{}", serde_json::to_string(suggestion).unwrap());
+                } else {
+                    println!(
+                        "{:<30} {:<16} {:>3} of {:<3} {:>4.0}%",
+                        suggestion.host,
+                        suggestion.profile,
+                        suggestion.count,
+                        suggestion.total,
+                        suggestion.confidence() * 100.0
+                    );
+                }
+            }
+
+            if !*json && !suggestions.is_empty() {
+                println!("accept one with `rules suggest --accept <host>`");
+            }
+            0
+        }
+        Commands::Stats {
+            command: StatsCommands::Startup { last },
+        } => match metrics::read_records(*last) {
+            Ok(records) => {
+                print!("{}", metrics::startup_summary(&records));
+                0
+            }
+            Err(err) => {
+                eprintln!("couldn't read {}: {err}", metrics::metrics_path().display());
+                1
+            }
+        },
+        Commands::Unregister { force } => {
+            let exe = env::current_exe().unwrap();
+            if let Some(command) = registered_command() {
+                let registered_exe = registry_utils::command_exe(&command);
+                if !force && !same_exe(Path::new(registered_exe), &exe) {
+                    eprintln!(
+                        "links go to {registered_exe}, not {}, uninstall that copy or pass --force",
+                        exe.display()
+                    );
+                    return 1;
+                }
+            }
+
+            match unregister_url_handler() {
+                Ok(()) => {
+                    println!("unregistered");
+                    0
+                }
+                Err(err) => {
+                    eprintln!("couldn't unregister: {err:#}");
+                    1
+                }
+            }
+        }
+    }
+}
+
+/// windows paths aren't case sensitive
+fn same_exe(a: &Path, b: &Path) -> bool {
+    if cfg!(windows) {
+        a.to_string_lossy()
+            .eq_ignore_ascii_case(&b.to_string_lossy())
+    } else {
+        a == b
     }
 }
 
+#[cfg(windows)]
+fn register_url_handler(exe: &str, args: &[String]) -> Result<(), anyhow::Error> {
+    Ok(registry_utils::register_url_handler(
+        &mut registry_utils::SystemRegistry,
+        exe,
+        args,
+    )?)
+}
+
+#[cfg(windows)]
+fn registered_command() -> Option<String> {
+    registry_utils::get_url_handler_command().ok()
+}
+
+#[cfg(windows)]
+fn unregister_url_handler() -> Result<(), anyhow::Error> {
+    Ok(registry_utils::unregister_url_handler(
+        &mut registry_utils::SystemRegistry,
+    )?)
+}
+
+#[cfg(target_os = "linux")]
+fn register_url_handler(exe: &str, args: &[String]) -> Result<(), anyhow::Error> {
+    registry_utils::register_desktop_entry(&registry_utils::applications_dir(), exe, args)
+}
+
+#[cfg(target_os = "linux")]
+fn registered_command() -> Option<String> {
+    registry_utils::get_desktop_entry_command(&registry_utils::applications_dir())
+}
+
+#[cfg(target_os = "linux")]
+fn unregister_url_handler() -> Result<(), anyhow::Error> {
+    registry_utils::unregister_desktop_entry(
+        &registry_utils::applications_dir(),
+        &registry_utils::mimeapps_list(),
+    )
+}
+
 #[tokio::main]
 async fn main() {
     // lets us print to the console despite using windows subsystem (ie, process doesn't spawn console)
@@ -87,36 +730,76 @@ async fn main() {
     let main_begin_time: Instant = Instant::now();
 
     let args = Args::parse();
-    set_log_level(LevelFilter::Info);
-    if let Some(log_level) = &args.log_level {
-        match LevelFilter::from_str(log_level.as_str()) {
-            Ok(x) => {
-                set_log_level(x);
-            }
-            Err(x) => warn!("failed to set log level! logging off: {:?}", x),
-        }
+    paths::init(args.data_root.clone(), args.portable);
+    // ChromeInterface reports a bad chromevalet.toml once logging's up
+    let logging_config = UserConfig::read().unwrap_or_default().logging;
+    logging::init(args.log_level.as_deref(), args.log_format, &logging_config);
+    if let Some(reason) = &paths::get().fallback_reason {
+        warn!("{reason}");
     }
 
     debug!("args: {:?}", args);
 
+    // register minimum nice behaviour for panics, just open the damn browser
+    // plain chrome until prefs are read, then the preferred profile in the default browser
+    crash::install_panic_hook();
     if let Some(url) = &args.url {
-        // register minimum nice behaviour for panics, just open the damn browser
-        unsafe {
-            PANIC_URL[0..url.len()].copy_from_slice(url.as_bytes());
+        let request = LaunchRequest {
+            browser: Browser::Chrome,
+            url: url.clone(),
+            profile_directory: None,
+            routing: Routing::Fallback,
+            source_app: args.source_app.clone(),
+            exit_when_done: false,
         };
-        panic::set_hook(Box::new(|_| {
-            unsafe {
-                // there's probably a less hairy way of doing this, but I'm not rust ninja enough yet
-                let mut url_str = str::from_utf8(&PANIC_URL).unwrap();
-                url_str = &url_str[0..PANIC_URL.into_iter().position(|r| r == 0).unwrap()];
-                open_url_in_chrome(&Browser::Chrome, &String::from(url_str), None, false);
-            }
-        }));
+        crash::set_fallback(request, UserConfig::default());
     }
 
-    let mut chrome = ChromeInterface::new();
-    if let Err(e) = chrome.read_prefs() {
-        warn!("couldn't read prefs: {}", e);
+    let mut chrome = {
+        let _span = info_span!("prefs_read").entered();
+        let mut chrome = ChromeInterface::new();
+        if let Err(e) = chrome.read_prefs() {
+            warn!("couldn't read prefs: {}", e);
+        }
+        chrome
+    };
+
+    // reroute shows the picker for the last link, the other commands exit here
+    let rerouted = match &args.command {
+        Some(Commands::Reroute) => match history::last() {
+            Ok(Some(entry)) => Some(entry),
+            Ok(None) => {
+                eprintln!("no links in history to reroute");
+                exit(1);
+            }
+            Err(err) => {
+                eprintln!("couldn't read {}: {err}", history::history_path().display());
+                exit(1);
+            }
+        },
+        Some(command) => exit(run_command(&args, command, &mut chrome)),
+        None => None,
+    };
+
+    metrics::begin(main_begin_time);
+
+    let url = rerouted
+        .as_ref()
+        .map(|entry| &entry.url)
+        .or(args.url.as_ref())
+        .cloned();
+
+    // rerouted links keep the program they were first clicked in
+    let source_app = match &rerouted {
+        Some(entry) => entry.source_app.clone(),
+        None if url.is_some() => args
+            .source_app
+            .clone()
+            .or_else(|| info_span!("source_app").in_scope(source_app::detect)),
+        None => None,
+    };
+    if let Some(source_app) = &source_app {
+        debug!("opened from {source_app}");
     }
 
     // if ctrl pressed or no preferred profile
@@ -124,34 +807,110 @@ async fn main() {
     // else
     //  open in preferred profile
 
-    let device_state = DeviceState::new();
-    let keys: Vec<Keycode> = device_state.get_keys();
+    // reading the keyboard needs a display, and alt only matters if the picker can be shown
+    let keys: Vec<Keycode> = if args.no_picker {
+        Vec::new()
+    } else {
+        DeviceState::new().get_keys()
+    };
     let preferred_profile = chrome.prefs().get_preferred_profile();
-    let default_browser = chrome.get_default_browser();
-    if (!args.force_ui && !keys.contains(&Keycode::LAlt)) && args.url.is_some() {
-        let preferred_profile = if !preferred_profile.is_empty() {
-            Some(&preferred_profile)
-        } else {
-            None
+    let default_browser = info_span!("registry_lookup").in_scope(|| chrome.get_default_browser());
+
+    // profiles are needed to check the fast path isn't sending the url to a deleted profile
+    let profiles_result =
+        info_span!("profile_parse").in_scope(|| chrome.populate_profile_entries());
+    if let Err(err) = &profiles_result {
+        error!("couldn't get chrome profile(s): {}", err);
+    }
+    let profiles_known = profiles_result.is_ok();
+
+    // prefs written by older versions don't know the preferred profile's account yet
+    if chrome.has_profile(&preferred_profile)
+        && chrome.prefs().preferred_profile_user_name.is_empty()
+    {
+        chrome.remember_preferred_profile_account();
+        if !chrome.prefs().preferred_profile_user_name.is_empty() {
+            if let Err(e) = chrome.write_prefs() {
+                error!("couldn't write prefs: {}", e);
+            }
+        }
+    }
+
+    if let Some(url) = &url {
+        let profile_directory = Some(preferred_profile.clone())
+            .filter(|profile| !profile.is_empty())
+            .filter(|profile| !profiles_known || chrome.has_profile(profile));
+        let request = LaunchRequest {
+            browser: default_browser,
+            url: url.clone(),
+            profile_directory,
+            routing: Routing::Fallback,
+            source_app: source_app.clone(),
+            exit_when_done: false,
         };
-        open_url_in_chrome(
-            &default_browser,
-            &args.url.as_ref().unwrap(),
-            preferred_profile,
-            true,
-        );
+        crash::set_fallback(request, chrome.config().clone());
     }
 
-    if let Err(err) = chrome.populate_profile_entries() {
-        error!("couldn't get chrome profile(s): {}", err);
-        soft_panic(&args.url);
+    let force_ui = args.force_ui || rerouted.is_some();
+    let routed_url = url
+        .as_ref()
+        .filter(|_| !force_ui && !keys.contains(&Keycode::LAlt));
+    if let Some(url) = routed_url {
+        // reading the network is only worth it if a rule will look at it
+        let network = chrome
+            .needs_network()
+            .then(|| info_span!("network_read").in_scope(|| NetworkSnapshot::read(&SystemNetwork)));
+        let link = Link {
+            url,
+            source_app: source_app.as_deref(),
+            network: network.as_ref(),
+        };
+        match routing::decide(&chrome, &link, default_browser, profiles_known) {
+            Decision::Open {
+                browser,
+                profile,
+                reason,
+            } => {
+                debug!("{reason}");
+                metrics::mark(Phase::RoutingDecision);
+                open_url_in_chrome(
+                    &browser,
+                    chrome.config(),
+                    url,
+                    profile.as_ref(),
+                    source_app.as_ref(),
+                    Routing::Automatic,
+                    true,
+                );
+            }
+            Decision::ShowPicker {
+                reason,
+                asked: true,
+            } => debug!("{reason}, showing picker"),
+            Decision::ShowPicker {
+                reason,
+                asked: false,
+            } => warn!("{reason}, showing picker"),
+        }
     }
 
-    let mut app_height = (chrome.profile_entries.len() as f32) * (MyApp::BUTTON_SIZE + 15.0) + 75.0; // need plenty of space for context menu on bottom button
-    let app_width = MyApp::PROFILE_BUTTON_WIDTH + MyApp::BUTTON_SIZE * 3.0 + 20.0; // profile button + button + margins (5px*3)
+    metrics::mark(Phase::RoutingDecision);
+
+    if !profiles_known {
+        // the fallback was set above, with the preferred profile if there is one
+        crash::open_fallback();
+    }
+
+    if args.no_picker {
+        warn!("picker needed, exiting (--no-picker)");
+        metrics::finish();
+        exit(NO_PICKER_EXIT_CODE);
+    }
 
     let mut is_default_browser = true;
-    if let Ok(x) = registry_utils::is_default_browser() {
+    let is_default_browser_result =
+        info_span!("registry_lookup").in_scope(registry_utils::is_default_browser);
+    if let Ok(x) = is_default_browser_result {
         #[cfg(debug_assertions)]
         let no_warning = args.disable_default_browser_warning;
         #[cfg(not(debug_assertions))]
@@ -159,34 +918,38 @@ async fn main() {
 
         if !x && !no_warning {
             is_default_browser = false;
-            app_height += 75.0; // more height for 'not set as default browser' ui widget
         }
     } else {
         error!("Couldn't do default browser detection");
     }
 
-    let ci_arcm = Arc::new(Mutex::new(chrome));
-    let profile_picture_fetch = ci_arcm.clone();
-    for entry in &mut profile_picture_fetch.lock().unwrap().profile_entries {
-        let profile_picture_shared = entry.profile_picture.clone();
-        let profile_name = entry.profile_name.clone();
-        tokio::runtime::Handle::current().spawn(async move {
-            let mut locked_picture = profile_picture_shared.lock().await;
-            let fetch_picture_result = locked_picture.get_picture().await;
-            if fetch_picture_result.is_err() {
-                warn!(
-                    "error fetching picture for \"{}\": {}",
-                    profile_name,
-                    fetch_picture_result.err().unwrap()
-                );
-            }
-        });
+    if let Err(e) = history::prune(&chrome.config().history) {
+        warn!("couldn't prune history: {}", e);
     }
+    let suggestions = match history::read() {
+        Ok(history) => chrome.rule_suggestions(&history),
+        Err(e) => {
+            warn!("couldn't read history: {}", e);
+            Vec::new()
+        }
+    };
+
+    let last_crash = crash::last_crash();
+    let app_size = MyApp::app_size(
+        MyApp::profile_rows(&chrome, false),
+        is_default_browser,
+        MyApp::message_rows(&chrome, last_crash.as_ref())
+            + rerouted.is_some() as usize
+            + !suggestions.is_empty() as usize,
+    );
+    let watcher = FileWatcher::new(chrome.watched_paths());
+    fetch_profile_pictures(&chrome.profile_entries);
+    let ci_arcm = Arc::new(Mutex::new(chrome));
 
     // actually run the app
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
-            .with_inner_size(eframe::egui::vec2(app_width, app_height))
+            .with_inner_size(app_size)
             .with_resizable(false)
             .with_decorations(false)
             .with_transparent(true),
@@ -200,11 +963,23 @@ async fn main() {
         Box::new(move |_cc| {
             Box::new(MyApp {
                 chrome_interface: ci_arcm,
-                url: args.url,
-                device_state: DeviceState::new(),
-                main_begin_time: main_begin_time,
-                is_default_browser: is_default_browser,
-                default_browser: default_browser,
+                url,
+                launcher: Box::new(ProcessLauncher),
+                main_begin_time,
+                is_default_browser,
+                default_browser,
+                watcher,
+                app_size,
+                show_hidden: false,
+                last_crash,
+                show_history: false,
+                history_search: String::new(),
+                history: Vec::new(),
+                source_app,
+                rerouted,
+                remember_reroute: false,
+                suggestions,
+                show_suggestions: false,
             })
         }),
     )
@@ -214,15 +989,222 @@ async fn main() {
 struct MyApp {
     chrome_interface: Arc<Mutex<ChromeInterface>>,
     url: Option<String>,
-    device_state: DeviceState,
+    /// the program the link was clicked in, shown next to it
+    source_app: Option<String>,
+    launcher: Box<dyn Launcher>,
     main_begin_time: Instant,
     is_default_browser: bool,
     default_browser: Browser,
+    watcher: FileWatcher,
+    app_size: egui::Vec2,
+    show_hidden: bool,
+    last_crash: Option<crash::LastCrash>,
+    show_history: bool,
+    history_search: String,
+    history: Vec<HistoryEntry>,
+    /// the history entry being sent somewhere else, see `reroute`
+    rerouted: Option<HistoryEntry>,
+    remember_reroute: bool,
+    /// rules to offer for hosts picked by hand, see `rules suggest`
+    suggestions: Vec<RuleSuggestion>,
+    show_suggestions: bool,
 }
 
 impl MyApp {
     const BUTTON_SIZE: f32 = 30.0;
     const PROFILE_BUTTON_WIDTH: f32 = 200.0;
+    const HISTORY_HEIGHT: f32 = 420.0;
+    const HISTORY_ROWS: usize = 100;
+    const REROUTE_SHORTCUT: egui::KeyboardShortcut =
+        egui::KeyboardShortcut::new(egui::Modifiers::CTRL, egui::Key::R);
+
+    fn app_size(
+        profile_count: usize,
+        is_default_browser: bool,
+        message_count: usize,
+    ) -> egui::Vec2 {
+        let mut app_height = (profile_count as f32) * (MyApp::BUTTON_SIZE + 15.0) + 75.0; // need plenty of space for context menu on bottom button
+        let app_width = MyApp::PROFILE_BUTTON_WIDTH + MyApp::BUTTON_SIZE * 3.0 + 20.0; // profile button + button + margins (5px*3)
+
+        if !is_default_browser {
+            app_height += 75.0; // more height for 'not set as default browser' ui widget
+        }
+
+        app_height += (message_count as f32) * 40.0; // errors and warnings are wrapped over a couple of lines
+
+        egui::vec2(app_width, app_height)
+    }
+
+    ///
+    /// picks up edits to prefs.json, chromevalet.toml and the browser's Local State
+    ///
+    fn reload_changed_files(&mut self, ctx: &egui::Context) {
+        let changed = self.watcher.poll();
+        ctx.request_repaint_after(FileWatcher::POLL_INTERVAL);
+        if changed.is_empty() {
+            return;
+        }
+
+        debug!("files changed: {:?}", changed);
+        let mut chrome_interface = self.chrome_interface.lock().unwrap();
+        let statefile_path = chrome_interface.statefile_path();
+        if changed.iter().any(|path| *path != statefile_path) {
+            chrome_interface.reload_config();
+        }
+
+        // the statefile moves if the default browser in prefs changed
+        // prefs changes can swap profile icons, so always refresh the entries
+        let browser_changed = chrome_interface.statefile_path() != statefile_path;
+        match chrome_interface.reload_profiles(browser_changed) {
+            Ok(changes) => {
+                if !changes.is_empty() {
+                    debug!("profiles changed: {:?}", changes);
+                }
+
+                fetch_profile_pictures(
+                    chrome_interface
+                        .profile_entries
+                        .iter()
+                        .filter(|entry| changes.needs_picture(&entry.profile_directory)),
+                );
+            }
+            Err(err) => {
+                error!("couldn't reload profiles: {err}");
+                let err = format!("couldn't read profiles: {err:#}");
+                chrome_interface.push_load_error(err);
+            }
+        }
+
+        let watched_paths = chrome_interface.watched_paths();
+        if watched_paths != self.watcher.paths() {
+            self.watcher = FileWatcher::new(watched_paths);
+        }
+    }
+
+    /// errors and warnings shown above the profiles, plus the remap button and last crash
+    fn message_rows(
+        chrome_interface: &ChromeInterface,
+        last_crash: Option<&crash::LastCrash>,
+    ) -> usize {
+        let remap_button = chrome_interface.preferred_profile_remap().is_some() as usize;
+        let last_crash = last_crash.is_some() as usize * 2;
+        chrome_interface.load_errors().len()
+            + chrome_interface.profile_warnings().len()
+            + chrome_interface.rule_warnings().len()
+            + remap_button
+            + last_crash
+    }
+
+    /// profile grid rows, plus one for the sort and 'show hidden' options
+    fn profile_rows(chrome_interface: &ChromeInterface, show_hidden: bool) -> usize {
+        chrome_interface.display_entries(show_hidden).len() + 1
+    }
+
+    fn update_app_size(&mut self, ctx: &egui::Context) {
+        let mut app_size = {
+            let chrome_interface = self.chrome_interface.lock().unwrap();
+            MyApp::app_size(
+                MyApp::profile_rows(&chrome_interface, self.show_hidden),
+                self.is_default_browser,
+                MyApp::message_rows(&chrome_interface, self.last_crash.as_ref())
+                    + self.rerouted.is_some() as usize
+                    + !self.suggestions.is_empty() as usize,
+            )
+        };
+
+        if self.show_history || self.show_suggestions {
+            app_size.y = app_size.y.max(MyApp::HISTORY_HEIGHT);
+        }
+
+        if app_size != self.app_size {
+            self.app_size = app_size;
+            ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(app_size));
+        }
+    }
+
+    ///
+    /// the panic message from the last run, until it's dismissed
+    ///
+    fn show_last_crash(&mut self, ui: &mut egui::Ui) {
+        let Some(last_crash) = &self.last_crash else {
+            return;
+        };
+
+        ui.scope(|ui| {
+            ui.style_mut().visuals.override_text_color =
+                Some(egui::Color32::from_rgba_unmultiplied(255, 123, 0, 255));
+            ui.add(
+                egui::Label::new(format!(
+                    "Chrome Valet crashed last time: {}",
+                    last_crash.summary
+                ))
+                .wrap(true),
+            )
+            .on_hover_text(last_crash.path.display().to_string());
+        });
+
+        if ui.button("Dismiss").clicked() {
+            if let Err(e) = crash::dismiss(last_crash) {
+                error!("couldn't dismiss crash report: {}", e);
+            }
+            self.last_crash = None;
+        }
+
+        ui.separator();
+    }
+
+    fn show_load_errors(&self, ui: &mut egui::Ui) {
+        let mut chrome_interface = self.chrome_interface.lock().unwrap();
+        let profile_warnings = chrome_interface.profile_warnings();
+        let rule_warnings: Vec<String> = chrome_interface
+            .rule_warnings()
+            .iter()
+            .map(|rule_warning| rule_warning.to_string())
+            .collect();
+        if chrome_interface.load_errors().is_empty()
+            && profile_warnings.is_empty()
+            && rule_warnings.is_empty()
+        {
+            return;
+        }
+
+        ui.scope(|ui| {
+            ui.style_mut().visuals.override_text_color =
+                Some(egui::Color32::from_rgba_unmultiplied(255, 123, 0, 255));
+            let messages = chrome_interface
+                .load_errors()
+                .iter()
+                .chain(profile_warnings.iter())
+                .chain(rule_warnings.iter());
+            for message in messages {
+                ui.add(egui::Label::new(message).wrap(true));
+            }
+        });
+
+        // the preferred profile was deleted, but there's another one signed in to the same account
+        let remap = chrome_interface.preferred_profile_remap().map(|entry| {
+            (
+                entry.profile_directory.clone(),
+                chrome_interface.display_name(entry),
+            )
+        });
+        if let Some((profile_dir, display_name)) = remap {
+            if ui
+                .add(egui::Button::new(format!("Prefer \"{display_name}\" instead")).wrap(true))
+                .clicked()
+            {
+                chrome_interface
+                    .prefs_mut()
+                    .set_preferred_profile(&profile_dir);
+                chrome_interface.remember_preferred_profile_account();
+                if let Err(e) = chrome_interface.write_prefs() {
+                    error!("couldn't write prefs: {}", e);
+                }
+            }
+        }
+
+        ui.separator();
+    }
 
     fn default_browser_check(&mut self, ui: &mut egui::Ui) {
         if !self.is_default_browser {
@@ -265,13 +1247,7 @@ impl MyApp {
     fn show_url(&mut self, ui: &mut egui::Ui) {
         // show the user which url we're talking about
         if let Some(url) = self.url.clone() {
-            let trimmed_url_for_display = url.clone();
-            let trim_len = 30;
-            let trimmed_url_for_display = if trimmed_url_for_display.len() > trim_len {
-                trimmed_url_for_display[0..trim_len].to_string() + "..."
-            } else {
-                trimmed_url_for_display
-            };
+            let trimmed_url_for_display = truncate_for_display(&url, 30);
 
             ui.with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
                 ui.style_mut().override_text_style = Some(egui::style::TextStyle::Monospace);
@@ -284,6 +1260,10 @@ impl MyApp {
                 if ui.add(clipboard_label).clicked() {
                     cli_clipboard::set_contents(url).unwrap();
                 }
+
+                if let Some(source_app) = &self.source_app {
+                    ui.label(egui::RichText::new(format!("from {source_app}")).weak());
+                }
             });
         }
     }
@@ -292,10 +1272,28 @@ impl MyApp {
         {
             self.default_browser_check(ui);
 
-            self.show_url(ui);
+            self.show_last_crash(ui);
+
+            self.show_load_errors(ui);
+
+            self.show_suggestion_notice(ui);
+
+            self.show_url(ui);
+
+            self.show_reroute(ui);
 
             ui.separator();
 
+            if self.show_history {
+                self.draw_history_panel(ui);
+                return;
+            }
+
+            if self.show_suggestions {
+                self.draw_suggestions_panel(ui);
+                return;
+            }
+
             egui::Grid::new("profile_grid").show(ui, |ui| {
                 ui.label("");
 
@@ -317,11 +1315,40 @@ impl MyApp {
                 let prefs = chrome_interface.prefs();
                 let preferred_profile = prefs.get_preferred_profile();
                 let mut new_preferred_profile = preferred_profile.clone();
+                let mut settings_change: Option<(String, ProfileDisplaySettings)> = None;
+                let mut profile_move: Option<(String, usize)> = None;
+                let mut profile_launch: Option<String> = None;
 
-                for profile_entry in &chrome_interface.profile_entries {
-                    self.draw_profile_icon(ui, profile_entry);
+                let display_entries = chrome_interface.display_entries(self.show_hidden);
+                for (position, profile_entry) in display_entries.into_iter().enumerate() {
+                    let settings = prefs.profile_settings(&profile_entry.profile_directory);
 
-                    self.draw_profile_label_button(ui, profile_entry);
+                    // the picture is the handle for dragging profiles into a new order
+                    let drag_id = egui::Id::new(("profile_drag", &profile_entry.profile_directory));
+                    ui.dnd_drag_source(drag_id, profile_entry.profile_directory.clone(), |ui| {
+                        self.draw_profile_icon(ui, profile_entry);
+                    });
+
+                    let label_response = self.draw_profile_label_button(
+                        ui,
+                        &chrome_interface.display_name(profile_entry),
+                        settings.hidden,
+                    );
+
+                    if label_response.clicked() {
+                        profile_launch = Some(profile_entry.profile_directory.clone());
+                    }
+
+                    if let Some(dragged_profile) = label_response.dnd_release_payload::<String>() {
+                        profile_move = Some(((*dragged_profile).clone(), position));
+                    }
+
+                    label_response.context_menu(|ui| {
+                        if let Some(new_settings) = self.draw_profile_settings_menu(ui, &settings) {
+                            settings_change =
+                                Some((profile_entry.profile_directory.clone(), new_settings));
+                        }
+                    });
 
                     // may update preferred_profile
                     self.draw_preferred_profile_button(
@@ -333,16 +1360,370 @@ impl MyApp {
                     ui.end_row();
                 } // for profile entry
 
+                let mut prefs_changed = false;
                 if preferred_profile != new_preferred_profile {
                     let prefs = chrome_interface.prefs_mut();
                     prefs.set_preferred_profile(&new_preferred_profile);
+                    chrome_interface.remember_preferred_profile_account();
+                    prefs_changed = true;
+                }
+
+                if let Some((profile_dir, new_settings)) = settings_change {
+                    let prefs = chrome_interface.prefs_mut();
+                    prefs.set_profile_settings(&profile_dir, new_settings);
+                    prefs_changed = true;
+                }
+
+                if let Some((profile_dir, position)) = profile_move {
+                    chrome_interface.move_profile(&profile_dir, position, self.show_hidden);
+                    prefs_changed = true;
+                }
 
+                if prefs_changed {
                     // todo: do this right in prefs once I pull out all the file stuff
                     if let Err(e) = chrome_interface.write_prefs() {
                         error!("couldn't write prefs: {}", e);
                     }
                 }
+
+                if let Some(profile_dir) = profile_launch {
+                    // if shift down, chrome_valet remains open
+                    let exit_after_open_url = !ui.input(|i| i.modifiers.shift);
+                    self.open_in_profile(&mut chrome_interface, profile_dir, exit_after_open_url);
+                }
             }); // grid
+
+            self.draw_profile_list_options(ui);
+        }
+    }
+
+    fn draw_profile_list_options(&mut self, ui: &mut egui::Ui) {
+        let mut chrome_interface = self.chrome_interface.lock().unwrap();
+        let hidden_count = chrome_interface.hidden_profile_count();
+        let profile_sort = chrome_interface.prefs().profile_sort;
+        let mut new_profile_sort = profile_sort;
+        let mut open_history = false;
+        let mut reroute_last = false;
+
+        ui.horizontal(|ui| {
+            ui.label("Sort");
+            egui::ComboBox::from_id_source("profile_sort")
+                .selected_text(profile_sort.to_string())
+                .show_ui(ui, |ui| {
+                    for sort_mode in ProfileSortMode::ALL {
+                        ui.selectable_value(
+                            &mut new_profile_sort,
+                            sort_mode,
+                            sort_mode.to_string(),
+                        );
+                    }
+                });
+
+            if hidden_count > 0 {
+                ui.checkbox(&mut self.show_hidden, format!("Show {hidden_count} hidden"));
+            }
+
+            if ui.button("History").clicked() {
+                open_history = true;
+            }
+
+            let reroute_shortcut = ui.ctx().format_shortcut(&MyApp::REROUTE_SHORTCUT);
+            if ui
+                .button("Reroute last")
+                .on_hover_text(format!(
+                    "Pick a profile for the last link ({reroute_shortcut})"
+                ))
+                .clicked()
+            {
+                reroute_last = true;
+            }
+        });
+
+        if new_profile_sort != profile_sort {
+            chrome_interface.prefs_mut().profile_sort = new_profile_sort;
+            if let Err(e) = chrome_interface.write_prefs() {
+                error!("couldn't write prefs: {}", e);
+            }
+        }
+
+        // these lock chrome_interface themselves
+        drop(chrome_interface);
+        if open_history {
+            self.open_history();
+        }
+        if reroute_last {
+            self.reroute_last();
+        }
+    }
+
+    ///
+    /// puts the last link chrome valet opened in the picker, to open it again somewhere else
+    ///
+    fn reroute_last(&mut self) {
+        let entry = match history::last() {
+            Ok(Some(entry)) => entry,
+            Ok(None) => return,
+            Err(e) => {
+                error!("couldn't read history: {}", e);
+                return;
+            }
+        };
+
+        self.url = Some(entry.url.clone());
+        self.source_app = entry.source_app.clone();
+        self.rerouted = Some(entry);
+        self.remember_reroute = false;
+        self.show_history = false;
+    }
+
+    ///
+    /// where the rerouted link went last time, and whether to make a rule of the new choice
+    ///
+    fn show_reroute(&mut self, ui: &mut egui::Ui) {
+        let Some(rerouted) = &self.rerouted else {
+            return;
+        };
+
+        let chrome_interface = self.chrome_interface.lock().unwrap();
+        let last_profile = rerouted
+            .profile
+            .as_deref()
+            .and_then(|profile_dir| {
+                chrome_interface
+                    .profile_entries
+                    .iter()
+                    .find(|entry| entry.profile_directory == profile_dir)
+            })
+            .map(|entry| chrome_interface.display_name(entry))
+            .unwrap_or_else(|| "the last used profile".to_string());
+        ui.label(format!("Rerouting, this opened in {last_profile}"));
+
+        let host = self
+            .url
+            .as_ref()
+            .and_then(|url| RoutingRule::for_host(url, ""))
+            .and_then(|rule| rule.host);
+        if let Some(host) = host {
+            ui.checkbox(
+                &mut self.remember_reroute,
+                format!("Always open {host} in the profile I pick"),
+            );
+        }
+    }
+
+    fn show_suggestion_notice(&mut self, ui: &mut egui::Ui) {
+        if self.suggestions.is_empty() || self.show_suggestions {
+            return;
+        }
+
+        let text = match self.suggestions.len() {
+            1 => "1 suggested rule".to_string(),
+            count => format!("{count} suggested rules"),
+        };
+        if ui.button(text).clicked() {
+            self.show_suggestions = true;
+            self.show_history = false;
+        }
+
+        ui.separator();
+    }
+
+    ///
+    /// hosts that keep being opened in the same profile, each can become a rule in one click
+    ///
+    fn draw_suggestions_panel(&mut self, ui: &mut egui::Ui) {
+        if ui.button("Back").clicked() {
+            self.show_suggestions = false;
+        }
+        ui.separator();
+
+        let mut chrome_interface = self.chrome_interface.lock().unwrap();
+        let mut accepted: Option<usize> = None;
+        let mut dismissed: Option<usize> = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (index, suggestion) in self.suggestions.iter().enumerate() {
+                let profile = chrome_interface
+                    .profile_entries
+                    .iter()
+                    .find(|entry| entry.profile_directory == suggestion.profile)
+                    .map(|entry| chrome_interface.display_name(entry))
+                    .unwrap_or(suggestion.profile.clone());
+                ui.add(
+                    egui::Label::new(format!(
+                        "You opened {} links to {} in {profile}",
+                        suggestion.count, suggestion.host
+                    ))
+                    .wrap(true),
+                );
+                ui.label(
+                    egui::RichText::new(format!(
+                        "{:.0}% of the times you picked",
+                        suggestion.confidence() * 100.0
+                    ))
+                    .weak(),
+                );
+
+                ui.horizontal(|ui| {
+                    if ui.button("Create rule").clicked() {
+                        accepted = Some(index);
+                    }
+                    if ui.button("Dismiss").clicked() {
+                        dismissed = Some(index);
+                    }
+                });
+
+                ui.separator();
+            }
+        });
+
+        if let Some(index) = accepted {
+            let suggestion = self.suggestions.remove(index);
+            chrome_interface
+                .prefs_mut()
+                .remember_rule(suggestion.rule());
+        } else if let Some(index) = dismissed {
+            let suggestion = self.suggestions.remove(index);
+            chrome_interface
+                .prefs_mut()
+                .dismissed_suggestions
+                .push(suggestion.host);
+        } else {
+            return;
+        }
+
+        if let Err(e) = chrome_interface.write_prefs() {
+            error!("couldn't write prefs: {}", e);
+        }
+
+        if self.suggestions.is_empty() {
+            self.show_suggestions = false;
+        }
+    }
+
+    fn open_history(&mut self) {
+        match history::read() {
+            Ok(history) => self.history = history,
+            Err(e) => error!("couldn't read history: {}", e),
+        }
+        self.show_history = true;
+    }
+
+    ///
+    /// recent links, newest first, each of which can be opened again in any profile
+    ///
+    fn draw_history_panel(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("Back").clicked() {
+                self.show_history = false;
+            }
+            ui.add(egui::TextEdit::singleline(&mut self.history_search).hint_text("Search"));
+        });
+        ui.separator();
+
+        let chrome_interface = self.chrome_interface.lock().unwrap();
+        let profile_name = |profile_dir: &str| {
+            chrome_interface
+                .profile_entries
+                .iter()
+                .find(|entry| entry.profile_directory == profile_dir)
+                .map(|entry| chrome_interface.display_name(entry))
+                .unwrap_or(profile_dir.to_string())
+        };
+
+        let query = self.history_search.to_lowercase();
+        let now = history::now();
+        let mut reopen: Option<(HistoryEntry, String)> = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            let entries = self
+                .history
+                .iter()
+                .rev()
+                .filter(|entry| {
+                    let profile = entry
+                        .profile
+                        .as_deref()
+                        .map(profile_name)
+                        .unwrap_or_default();
+                    query.is_empty()
+                        || entry.matches(&query)
+                        || profile.to_lowercase().contains(&query)
+                })
+                .take(MyApp::HISTORY_ROWS);
+
+            for entry in entries {
+                ui.horizontal(|ui| {
+                    ui.label(egui::RichText::new(history::format_age(entry.time, now)).weak());
+                    let profile = entry.profile.as_deref().map(profile_name);
+                    ui.label(profile.unwrap_or("last used profile".to_string()));
+                });
+
+                ui.add(egui::Label::new(truncate_for_display(&entry.url, 40)).wrap(false))
+                    .on_hover_text(&entry.url);
+
+                ui.menu_button("Reopen in…", |ui| {
+                    for profile_entry in chrome_interface.display_entries(false) {
+                        if ui
+                            .button(chrome_interface.display_name(profile_entry))
+                            .clicked()
+                        {
+                            reopen = Some((entry.clone(), profile_entry.profile_directory.clone()));
+                            ui.close_menu();
+                        }
+                    }
+                });
+
+                ui.separator();
+            }
+        });
+
+        if let Some((entry, profile_dir)) = reopen {
+            let request = LaunchRequest {
+                browser: Browser::try_from(&entry.browser).unwrap_or(self.default_browser),
+                url: entry.url,
+                profile_directory: Some(profile_dir),
+                routing: Routing::Manual,
+                source_app: entry.source_app,
+                exit_when_done: !ui.input(|i| i.modifiers.shift),
+            };
+            self.launcher.launch(chrome_interface.config(), &request);
+
+            drop(chrome_interface);
+            self.open_history();
+        }
+    }
+
+    ///
+    /// right click menu on a profile, returns the new settings if the user changed any
+    ///
+    fn draw_profile_settings_menu(
+        &self,
+        ui: &mut egui::Ui,
+        settings: &ProfileDisplaySettings,
+    ) -> Option<ProfileDisplaySettings> {
+        let mut new_settings = settings.clone();
+
+        ui.checkbox(&mut new_settings.hidden, "Hidden");
+
+        ui.label("Alias");
+        let mut alias = new_settings.alias.clone().unwrap_or_default();
+        if ui.text_edit_singleline(&mut alias).changed() {
+            new_settings.alias = Some(alias).filter(|alias| !alias.is_empty());
+        }
+
+        ui.label("Icon file");
+        let mut icon_path = new_settings.icon_path.clone().unwrap_or_default();
+        if ui.text_edit_singleline(&mut icon_path).changed() {
+            new_settings.icon_path = Some(icon_path).filter(|icon_path| !icon_path.is_empty());
+        }
+
+        if new_settings.order.is_some() && ui.button("Reset position").clicked() {
+            new_settings.order = None;
+        }
+
+        if new_settings != *settings {
+            Some(new_settings)
+        } else {
+            None
         }
     }
 
@@ -377,32 +1758,118 @@ impl MyApp {
         };
     }
 
-    fn draw_profile_label_button(&self, ui: &mut egui::Ui, profile_entry: &ChromeProfileEntry) {
-        let mut button = egui::Button::new(profile_entry.profile_name.clone());
+    fn draw_profile_label_button(
+        &self,
+        ui: &mut egui::Ui,
+        display_name: &str,
+        hidden: bool,
+    ) -> egui::Response {
+        let mut label = egui::RichText::new(display_name);
+        if hidden {
+            label = label.weak().italics();
+        }
+        // with no url a click opens nothing, but the menu and dragging still work
+        let button = egui::Button::new(label);
+        ui.add_sized(egui::vec2(200.0, MyApp::BUTTON_SIZE), button)
+    }
+
+    ///
+    /// user clicked on profile, open link
+    /// a rerouted link can leave a rule behind, so the next one goes to the same profile
+    ///
+    fn open_in_profile(
+        &self,
+        chrome_interface: &mut ChromeInterface,
+        profile_dir: String,
+        exit_when_done: bool,
+    ) {
+        let Some(url) = &self.url else {
+            return;
+        };
 
-        // if there's no url, the buttons do nothing
-        if self.url.is_none() {
-            button = button.sense(egui::Sense::hover());
+        if self.rerouted.is_some() && self.remember_reroute {
+            if let Some(rule) = RoutingRule::for_host(url, &profile_dir) {
+                debug!("remembering rule: {:?}", rule);
+                chrome_interface.prefs_mut().remember_rule(rule);
+                if let Err(e) = chrome_interface.write_prefs() {
+                    error!("couldn't write prefs: {}", e);
+                }
+            }
         }
 
-        if ui
-            .add_sized(egui::vec2(200.0, MyApp::BUTTON_SIZE), button)
-            .clicked()
-        {
-            // if shift down, chrome_valet remains open
-            let mut exit_after_open_url = true;
-            let keys: Vec<Keycode> = self.device_state.get_keys();
-            if keys.contains(&Keycode::LShift) {
-                exit_after_open_url = false;
-            }
-
-            // user clicked on profile, open link
-            open_url_in_chrome(
-                &self.default_browser,
-                &self.url.as_ref().unwrap(),
-                Some(&profile_entry.profile_directory.clone()),
-                exit_after_open_url,
-            );
+        let request = LaunchRequest {
+            browser: self.default_browser,
+            url: url.clone(),
+            profile_directory: Some(profile_dir),
+            routing: Routing::Manual,
+            source_app: self.source_app.clone(),
+            exit_when_done,
+        };
+        self.launcher.launch(chrome_interface.config(), &request);
+    }
+
+    ///
+    /// the context in use, in the title bar, with a menu to switch or use one for an hour
+    ///
+    fn draw_context_switcher(ui: &mut egui::Ui, chrome_interface: &Arc<Mutex<ChromeInterface>>) {
+        let mut chrome_interface = chrome_interface.lock().unwrap();
+        if chrome_interface.config().contexts.is_empty() {
+            return;
+        }
+
+        let names: Vec<String> = chrome_interface.config().contexts.keys().cloned().collect();
+        let current = chrome_interface
+            .current_context()
+            .map(|(name, _)| name.to_string());
+        let until = chrome_interface
+            .context_override()
+            .map(|context_override| context_override.until_local());
+        let label = match (&current, &until) {
+            (Some(name), Some(until)) => format!("{name} until {until}"),
+            (Some(name), None) => name.clone(),
+            (None, _) => "No context".to_string(),
+        };
+
+        let mut changed = false;
+        ui.menu_button(label, |ui| {
+            for name in &names {
+                if ui.radio(current.as_ref() == Some(name), name).clicked() {
+                    chrome_interface.prefs_mut().use_context(Some(name.clone()));
+                    changed = true;
+                    ui.close_menu();
+                }
+            }
+            if ui.radio(current.is_none(), "No context").clicked() {
+                chrome_interface.prefs_mut().use_context(None);
+                changed = true;
+                ui.close_menu();
+            }
+
+            ui.separator();
+            ui.menu_button("For the next hour", |ui| {
+                for name in &names {
+                    if ui.button(name).clicked() {
+                        chrome_interface
+                            .prefs_mut()
+                            .override_context(name.clone(), history::now() + 60 * 60);
+                        changed = true;
+                        ui.close_menu();
+                    }
+                }
+            });
+            if until.is_some() && ui.button("End early").clicked() {
+                chrome_interface.prefs_mut().context_override = None;
+                changed = true;
+                ui.close_menu();
+            }
+        })
+        .response
+        .on_hover_text("Which context's rules and profile links open with");
+
+        if changed {
+            if let Err(e) = chrome_interface.write_prefs() {
+                error!("couldn't write prefs: {}", e);
+            }
         }
     }
 
@@ -429,6 +1896,31 @@ impl MyApp {
     }
 }
 
+impl MyApp {
+    ///
+    /// one frame of the picker, separate from eframe::App::update so tests can run it without a window
+    ///
+    fn ui(&mut self, ctx: &egui::Context) {
+        metrics::mark(Phase::UiFirstFrame);
+        if ctx.input_mut(|i| i.consume_shortcut(&MyApp::REROUTE_SHORTCUT)) {
+            self.reroute_last();
+        }
+
+        self.reload_changed_files(ctx);
+        self.update_app_size(ctx);
+
+        let chrome_interface = self.chrome_interface.clone();
+        custom_frame::custom_window_frame(
+            ctx,
+            "Chrome Valet",
+            |ui| MyApp::draw_context_switcher(ui, &chrome_interface),
+            |ui| {
+                self.main_panel_contents(ui, ctx);
+            },
+        );
+    }
+}
+
 impl eframe::App for MyApp {
 
     fn clear_color(&self, _visuals: &egui::Visuals) -> [f32; 4] {
@@ -436,47 +1928,71 @@ impl eframe::App for MyApp {
     }
 
     fn update(&mut self, ctx: &eframe::egui::Context, _: &mut eframe::Frame) {
-        custom_frame::custom_window_frame(ctx,  "Chrome Valet", |ui| {
-            self.main_panel_contents(ui, ctx);
-        });
+        self.ui(ctx);
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        metrics::finish();
+    }
+}
+
+///
+/// loads profile pictures in the background, the ui shows them once they're decoded
+///
+fn fetch_profile_pictures<'a>(entries: impl IntoIterator<Item = &'a ChromeProfileEntry>) {
+    let entries: Vec<&ChromeProfileEntry> = entries.into_iter().collect();
+    let remaining = Arc::new(AtomicUsize::new(entries.len()));
+    for entry in entries {
+        let remaining = remaining.clone();
+        let profile_picture_shared = entry.profile_picture.clone();
+        let profile_name = entry.profile_name.clone();
+        let span = info_span!("picture_decode", profile = %profile_name);
+        let fetch_picture = async move {
+            let mut locked_picture = profile_picture_shared.lock().await;
+            let fetch_picture_result = locked_picture.get_picture().await;
+            if fetch_picture_result.is_err() {
+                warn!(
+                    "error fetching picture for \"{}\": {}",
+                    profile_name,
+                    fetch_picture_result.err().unwrap()
+                );
+            }
+
+            if remaining.fetch_sub(1, Ordering::SeqCst) == 1 {
+                metrics::mark(Phase::AvatarsReady);
+            }
+        };
+        tokio::runtime::Handle::current().spawn(fetch_picture.instrument(span));
     }
 }
 
 fn open_url_in_chrome(
     browser: &Browser,
-    url: &String,
+    config: &UserConfig,
+    url: &str,
     profile_name: Option<&String>,
+    source_app: Option<&String>,
+    routing: Routing,
     exit_when_done: bool,
 ) {
-    debug!("url: {}", url);
-    let mut chrome_command_line: String = String::default();
-    match registry_utils::get_browser_exe(browser) {
-        Err(e) => error!("failed to get browser exe location: {}", e),
-        Ok(v) => chrome_command_line = v,
-    }
-
-    // todo: break the open commands by argument, keep them in order, replace the one with %1 with the url
-    // for now we assume all chromium browsers play nice with these arguments
-
-    let mut chrome_command = Command::new(chrome_command_line);
-    chrome_command.creation_flags(DETACHED_PROCESS);
-
-    if profile_name.is_some() {
-        chrome_command.arg(format!("--profile-directory={}", profile_name.unwrap()));
-    }
-
-    chrome_command.arg("--single-argument").arg(url);
-
-    let chrome_command_child_result = chrome_command.spawn();
-
-    debug!("chrome command: {:?}", chrome_command);
-
-    if let Err(e) = chrome_command_child_result {
-        error!("Error excecuting command: {}", e);
+    let request = LaunchRequest {
+        browser: *browser,
+        url: url.to_string(),
+        profile_directory: profile_name.cloned(),
+        routing,
+        source_app: source_app.cloned(),
+        exit_when_done,
     };
+    ProcessLauncher.launch(config, &request);
+}
 
-    if exit_when_done {
-        exit(0);
+///
+/// `text` cut to `max_chars` characters, with "..." if anything was cut
+///
+fn truncate_for_display(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => text[..end].to_string() + "...",
+        None => text.to_string(),
     }
 }
 
@@ -486,9 +2002,313 @@ fn open_url_in_chrome(
 fn open_default_apps() {
     let mut default_apps_command = Command::new("cmd");
     default_apps_command.args(["/c", "start", "ms-settings:defaultapps"]);
+    #[cfg(windows)]
     default_apps_command.creation_flags(DETACHED_PROCESS);
     let default_apps_command_result = default_apps_command.spawn();
     if let Err(e) = default_apps_command_result {
         error!("Error excecuting command: {}", e);
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrome_interface::ProgramPrefs;
+    use eframe::egui::accesskit;
+    use launcher::RecordingLauncher;
+    use std::fs;
+    use std::sync::OnceLock;
+    use tempfile::TempDir;
+    use usage::ProfileUsage;
+
+    const URL: &str = "https://example.com/page";
+
+    const LOCAL_STATE: &str = r#"{
+        "profile": {
+            "info_cache": {
+                "Default": { "shortcut_name": "Personal", "user_name": "me@example.com" },
+                "Profile 1": { "shortcut_name": "Work", "user_name": "me@work.example" }
+            }
+        }
+    }"#;
+
+    /// the tests share paths::init's data root, so they take turns
+    static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    fn data_root() -> &'static TempDir {
+        static DATA_ROOT: OnceLock<TempDir> = OnceLock::new();
+        DATA_ROOT.get_or_init(|| {
+            let data_root = tempfile::tempdir().unwrap();
+            paths::init(Some(data_root.path().to_path_buf()), false);
+            assert_eq!(paths::get().data_root, data_root.path());
+
+            let user_data_dir = paths::browser_user_data_dir(&Browser::Chrome);
+            fs::create_dir_all(&user_data_dir).unwrap();
+            fs::write(user_data_dir.join("Local State"), LOCAL_STATE).unwrap();
+            fs::create_dir_all(paths::app_data_dir()).unwrap();
+            data_root
+        })
+    }
+
+    ///
+    /// the picker, run a frame at a time
+    /// widgets are found by name and position in the accesskit tree
+    ///
+    struct Harness {
+        ctx: egui::Context,
+        app: MyApp,
+        launcher: RecordingLauncher,
+        widgets: Vec<(String, egui::Rect)>,
+        modifiers: egui::Modifiers,
+    }
+
+    impl Harness {
+        fn new(prefs: &str, url: Option<&str>) -> Self {
+            data_root();
+            fs::write(ProgramPrefs::prefs_path(), prefs).unwrap();
+            _ = fs::remove_file(ProfileUsage::usage_path());
+            _ = fs::remove_file(history::history_path());
+
+            let mut chrome = ChromeInterface::new();
+            chrome.populate_profile_entries().unwrap();
+
+            let launcher = RecordingLauncher::default();
+            let ctx = egui::Context::default();
+            ctx.enable_accesskit();
+
+            let mut harness = Harness {
+                ctx,
+                app: MyApp {
+                    app_size: MyApp::app_size(MyApp::profile_rows(&chrome, false), true, 0),
+                    chrome_interface: Arc::new(Mutex::new(chrome)),
+                    url: url.map(String::from),
+                    source_app: None,
+                    launcher: Box::new(launcher.clone()),
+                    main_begin_time: Instant::now(),
+                    is_default_browser: true,
+                    default_browser: Browser::Chrome,
+                    watcher: FileWatcher::new(Vec::new()),
+                    show_hidden: false,
+                    last_crash: None,
+                    show_history: false,
+                    history_search: String::new(),
+                    history: Vec::new(),
+                    rerouted: None,
+                    remember_reroute: false,
+                    suggestions: Vec::new(),
+                    show_suggestions: false,
+                },
+                launcher,
+                widgets: Vec::new(),
+                modifiers: egui::Modifiers::default(),
+            };
+
+            // grids size their columns from the first frame, so widgets only settle on the second
+            harness.run_frame(Vec::new());
+            harness.run_frame(Vec::new());
+            harness
+        }
+
+        fn run_frame(&mut self, events: Vec<egui::Event>) {
+            let raw_input = egui::RawInput {
+                screen_rect: Some(egui::Rect::from_min_size(
+                    egui::Pos2::ZERO,
+                    self.app.app_size,
+                )),
+                modifiers: self.modifiers,
+                events,
+                ..Default::default()
+            };
+
+            let app = &mut self.app;
+            let output = self.ctx.run(raw_input, |ctx| app.ui(ctx));
+            if let Some(update) = output.platform_output.accesskit_update {
+                self.widgets = update
+                    .nodes
+                    .iter()
+                    .filter_map(|(_, node)| {
+                        Some((node.name()?.to_string(), to_rect(node.bounds()?)))
+                    })
+                    .collect();
+            }
+        }
+
+        fn widget(&self, name: &str) -> egui::Rect {
+            match self
+                .widgets
+                .iter()
+                .find(|(widget_name, _)| widget_name == name)
+            {
+                Some((_, rect)) => *rect,
+                None => panic!("no widget named \"{name}\" in {:?}", self.widgets),
+            }
+        }
+
+        /// the heart button on the same row as the profile button
+        fn heart_for(&self, profile_name: &str) -> egui::Rect {
+            let row = self.widget(profile_name);
+            self.widgets
+                .iter()
+                .find(|(name, rect)| name == "♡" && row.y_range().contains(rect.center().y))
+                .map(|(_, rect)| *rect)
+                .unwrap_or_else(|| panic!("no heart next to \"{profile_name}\""))
+        }
+
+        fn click(&mut self, rect: egui::Rect) {
+            self.click_with(rect, egui::PointerButton::Primary);
+        }
+
+        fn click_with(&mut self, rect: egui::Rect, pointer_button: egui::PointerButton) {
+            let pos = rect.center();
+            let button = |pressed| egui::Event::PointerButton {
+                pos,
+                button: pointer_button,
+                pressed,
+                modifiers: self.modifiers,
+            };
+
+            let (press, release) = (button(true), button(false));
+            self.run_frame(vec![egui::Event::PointerMoved(pos)]);
+            self.run_frame(vec![press]);
+            self.run_frame(vec![release]);
+            self.run_frame(Vec::new());
+        }
+
+        fn prefs_on_disk(&self) -> serde_json::Value {
+            serde_json::from_str(&fs::read_to_string(ProgramPrefs::prefs_path()).unwrap()).unwrap()
+        }
+    }
+
+    fn to_rect(rect: accesskit::Rect) -> egui::Rect {
+        egui::Rect::from_min_max(
+            egui::pos2(rect.x0 as f32, rect.y0 as f32),
+            egui::pos2(rect.x1 as f32, rect.y1 as f32),
+        )
+    }
+
+    fn lock() -> std::sync::MutexGuard<'static, ()> {
+        TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    #[test]
+    fn shows_fixture_profiles() {
+        let _lock = lock();
+        let harness = Harness::new(
+            r#"{ "profile_settings": { "Profile 1": { "alias": "Day job" } } }"#,
+            Some(URL),
+        );
+
+        harness.widget("Personal");
+        harness.widget("Day job");
+        assert!(harness.widgets.iter().all(|(name, _)| name != "Work"));
+    }
+
+    #[test]
+    fn heart_sets_preferred_profile() {
+        let _lock = lock();
+        let mut harness = Harness::new(r#"{ "preferred_profile": "Default" }"#, Some(URL));
+
+        harness.click(harness.heart_for("Work"));
+
+        let prefs = harness.prefs_on_disk();
+        assert_eq!(prefs["preferred_profile"], "Profile 1");
+        assert_eq!(prefs["preferred_profile_user_name"], "me@work.example");
+        assert!(harness.launcher.requests().is_empty());
+    }
+
+    #[test]
+    fn profile_button_launches_url() {
+        let _lock = lock();
+        let mut harness = Harness::new(r#"{ "preferred_profile": "Default" }"#, Some(URL));
+
+        harness.click(harness.widget("Work"));
+
+        assert_eq!(
+            harness.launcher.requests(),
+            [LaunchRequest {
+                browser: Browser::Chrome,
+                url: URL.to_string(),
+                profile_directory: Some("Profile 1".to_string()),
+                routing: Routing::Manual,
+                source_app: None,
+                exit_when_done: true,
+            }]
+        );
+        assert_eq!(harness.prefs_on_disk()["preferred_profile"], "Default");
+    }
+
+    #[test]
+    fn shift_click_keeps_picker_open() {
+        let _lock = lock();
+        let mut harness = Harness::new("{}", Some(URL));
+
+        harness.modifiers = egui::Modifiers::SHIFT;
+        harness.click(harness.widget("Personal"));
+
+        let requests = harness.launcher.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].profile_directory.as_deref(), Some("Default"));
+        assert!(!requests[0].exit_when_done);
+    }
+
+    #[test]
+    fn long_non_ascii_url_is_shortened() {
+        let _lock = lock();
+        let harness = Harness::new("{}", Some("https://example.jp/ページ/ページ/ページ/ページ"));
+
+        assert!(harness
+            .widgets
+            .iter()
+            .any(|(name, _)| name == "URL: https://example.jp/ページ/ページ/ページ..."));
+    }
+
+    #[test]
+    fn profile_button_without_url_does_nothing() {
+        let _lock = lock();
+        let mut harness = Harness::new("{}", None);
+
+        harness.click(harness.widget("Work"));
+
+        assert!(harness.launcher.requests().is_empty());
+    }
+
+    #[test]
+    fn profile_menu_works_without_url() {
+        let _lock = lock();
+        let mut harness = Harness::new("{}", None);
+
+        harness.click_with(harness.widget("Work"), egui::PointerButton::Secondary);
+
+        harness.widget("Hidden");
+        assert!(harness.launcher.requests().is_empty());
+    }
+
+    #[test]
+    fn reroute_last_puts_last_link_in_picker() {
+        let _lock = lock();
+        let entry = HistoryEntry {
+            time: history::now(),
+            original_url: URL.to_string(),
+            url: URL.to_string(),
+            browser: Browser::Chrome.to_string(),
+            profile: Some("Default".to_string()),
+            routing: Routing::Automatic,
+            source_app: Some("slack".to_string()),
+        };
+        let mut harness = Harness::new("{}", None);
+        history::record(&entry, &Default::default()).unwrap();
+
+        harness.click(harness.widget("Reroute last"));
+
+        assert_eq!(harness.app.url.as_deref(), Some(URL));
+        assert_eq!(harness.app.rerouted, Some(entry));
+        assert_eq!(harness.app.source_app.as_deref(), Some("slack"));
+
+        harness.click(harness.widget("Work"));
+
+        let requests = harness.launcher.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].url, URL);
+        assert_eq!(requests[0].profile_directory.as_deref(), Some("Profile 1"));
+    }
+}
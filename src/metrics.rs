@@ -0,0 +1,287 @@
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Result as IoResult;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::jsonl;
+use crate::paths::app_data_dir;
+
+/// runs kept in metrics.jsonl, older ones are pruned like history
+const MAX_RECORDS: usize = 2000;
+
+/// metrics.jsonl is only read to prune once it's this big, a run's record is 80 to 200 bytes,
+/// so until then a run is a plain append
+const PRUNE_AT_BYTES: u64 = MAX_RECORDS as u64 * 256;
+
+///
+/// points in a run of chrome valet, timed from the start of main
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// chose a profile from prefs and rules, or chose to show the picker
+    RoutingDecision,
+    UiFirstFrame,
+    /// every profile picture decoded
+    AvatarsReady,
+    LaunchSpawned,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 4] = [
+        Phase::RoutingDecision,
+        Phase::UiFirstFrame,
+        Phase::AvatarsReady,
+        Phase::LaunchSpawned,
+    ];
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Phase::RoutingDecision => write!(f, "routing_decision"),
+            Phase::UiFirstFrame => write!(f, "ui_first_frame"),
+            Phase::AvatarsReady => write!(f, "avatars_ready"),
+            Phase::LaunchSpawned => write!(f, "launch_spawned"),
+        }
+    }
+}
+
+///
+/// one run's timings, a line of metrics.jsonl
+///
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct StartupRecord {
+    /// seconds since the epoch
+    #[serde(default)]
+    pub time: u64,
+
+    #[serde(default)]
+    pub version: String,
+
+    /// milliseconds from the start of main
+    #[serde(default)]
+    pub phases: BTreeMap<Phase, f64>,
+}
+
+struct StartupTimer {
+    begin: Instant,
+    record: StartupRecord,
+}
+
+impl StartupTimer {
+    fn mark(&mut self, phase: Phase) {
+        if !self.record.phases.contains_key(&phase) {
+            let millis = self.begin.elapsed().as_secs_f64() * 1000.0;
+            debug!("{phase} after {millis:.1}ms");
+            self.record.phases.insert(phase, millis);
+        }
+    }
+}
+
+static STARTUP: Mutex<Option<StartupTimer>> = Mutex::new(None);
+
+pub fn metrics_path() -> PathBuf {
+    app_data_dir().join("metrics.jsonl")
+}
+
+///
+/// starts timing this run, phases are measured from `begin`
+///
+pub fn begin(begin: Instant) {
+    let mut startup = STARTUP.lock().unwrap_or_else(|e| e.into_inner());
+    *startup = Some(StartupTimer {
+        begin,
+        record: StartupRecord::default(),
+    });
+}
+
+///
+/// records when `phase` was first reached, later calls for the same phase are ignored
+///
+pub fn mark(phase: Phase) {
+    let mut startup = STARTUP.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(timer) = startup.as_mut() {
+        timer.mark(phase);
+    }
+}
+
+///
+/// appends this run's timings to metrics.jsonl, only the first call writes anything
+/// metrics.jsonl is pruned once it's past PRUNE_AT_BYTES
+///
+pub fn finish() {
+    let timer = STARTUP.lock().unwrap_or_else(|e| e.into_inner()).take();
+    let Some(mut timer) = timer else {
+        return;
+    };
+
+    if timer.record.phases.is_empty() {
+        return;
+    }
+
+    timer.record.time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+    timer.record.version = env!("CARGO_PKG_VERSION").to_string();
+
+    let metrics_path = metrics_path();
+    if let Err(e) = jsonl::append(&metrics_path, &timer.record) {
+        warn!("couldn't write startup metrics: {e}");
+        return;
+    }
+    if let Err(e) = prune_at(&metrics_path, MAX_RECORDS, PRUNE_AT_BYTES) {
+        warn!("couldn't prune startup metrics: {e}");
+    }
+}
+
+///
+/// the last `count` runs in metrics.jsonl, lines that don't parse are skipped
+///
+pub fn read_records(count: usize) -> IoResult<Vec<StartupRecord>> {
+    read_records_at(&metrics_path(), count)
+}
+
+fn read_records_at(metrics_path: &Path, count: usize) -> IoResult<Vec<StartupRecord>> {
+    Ok(jsonl::newest(jsonl::read(metrics_path)?, count))
+}
+
+///
+/// drops all but the newest MAX_RECORDS runs once metrics.jsonl is past PRUNE_AT_BYTES,
+/// returns how many were dropped
+///
+fn prune_at(metrics_path: &Path, max_records: usize, prune_at_bytes: u64) -> IoResult<usize> {
+    if !jsonl::larger_than(metrics_path, prune_at_bytes) {
+        return Ok(0);
+    }
+    jsonl::retain(metrics_path, |records: Vec<StartupRecord>| {
+        jsonl::newest(records, max_records)
+    })
+}
+
+/// nearest rank percentile of sorted `values`
+fn percentile(values: &[f64], percent: f64) -> f64 {
+    let rank = ((percent / 100.0) * values.len() as f64).ceil() as usize;
+    values[rank.clamp(1, values.len()) - 1]
+}
+
+///
+/// p50 and p95 per phase, as printed by `stats startup`
+///
+pub fn startup_summary(records: &[StartupRecord]) -> String {
+    let mut text = format!("{} run(s)\n", records.len());
+    text += &format!(
+        "{:<18} {:>6} {:>10} {:>10}\n",
+        "phase", "runs", "p50", "p95"
+    );
+    for phase in Phase::ALL {
+        let mut values: Vec<f64> = records
+            .iter()
+            .filter_map(|record| record.phases.get(&phase).copied())
+            .collect();
+        if values.is_empty() {
+            text += &format!(
+                "{:<18} {:>6} {:>10} {:>10}\n",
+                phase.to_string(),
+                0,
+                "-",
+                "-"
+            );
+            continue;
+        }
+
+        values.sort_by(f64::total_cmp);
+        text += &format!(
+            "{:<18} {:>6} {:>8.1}ms {:>8.1}ms\n",
+            phase.to_string(),
+            values.len(),
+            percentile(&values, 50.0),
+            percentile(&values, 95.0)
+        );
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn record(time: u64, phases: &[(Phase, f64)]) -> StartupRecord {
+        StartupRecord {
+            time,
+            version: "0.0.0".to_string(),
+            phases: phases.iter().copied().collect(),
+        }
+    }
+
+    #[test]
+    fn mark_keeps_the_first_time_a_phase_is_reached() {
+        let mut timer = StartupTimer {
+            begin: Instant::now(),
+            record: StartupRecord::default(),
+        };
+        timer.mark(Phase::RoutingDecision);
+        let first = timer.record.phases[&Phase::RoutingDecision];
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        timer.mark(Phase::RoutingDecision);
+        timer.mark(Phase::LaunchSpawned);
+
+        assert_eq!(timer.record.phases[&Phase::RoutingDecision], first);
+        assert!(timer.record.phases[&Phase::LaunchSpawned] > first);
+        assert_eq!(timer.record.phases.len(), 2);
+    }
+
+    #[test]
+    fn summary_has_percentiles_per_phase() {
+        let records: Vec<StartupRecord> = (1..=20)
+            .map(|run| record(run, &[(Phase::RoutingDecision, run as f64)]))
+            .collect();
+        let summary = startup_summary(&records);
+        let lines: Vec<&str> = summary.lines().collect();
+        assert_eq!(lines[0], "20 run(s)");
+        assert_eq!(lines[2], "routing_decision       20     10.0ms     19.0ms");
+        assert_eq!(lines[3], "ui_first_frame          0          -          -");
+        assert_eq!(percentile(&[4.0], 95.0), 4.0);
+    }
+
+    #[test]
+    fn prune_keeps_the_newest_runs_once_the_file_is_big() {
+        let dir = tempfile::tempdir().unwrap();
+        let metrics_path = dir.path().join("metrics.jsonl");
+        assert_eq!(prune_at(&metrics_path, 3, 0).unwrap(), 0);
+
+        for time in 1..=5 {
+            jsonl::append(&metrics_path, &record(time, &[(Phase::UiFirstFrame, 1.0)])).unwrap();
+        }
+        let size = fs::metadata(&metrics_path).unwrap().len();
+
+        // under the size nothing is read or rewritten
+        assert_eq!(prune_at(&metrics_path, 3, size).unwrap(), 0);
+        assert_eq!(read_records_at(&metrics_path, 10).unwrap().len(), 5);
+
+        assert_eq!(prune_at(&metrics_path, 3, size - 1).unwrap(), 2);
+        let times: Vec<u64> = read_records_at(&metrics_path, 10)
+            .unwrap()
+            .iter()
+            .map(|record| record.time)
+            .collect();
+        assert_eq!(times, [3, 4, 5]);
+        assert_eq!(prune_at(&metrics_path, 3, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn pruning_starts_past_max_records() {
+        let longest = record(
+            u64::MAX,
+            &Phase::ALL.map(|phase| (phase, 123456.78901234567)),
+        );
+        let longest = serde_json::to_string(&longest).unwrap().len() + 1;
+        assert!(PRUNE_AT_BYTES > (MAX_RECORDS * longest) as u64);
+    }
+}
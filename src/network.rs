@@ -0,0 +1,415 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+#[cfg(target_os = "linux")]
+use std::fs;
+use std::net::IpAddr;
+
+use crate::domains;
+use crate::rules::glob_matches;
+
+///
+/// what routing needs to know about the local network, so conditions can run against a fake
+/// read from the operating system, nothing is sent anywhere
+///
+pub trait NetworkState {
+    /// interfaces that are up, with their addresses
+    fn interfaces(&self) -> Vec<NetworkInterface>;
+
+    /// dns suffixes this computer searches, eg. "corp.example.com"
+    fn dns_suffixes(&self) -> Vec<String>;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkInterface {
+    /// as the operating system names it, eg. "tun0" or "Corp VPN"
+    pub name: String,
+    pub addresses: Vec<IpAddr>,
+}
+
+/// the real network
+pub struct SystemNetwork;
+
+impl NetworkState for SystemNetwork {
+    fn interfaces(&self) -> Vec<NetworkInterface> {
+        let mut interfaces: Vec<NetworkInterface> = Vec::new();
+        let addresses = match if_addrs::get_if_addrs() {
+            Ok(addresses) => addresses,
+            Err(e) => {
+                warn!("couldn't list network interfaces: {}", e);
+                return interfaces;
+            }
+        };
+
+        // one entry per address, grouped by interface
+        for address in addresses {
+            match interfaces
+                .iter_mut()
+                .find(|interface| interface.name == address.name)
+            {
+                Some(interface) => interface.addresses.push(address.ip()),
+                None => interfaces.push(NetworkInterface {
+                    name: address.name.clone(),
+                    addresses: vec![address.ip()],
+                }),
+            }
+        }
+        interfaces
+    }
+
+    fn dns_suffixes(&self) -> Vec<String> {
+        #[cfg(windows)]
+        {
+            windows_dns_suffixes()
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            fs::read_to_string("/etc/resolv.conf")
+                .map(|resolv_conf| parse_resolv_conf(&resolv_conf))
+                .unwrap_or_default()
+        }
+
+        #[cfg(not(any(windows, target_os = "linux")))]
+        {
+            Vec::new()
+        }
+    }
+}
+
+///
+/// a network for tests, (interface, address) pairs are grouped by interface like the real one
+///
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct FakeNetwork {
+    pub interfaces: Vec<(&'static str, &'static str)>,
+    pub dns_suffixes: Vec<&'static str>,
+}
+
+#[cfg(test)]
+impl NetworkState for FakeNetwork {
+    fn interfaces(&self) -> Vec<NetworkInterface> {
+        let mut interfaces: Vec<NetworkInterface> = Vec::new();
+        for (name, address) in &self.interfaces {
+            let address = address.parse().unwrap();
+            match interfaces
+                .iter_mut()
+                .find(|interface| interface.name == *name)
+            {
+                Some(interface) => interface.addresses.push(address),
+                None => interfaces.push(NetworkInterface {
+                    name: name.to_string(),
+                    addresses: vec![address],
+                }),
+            }
+        }
+        interfaces
+    }
+
+    fn dns_suffixes(&self) -> Vec<String> {
+        self.dns_suffixes
+            .iter()
+            .map(|suffix| suffix.to_string())
+            .collect()
+    }
+}
+
+///
+/// the network read once, so every rule checked for a link sees the same state
+///
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkSnapshot {
+    pub interfaces: Vec<NetworkInterface>,
+    pub dns_suffixes: Vec<String>,
+}
+
+impl NetworkSnapshot {
+    pub fn read(state: &dyn NetworkState) -> Self {
+        NetworkSnapshot {
+            interfaces: state.interfaces(),
+            dns_suffixes: state
+                .dns_suffixes()
+                .iter()
+                .map(|suffix| domains::normalize_host(suffix))
+                .collect(),
+        }
+    }
+}
+
+///
+/// a rule condition on the local network, every field that's set has to hold
+/// eg. only while the vpn is connected
+///
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkCondition {
+    /// an interface with this name is up, `*` matches any run of characters, eg. "tun*"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interface: Option<String>,
+
+    /// one of this computer's addresses is in this range, eg. "10.20.0.0/16"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cidr: Option<String>,
+
+    /// a dns suffix this computer searches is this domain or under it, eg. "corp.example.com"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns_suffix: Option<String>,
+}
+
+impl NetworkCondition {
+    ///
+    /// Ok with why the condition holds, or Err with why it doesn't
+    ///
+    pub fn check(&self, network: &NetworkSnapshot) -> Result<String, String> {
+        let mut reasons = Vec::new();
+        if let Some(pattern) = &self.interface {
+            let interface = network
+                .interfaces
+                .iter()
+                .find(|interface| {
+                    glob_matches(&pattern.to_lowercase(), &interface.name.to_lowercase())
+                })
+                .ok_or_else(|| format!("no network interface matches \"{pattern}\""))?;
+            reasons.push(format!("interface \"{}\" is up", interface.name));
+        }
+
+        if let Some(cidr) = &self.cidr {
+            let (network_address, prefix_len) = parse_cidr(cidr)?;
+            let address = network
+                .interfaces
+                .iter()
+                .flat_map(|interface| &interface.addresses)
+                .find(|address| in_range(address, &network_address, prefix_len))
+                .ok_or_else(|| format!("no address in {cidr}"))?;
+            reasons.push(format!("{address} is in {cidr}"));
+        }
+
+        if let Some(dns_suffix) = &self.dns_suffix {
+            let suffix = network
+                .dns_suffixes
+                .iter()
+                .find(|suffix| domains::domain_matches(dns_suffix, suffix, true))
+                .ok_or_else(|| format!("no dns suffix under \"{dns_suffix}\""))?;
+            reasons.push(format!("dns suffix \"{suffix}\""));
+        }
+
+        Ok(reasons.join(", "))
+    }
+
+    /// checks something is set and the range parses
+    pub fn validate(&self) -> Result<(), String> {
+        if self.interface.is_none() && self.cidr.is_none() && self.dns_suffix.is_none() {
+            return Err("needs an interface, cidr or dns_suffix".to_string());
+        }
+        if let Some(cidr) = &self.cidr {
+            parse_cidr(cidr)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for NetworkCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(interface) = &self.interface {
+            parts.push(format!("interface \"{interface}\""));
+        }
+        if let Some(cidr) = &self.cidr {
+            parts.push(format!("address in {cidr}"));
+        }
+        if let Some(dns_suffix) = &self.dns_suffix {
+            parts.push(format!("dns suffix \"{dns_suffix}\""));
+        }
+        write!(f, "{}", parts.join(" and "))
+    }
+}
+
+/// "10.20.0.0/16" or "fd00::/8", a bare address is a range of one
+fn parse_cidr(cidr: &str) -> Result<(IpAddr, u8), String> {
+    let invalid = || format!("\"{cidr}\" isn't an address range like \"10.20.0.0/16\"");
+    let (address, prefix_len) = match cidr.split_once('/') {
+        Some((address, prefix_len)) => (address, Some(prefix_len)),
+        None => (cidr, None),
+    };
+
+    let address: IpAddr = address.trim().parse().map_err(|_| invalid())?;
+    let max_len = if address.is_ipv4() { 32 } else { 128 };
+    let prefix_len = match prefix_len {
+        Some(prefix_len) => prefix_len.trim().parse().map_err(|_| invalid())?,
+        None => max_len,
+    };
+    if prefix_len > max_len {
+        return Err(invalid());
+    }
+    Ok((address, prefix_len))
+}
+
+fn in_range(address: &IpAddr, network_address: &IpAddr, prefix_len: u8) -> bool {
+    let (address, network_address, bits) = match (address, network_address) {
+        (IpAddr::V4(address), IpAddr::V4(network_address)) => (
+            u32::from(*address) as u128,
+            u32::from(*network_address) as u128,
+            32,
+        ),
+        (IpAddr::V6(address), IpAddr::V6(network_address)) => {
+            (u128::from(*address), u128::from(*network_address), 128)
+        }
+        _ => return false,
+    };
+
+    let host_bits = bits - prefix_len as u32;
+    host_bits >= bits || (address >> host_bits) == (network_address >> host_bits)
+}
+
+/// the `search` and `domain` lines of /etc/resolv.conf
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_resolv_conf(resolv_conf: &str) -> Vec<String> {
+    resolv_conf
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("search") | Some("domain") => Some(words),
+                _ => None,
+            }
+        })
+        .flatten()
+        .map(String::from)
+        .collect()
+}
+
+/// the primary and connection specific suffixes and the search list, as ipconfig /all shows them
+#[cfg(windows)]
+fn windows_dns_suffixes() -> Vec<String> {
+    use registry::{Hive, RegKey, Security};
+    use utfx::U16CString;
+
+    const TCPIP_PARAMETERS: &str = r"SYSTEM\CurrentControlSet\Services\Tcpip\Parameters";
+
+    let mut suffixes = Vec::new();
+    let mut read = |key: &RegKey| {
+        for name in ["Domain", "DhcpDomain", "SearchList"] {
+            let Ok(name) = U16CString::from_os_str(name) else {
+                continue;
+            };
+            if let Ok(value) = key.value(name) {
+                suffixes.extend(
+                    value
+                        .to_string()
+                        .split([',', ' '])
+                        .filter(|suffix| !suffix.is_empty())
+                        .map(String::from),
+                );
+            }
+        }
+    };
+
+    if let Ok(parameters) = Hive::LocalMachine.open(TCPIP_PARAMETERS, Security::Read) {
+        read(&parameters);
+        if let Ok(interfaces) = parameters.open("Interfaces", Security::Read) {
+            for interface in interfaces.keys().flatten() {
+                if let Ok(interface) = interface.open(Security::Read) {
+                    read(&interface);
+                }
+            }
+        }
+    }
+
+    suffixes.sort();
+    suffixes.dedup();
+    suffixes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn on_vpn() -> NetworkSnapshot {
+        NetworkSnapshot::read(&FakeNetwork {
+            interfaces: vec![
+                ("eth0", "192.168.1.20"),
+                ("tun0", "10.20.3.4"),
+                ("tun0", "fd00::4"),
+            ],
+            dns_suffixes: vec!["Eu.Corp.Example.com.", "home.arpa"],
+        })
+    }
+
+    #[test]
+    fn conditions_on_the_vpn() {
+        let network = on_vpn();
+
+        let condition = NetworkCondition {
+            interface: Some("tun*".to_string()),
+            cidr: Some("10.20.0.0/16".to_string()),
+            dns_suffix: Some("corp.example.com".to_string()),
+        };
+        assert_eq!(
+            condition.check(&network),
+            Ok(
+                "interface \"tun0\" is up, 10.20.3.4 is in 10.20.0.0/16, dns suffix \"eu.corp.example.com\""
+                    .to_string()
+            )
+        );
+
+        let ipv6 = NetworkCondition {
+            cidr: Some("fd00::/8".to_string()),
+            ..Default::default()
+        };
+        assert!(ipv6.check(&network).is_ok());
+    }
+
+    #[test]
+    fn conditions_off_the_vpn() {
+        let network = NetworkSnapshot::read(&FakeNetwork {
+            interfaces: vec![("eth0", "192.168.1.20")],
+            dns_suffixes: vec!["home.arpa"],
+        });
+
+        let interface = NetworkCondition {
+            interface: Some("tun*".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            interface.check(&network),
+            Err("no network interface matches \"tun*\"".to_string())
+        );
+
+        let cidr = NetworkCondition {
+            cidr: Some("10.20.0.0/16".to_string()),
+            ..Default::default()
+        };
+        assert!(cidr.check(&network).is_err());
+
+        let dns_suffix = NetworkCondition {
+            dns_suffix: Some("corp.example.com".to_string()),
+            ..Default::default()
+        };
+        assert!(dns_suffix.check(&network).is_err());
+    }
+
+    #[test]
+    fn cidr_ranges() {
+        let range = |cidr: &str, address: &str| {
+            let (network_address, prefix_len) = parse_cidr(cidr).unwrap();
+            in_range(&address.parse().unwrap(), &network_address, prefix_len)
+        };
+        assert!(range("10.0.0.0/8", "10.255.0.1"));
+        assert!(!range("10.0.0.0/8", "11.0.0.1"));
+        assert!(range("0.0.0.0/0", "8.8.8.8"));
+        assert!(range("192.168.1.5", "192.168.1.5"));
+        assert!(!range("10.0.0.0/8", "::ffff:10.0.0.1"));
+        assert!(parse_cidr("10.0.0.0/33").is_err());
+        assert!(parse_cidr("intranet").is_err());
+        assert!(NetworkCondition::default().validate().is_err());
+    }
+
+    #[test]
+    fn resolv_conf_search_domains() {
+        let resolv_conf = "# generated\nnameserver 10.20.0.1\nsearch corp.example.com example.com\ndomain lab.example\n";
+        assert_eq!(
+            parse_resolv_conf(resolv_conf),
+            ["corp.example.com", "example.com", "lab.example"]
+        );
+    }
+}
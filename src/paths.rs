@@ -0,0 +1,103 @@
+use std::env;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::registry_utils::Browser;
+
+const LOCALAPPDATA: &str = "LOCALAPPDATA";
+const CHROME_VALET_HOME: &str = "CHROME_VALET_HOME";
+const PROGRAM_NAME: &str = "ChromeValet";
+
+/// a file with this name next to the executable turns on portable mode
+const PORTABLE_MARKER: &str = "chromevalet.portable";
+
+///
+/// where chrome valet reads and writes files
+///
+#[derive(Debug, Clone)]
+pub struct Paths {
+    /// stands in for %LOCALAPPDATA%, browser user data dirs are found under it
+    pub data_root: PathBuf,
+
+    /// prefs, config, logs and the rest of chrome valet's own files
+    pub app_data_dir: PathBuf,
+
+    /// why the data root fell back to a guess, resolved before logging's up so main warns about it
+    pub fallback_reason: Option<String>,
+}
+
+static PATHS: OnceLock<Paths> = OnceLock::new();
+
+impl Paths {
+    ///
+    /// the data root is `data_root` (--data-root), then CHROME_VALET_HOME, then %LOCALAPPDATA%
+    /// portable mode keeps chrome valet's own files next to the executable
+    ///
+    pub fn resolve(data_root: Option<PathBuf>, portable: bool) -> Self {
+        let mut fallback_reason = None;
+        let data_root = data_root
+            .or_else(|| env::var_os(CHROME_VALET_HOME).map(PathBuf::from))
+            .or_else(|| env::var_os(LOCALAPPDATA).map(PathBuf::from))
+            .unwrap_or_else(|| {
+                let fallback = Self::fallback_data_root();
+                fallback_reason = Some(format!(
+                    "{LOCALAPPDATA} not set, using {}",
+                    fallback.display()
+                ));
+                fallback
+            });
+
+        let exe_dir = env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(PathBuf::from));
+        let portable_dir = exe_dir.filter(|dir| portable || dir.join(PORTABLE_MARKER).exists());
+
+        Paths {
+            app_data_dir: portable_dir.unwrap_or_else(|| data_root.join(PROGRAM_NAME)),
+            data_root,
+            fallback_reason,
+        }
+    }
+
+    /// %LOCALAPPDATA% isn't set, eg. not on windows
+    fn fallback_data_root() -> PathBuf {
+        match env::var_os("XDG_DATA_HOME") {
+            Some(dir) => PathBuf::from(dir),
+            None => match env::var_os("HOME") {
+                Some(home) => PathBuf::from(home).join(".local").join("share"),
+                None => env::temp_dir(),
+            },
+        }
+    }
+}
+
+///
+/// sets the paths for this process, only the first call has any effect
+///
+pub fn init(data_root: Option<PathBuf>, portable: bool) {
+    _ = PATHS.set(Paths::resolve(data_root, portable));
+}
+
+pub fn get() -> &'static Paths {
+    PATHS.get_or_init(|| Paths::resolve(None, false))
+}
+
+pub fn app_data_dir() -> PathBuf {
+    get().app_data_dir.clone()
+}
+
+///
+/// the browser's "User Data" dir, holding "Local State" and a directory per profile
+///
+pub fn browser_user_data_dir(browser: &Browser) -> PathBuf {
+    let browser = match browser.get_definition() {
+        Some(_) => *browser,
+        None => Browser::Chrome,
+    };
+
+    let mut user_data_dir = get().data_root.clone();
+    for part in browser.get_definition().unwrap().app_data_dir.split('\\') {
+        user_data_dir = user_data_dir.join(part);
+    }
+    user_data_dir.join("User Data")
+}
@@ -1,14 +1,170 @@
-use registry::{Hive, RegKey, Security};
+#[cfg(windows)]
+use registry::{Data, Hive, RegKey, Security};
+#[cfg(test)]
+use std::collections::HashMap;
 use std::fmt;
+#[cfg(windows)]
 use utfx::U16CString;
 
+quick_error! {
+    #[derive(Debug)]
+    pub enum RegistryError {
+        KeyNotFound(path: String) {
+            display("registry key not found: {}", path)
+        }
+        ValueNotFound(path: String, name: String) {
+            display("registry value \"{}\" not found in {}", name, path)
+        }
+        Other(path: String, err: String) {
+            display("registry error at {}: {}", path, err)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegHive {
+    CurrentUser,
+    LocalMachine,
+}
+
+///
+/// registry reads, so lookups can run against a fake
+/// an empty value name is the key's default value
+///
+pub trait RegistryReader {
+    fn get_string(&self, hive: RegHive, path: &str, name: &str) -> Result<String, RegistryError>;
+}
+
+///
+/// the registry writes chrome valet makes, so registration can run against a fake
+/// an empty value name is the key's default value
+///
+#[cfg_attr(not(windows), allow(dead_code))]
+pub trait RegistryWriter {
+    fn set_string(
+        &mut self,
+        hive: RegHive,
+        path: &str,
+        name: &str,
+        value: &str,
+    ) -> Result<(), RegistryError>;
+    fn delete_key(&mut self, hive: RegHive, path: &str) -> Result<(), RegistryError>;
+    fn delete_value(&mut self, hive: RegHive, path: &str, name: &str) -> Result<(), RegistryError>;
+}
+
+/// the real registry, through the registry crate
+#[cfg(windows)]
+pub struct SystemRegistry;
+
+#[cfg(windows)]
+impl SystemRegistry {
+    fn hive(hive: RegHive) -> Hive {
+        match hive {
+            RegHive::CurrentUser => Hive::CurrentUser,
+            RegHive::LocalMachine => Hive::LocalMachine,
+        }
+    }
+
+    fn value_name(name: &str) -> U16CString {
+        U16CString::from_os_str(name).unwrap_or_default()
+    }
+}
+
+#[cfg(windows)]
+impl RegistryReader for SystemRegistry {
+    fn get_string(&self, hive: RegHive, path: &str, name: &str) -> Result<String, RegistryError> {
+        let regkey: RegKey = match Self::hive(hive).open(path, Security::Read) {
+            Ok(regkey) => regkey,
+            Err(registry::key::Error::NotFound(_, _)) => {
+                return Err(RegistryError::KeyNotFound(path.to_string()))
+            }
+            Err(e) => return Err(RegistryError::Other(path.to_string(), e.to_string())),
+        };
+
+        match regkey.value(Self::value_name(name)) {
+            Ok(v) => Ok(v.to_string()),
+            Err(registry::value::Error::NotFound(_, _)) => Err(RegistryError::ValueNotFound(
+                path.to_string(),
+                name.to_string(),
+            )),
+            Err(e) => Err(RegistryError::Other(path.to_string(), e.to_string())),
+        }
+    }
+}
+
+/// there's no registry off windows, every key is missing
+#[cfg(not(windows))]
+pub struct SystemRegistry;
+
+#[cfg(not(windows))]
+impl RegistryReader for SystemRegistry {
+    fn get_string(&self, _hive: RegHive, path: &str, _name: &str) -> Result<String, RegistryError> {
+        Err(RegistryError::KeyNotFound(path.to_string()))
+    }
+}
+
+#[cfg(windows)]
+impl RegistryWriter for SystemRegistry {
+    fn set_string(
+        &mut self,
+        hive: RegHive,
+        path: &str,
+        name: &str,
+        value: &str,
+    ) -> Result<(), RegistryError> {
+        let other = |e: &dyn fmt::Display| RegistryError::Other(path.to_string(), e.to_string());
+        let regkey = Self::hive(hive)
+            .create(path, Security::Read | Security::Write)
+            .map_err(|e| other(&e))?;
+        let data = Data::String(U16CString::from_os_str(value).map_err(|e| other(&e))?);
+        regkey
+            .set_value(Self::value_name(name), &data)
+            .map_err(|e| other(&e))
+    }
+
+    fn delete_key(&mut self, hive: RegHive, path: &str) -> Result<(), RegistryError> {
+        match Self::hive(hive).delete(path, true) {
+            Ok(()) => Ok(()),
+            Err(registry::key::Error::NotFound(_, _)) => {
+                Err(RegistryError::KeyNotFound(path.to_string()))
+            }
+            Err(e) => Err(RegistryError::Other(path.to_string(), e.to_string())),
+        }
+    }
+
+    fn delete_value(&mut self, hive: RegHive, path: &str, name: &str) -> Result<(), RegistryError> {
+        let regkey = match Self::hive(hive).open(path, Security::Read | Security::Write) {
+            Ok(regkey) => regkey,
+            Err(registry::key::Error::NotFound(_, _)) => {
+                return Err(RegistryError::KeyNotFound(path.to_string()))
+            }
+            Err(e) => return Err(RegistryError::Other(path.to_string(), e.to_string())),
+        };
+
+        match regkey.delete_value(Self::value_name(name)) {
+            Ok(()) => Ok(()),
+            Err(registry::value::Error::NotFound(_, _)) => Err(RegistryError::ValueNotFound(
+                path.to_string(),
+                name.to_string(),
+            )),
+            Err(e) => Err(RegistryError::Other(path.to_string(), e.to_string())),
+        }
+    }
+}
+
+/// name chrome valet registers under in StartMenuInternet and RegisteredApplications
+pub const REGISTERED_APP_NAME: &str = "ChromeValet";
+
+/// ProgID links are sent to when chrome valet is the default browser
+pub const URL_PROG_ID: &str = "ChromeValetURL";
+
 pub struct BrowserDefinition {
     pub browser_exe: String,
     pub url_class_name: String,
     pub app_data_dir: String,
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum Browser {
     Chrome,
     Brave,
@@ -52,65 +208,856 @@ impl Browser {
     }
 }
 
-pub fn get_browser_exe(browser_type: &Browser) -> Result<String, registry::Error> {
+const USER_CHOICE_HTTP_KEY: &str =
+    r"Software\Microsoft\Windows\Shell\Associations\UrlAssociations\http\UserChoice";
+
+///
+/// the executable registered in App Paths for `browser_type`
+/// per user installs register in HKCU, so that's checked before HKLM
+///
+pub fn get_browser_exe_from(
+    registry: &impl RegistryReader,
+    browser_type: &Browser,
+) -> Result<String, RegistryError> {
     let mut browser_exe = Browser::Chrome.get_definition().unwrap().browser_exe;
     if let Some(browser_def) = browser_type.get_definition() {
         browser_exe = browser_def.browser_exe;
     }
 
-    let regkey: RegKey = Hive::LocalMachine.open(
-        format!("SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\App Paths\\{browser_exe}"),
-        Security::Read,
-    )?;
-    let value: String = match regkey.value(U16CString::default()) {
-        Err(e) => return Err(registry::Error::Value(e)),
-        Ok(v) => v.to_string(),
-    };
-
-    Ok(value)
+    let app_path =
+        format!("SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\App Paths\\{browser_exe}");
+    match registry.get_string(RegHive::CurrentUser, &app_path, "") {
+        Err(RegistryError::KeyNotFound(_)) | Err(RegistryError::ValueNotFound(_, _)) => {
+            registry.get_string(RegHive::LocalMachine, &app_path, "")
+        }
+        result => result,
+    }
 }
 
 #[allow(dead_code)]
-pub fn get_browser_launch_command(browser_type: &Browser) -> Result<String, registry::Error> {
+pub fn get_browser_launch_command_from(
+    registry: &impl RegistryReader,
+    browser_type: &Browser,
+) -> Result<String, RegistryError> {
     let mut url_class_name = Browser::Chrome.get_definition().unwrap().url_class_name;
     if let Some(browser_def) = browser_type.get_definition() {
         url_class_name = browser_def.url_class_name;
     }
 
-    let regkey: RegKey = Hive::LocalMachine.open(
-        format!("Software\\Classes\\{url_class_name}\\shell\\open\\command"),
-        Security::Read,
+    registry.get_string(
+        RegHive::LocalMachine,
+        &format!("Software\\Classes\\{url_class_name}\\shell\\open\\command"),
+        "",
+    )
+}
+
+pub fn is_default_browser_from(registry: &impl RegistryReader) -> Result<bool, RegistryError> {
+    let prog_id = registry.get_string(RegHive::CurrentUser, USER_CHOICE_HTTP_KEY, "ProgID")?;
+    Ok(prog_id == URL_PROG_ID)
+}
+
+///
+/// the browser the user picked for http links
+/// ProgIDs can carry a suffix per install, eg "ChromeHTML.ABCDEFGHIJKL"
+///
+pub fn get_default_browser_from(
+    registry: &impl RegistryReader,
+) -> Result<Option<Browser>, RegistryError> {
+    let prog_id = registry.get_string(RegHive::CurrentUser, USER_CHOICE_HTTP_KEY, "ProgID")?;
+    let prog_id_base = prog_id.split('.').next().unwrap_or_default();
+
+    for browser in [Browser::Chrome, Browser::Brave] {
+        if prog_id_base.eq_ignore_ascii_case(&browser.get_definition().unwrap().url_class_name) {
+            return Ok(Some(browser));
+        }
+    }
+
+    Ok(None)
+}
+
+///
+/// the command windows runs for links sent to chrome valet's ProgID
+///
+pub fn get_url_handler_command_from(
+    registry: &impl RegistryReader,
+) -> Result<String, RegistryError> {
+    registry.get_string(
+        RegHive::CurrentUser,
+        &format!("{}\\shell\\open\\command", prog_id_key()),
+        "",
+    )
+}
+
+///
+/// the ProgID chrome valet's capabilities send `scheme` links to
+///
+pub fn get_url_association_from(
+    registry: &impl RegistryReader,
+    scheme: &str,
+) -> Result<String, RegistryError> {
+    registry.get_string(
+        RegHive::CurrentUser,
+        &format!(
+            "{}\\Capabilities\\URLAssociations",
+            start_menu_internet_key()
+        ),
+        scheme,
+    )
+}
+
+pub fn get_browser_exe(browser_type: &Browser) -> Result<String, RegistryError> {
+    get_browser_exe_from(&SystemRegistry, browser_type)
+}
+
+#[allow(dead_code)]
+pub fn get_browser_launch_command(browser_type: &Browser) -> Result<String, RegistryError> {
+    get_browser_launch_command_from(&SystemRegistry, browser_type)
+}
+
+pub fn is_default_browser() -> Result<bool, RegistryError> {
+    is_default_browser_from(&SystemRegistry)
+}
+
+pub fn get_default_browser() -> Result<Option<Browser>, RegistryError> {
+    get_default_browser_from(&SystemRegistry)
+}
+
+fn start_menu_internet_key() -> String {
+    format!("Software\\Clients\\StartMenuInternet\\{REGISTERED_APP_NAME}")
+}
+
+fn prog_id_key() -> String {
+    format!("Software\\Classes\\{URL_PROG_ID}")
+}
+
+///
+/// the command line links are sent to, `args` go between `exe` and the url
+///
+pub fn handler_command(exe: &str, args: &[String], url: &str) -> String {
+    let mut command = format!("\"{exe}\"");
+    for arg in args {
+        command += " ";
+        command += &quote_arg(arg);
+    }
+    command + " --url " + url
+}
+
+///
+/// `arg` in double quotes, escaped the way windows splits a command line
+/// backslashes are only special before a quote, so those, and the ones before the closing quote, are doubled
+///
+fn quote_arg(arg: &str) -> String {
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+        let escapes = if c == '"' {
+            backslashes * 2 + 1
+        } else {
+            backslashes
+        };
+        quoted += &"\\".repeat(escapes);
+        quoted.push(c);
+        backslashes = 0;
+    }
+    quoted += &"\\".repeat(backslashes * 2);
+    quoted.push('"');
+    quoted
+}
+
+///
+/// the executable a registered command line runs, eg. `"C:\valet\chrome_valet.exe" --url "%1"`
+/// the installer writes it unquoted, so an unquoted path runs to the end of its .exe
+///
+pub fn command_exe(command: &str) -> &str {
+    if let Some(quoted) = command.strip_prefix('"') {
+        return quoted.split('"').next().unwrap_or_default();
+    }
+
+    // ascii lowercasing keeps byte offsets the same
+    let exe_end = command
+        .to_ascii_lowercase()
+        .match_indices(".exe")
+        .map(|(i, _)| i + ".exe".len())
+        .find(|&end| command[end..].is_empty() || command[end..].starts_with(' '));
+    let end = exe_end
+        .or_else(|| command.find(" --url"))
+        .unwrap_or(command.len());
+    &command[..end]
+}
+
+///
+/// registers `exe` as a browser for the current user, the same keys the installer writes
+/// `args` are passed before the url, eg. --portable
+/// windows still asks the user to pick it as the default browser
+///
+#[cfg_attr(not(windows), allow(dead_code))]
+pub fn register_url_handler(
+    registry: &mut impl RegistryWriter,
+    exe: &str,
+    args: &[String],
+) -> Result<(), RegistryError> {
+    let hkcu = RegHive::CurrentUser;
+    let app_key = start_menu_internet_key();
+    let prog_id_key = prog_id_key();
+    let icon = format!("{exe},0");
+
+    let capabilities_key = format!("{app_key}\\Capabilities");
+    registry.set_string(
+        hkcu,
+        &capabilities_key,
+        "ApplicationDescription",
+        REGISTERED_APP_NAME,
+    )?;
+    registry.set_string(hkcu, &capabilities_key, "ApplicationIcon", &icon)?;
+    registry.set_string(hkcu, &capabilities_key, "ApplicationName", "Chrome Valet")?;
+
+    let url_associations_key = format!("{capabilities_key}\\URLAssociations");
+    for scheme in ["http", "https", "ftp"] {
+        registry.set_string(hkcu, &url_associations_key, scheme, URL_PROG_ID)?;
+    }
+
+    registry.set_string(hkcu, &format!("{app_key}\\DefaultIcon"), "", &icon)?;
+    registry.set_string(hkcu, &format!("{app_key}\\shell\\open\\command"), "", exe)?;
+
+    registry.set_string(
+        hkcu,
+        &prog_id_key,
+        "",
+        &format!("{REGISTERED_APP_NAME} URL"),
     )?;
-    let value: String = match regkey.value(U16CString::default()) {
-        Err(e) => return Err(registry::Error::Value(e)),
-        Ok(v) => v.to_string(),
+    registry.set_string(
+        hkcu,
+        &prog_id_key,
+        "FriendlyTypeName",
+        &format!("{REGISTERED_APP_NAME} URL"),
+    )?;
+    registry.set_string(hkcu, &prog_id_key, "URL Protocol", "")?;
+
+    let application_key = format!("{prog_id_key}\\Application");
+    registry.set_string(
+        hkcu,
+        &application_key,
+        "ApplicationDescription",
+        REGISTERED_APP_NAME,
+    )?;
+    registry.set_string(hkcu, &application_key, "ApplicationIcon", &icon)?;
+    registry.set_string(hkcu, &application_key, "ApplicationName", "Chrome Valet")?;
+
+    registry.set_string(hkcu, &format!("{prog_id_key}\\DefaultIcon"), "", &icon)?;
+    registry.set_string(
+        hkcu,
+        &format!("{prog_id_key}\\shell\\open\\command"),
+        "",
+        &handler_command(exe, args, "\"%1\""),
+    )?;
+
+    registry.set_string(
+        hkcu,
+        "Software\\RegisteredApplications",
+        REGISTERED_APP_NAME,
+        &capabilities_key,
+    )?;
+
+    Ok(())
+}
+
+///
+/// removes what register_url_handler wrote, anything already gone is skipped
+///
+#[cfg_attr(not(windows), allow(dead_code))]
+pub fn unregister_url_handler(registry: &mut impl RegistryWriter) -> Result<(), RegistryError> {
+    let hkcu = RegHive::CurrentUser;
+    let results = [
+        registry.delete_value(
+            hkcu,
+            "Software\\RegisteredApplications",
+            REGISTERED_APP_NAME,
+        ),
+        registry.delete_key(hkcu, &prog_id_key()),
+        registry.delete_key(hkcu, &start_menu_internet_key()),
+    ];
+
+    for result in results {
+        match result {
+            Ok(())
+            | Err(RegistryError::KeyNotFound(_))
+            | Err(RegistryError::ValueNotFound(_, _)) => (),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+pub const DESKTOP_FILE_NAME: &str = "chrome-valet.desktop";
+
+/// where the current user's .desktop entries live
+#[cfg(target_os = "linux")]
+pub fn applications_dir() -> std::path::PathBuf {
+    let data_home = match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) => std::path::PathBuf::from(dir),
+        None => std::path::PathBuf::from(std::env::var_os("HOME").unwrap_or_default())
+            .join(".local")
+            .join("share"),
     };
+    data_home.join("applications")
+}
 
-    Ok(value)
+///
+/// the .desktop entry that makes chrome valet a browser on linux
+///
+#[cfg(target_os = "linux")]
+pub fn desktop_entry(exe: &str, args: &[String]) -> String {
+    let command = handler_command(exe, args, "%u");
+    format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name=Chrome Valet\n\
+         Comment=Open links in a preferred browser profile\n\
+         Exec={command}\n\
+         Categories=Network;WebBrowser;\n\
+         MimeType=x-scheme-handler/http;x-scheme-handler/https;\n\
+         NoDisplay=true\n"
+    )
 }
 
-pub fn is_default_browser() -> Result<bool, registry::Error> {
-    let regkey = Hive::CurrentUser.open(
-        r"Software\Microsoft\Windows\Shell\Associations\UrlAssociations\http\UserChoice",
-        Security::Read,
-    )?;
-    match regkey.value(U16CString::from_os_str("ProgID").unwrap()) {
-        Err(e) => Err(registry::Error::Value(e)),
-        Ok(v) => Ok(v.to_string() == "ChromeValetURL"),
+///
+/// writes the .desktop entry and makes it the http/https handler with xdg-mime
+///
+#[cfg(target_os = "linux")]
+pub fn register_desktop_entry(
+    applications_dir: &std::path::Path,
+    exe: &str,
+    args: &[String],
+) -> Result<(), anyhow::Error> {
+    use anyhow::{bail, Context};
+    use std::process::Command;
+
+    std::fs::create_dir_all(applications_dir)?;
+    let desktop_file = applications_dir.join(DESKTOP_FILE_NAME);
+    std::fs::write(&desktop_file, desktop_entry(exe, args))
+        .with_context(|| format!("couldn't write {}", desktop_file.display()))?;
+
+    for mime_type in ["x-scheme-handler/http", "x-scheme-handler/https"] {
+        let status = Command::new("xdg-mime")
+            .args(["default", DESKTOP_FILE_NAME, mime_type])
+            .status()
+            .context("couldn't run xdg-mime")?;
+        if !status.success() {
+            bail!("xdg-mime default {DESKTOP_FILE_NAME} {mime_type} failed: {status}");
+        }
     }
+
+    Ok(())
 }
 
-pub fn get_default_browser() -> Result<Option<Browser>, registry::Error> {
-    let regkey = Hive::CurrentUser.open(
-        r"Software\Microsoft\Windows\Shell\Associations\UrlAssociations\http\UserChoice",
-        Security::Read,
-    )?;
-    match regkey.value(U16CString::from_os_str("ProgID").unwrap()) {
-        Err(e) => Err(registry::Error::Value(e)),
-        Ok(v) => match v.to_string().as_str() {
-            "ChromeHTML" => Ok(Some(Browser::Chrome)),
-            "BraveHTML" => Ok(Some(Browser::Brave)),
-            _ => Ok(None),
-        },
+///
+/// the .desktop file xdg opens `mime_type` with, empty if there's none
+///
+#[cfg(target_os = "linux")]
+pub fn get_xdg_default(mime_type: &str) -> Result<String, anyhow::Error> {
+    use anyhow::{bail, Context};
+
+    let output = std::process::Command::new("xdg-mime")
+        .args(["query", "default", mime_type])
+        .output()
+        .context("couldn't run xdg-mime")?;
+    if !output.status.success() {
+        bail!(
+            "xdg-mime query default {mime_type} failed: {}",
+            output.status
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+///
+/// the command the .desktop entry runs, None if there isn't one
+///
+#[cfg(target_os = "linux")]
+pub fn get_desktop_entry_command(applications_dir: &std::path::Path) -> Option<String> {
+    let entry = std::fs::read_to_string(applications_dir.join(DESKTOP_FILE_NAME)).ok()?;
+    entry
+        .lines()
+        .find_map(|line| line.strip_prefix("Exec="))
+        .map(String::from)
+}
+
+/// the mimeapps.list xdg-mime writes the current user's defaults to
+#[cfg(target_os = "linux")]
+pub fn mimeapps_list() -> std::path::PathBuf {
+    let config_home = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => std::path::PathBuf::from(dir),
+        None => {
+            std::path::PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(".config")
+        }
+    };
+    config_home.join("mimeapps.list")
+}
+
+///
+/// mimeapps.list without chrome valet as a handler for http/https
+/// a line left with no handlers is dropped, so xdg falls back to another browser
+///
+#[cfg(target_os = "linux")]
+pub fn without_mime_defaults(mimeapps: &str) -> String {
+    let mut kept = String::new();
+    for line in mimeapps.lines() {
+        let handled = line.split_once('=').filter(|(mime_type, _)| {
+            matches!(
+                mime_type.trim(),
+                "x-scheme-handler/http" | "x-scheme-handler/https"
+            )
+        });
+
+        let Some((mime_type, handlers)) = handled else {
+            kept += line;
+            kept += "\n";
+            continue;
+        };
+
+        let others: Vec<&str> = handlers
+            .split(';')
+            .map(str::trim)
+            .filter(|handler| !handler.is_empty() && *handler != DESKTOP_FILE_NAME)
+            .collect();
+        if !others.is_empty() {
+            kept += &format!("{mime_type}={};\n", others.join(";"));
+        }
+    }
+    kept
+}
+
+///
+/// removes the .desktop entry and the xdg-mime defaults register_desktop_entry set
+/// xdg falls back to another browser
+///
+#[cfg(target_os = "linux")]
+pub fn unregister_desktop_entry(
+    applications_dir: &std::path::Path,
+    mimeapps_list: &std::path::Path,
+) -> Result<(), anyhow::Error> {
+    let desktop_file = applications_dir.join(DESKTOP_FILE_NAME);
+    match std::fs::remove_file(&desktop_file) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => (),
+    }
+
+    match std::fs::read_to_string(mimeapps_list) {
+        Ok(mimeapps) => {
+            let kept = without_mime_defaults(&mimeapps);
+            if kept != mimeapps {
+                std::fs::write(mimeapps_list, kept)?;
+            }
+            Ok(())
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+///
+/// in-memory registry for tests, paths and value names are case insensitive like the real one
+///
+#[cfg(test)]
+#[derive(Default)]
+pub struct FakeRegistry {
+    keys: HashMap<(RegHive, String), HashMap<String, String>>,
+}
+
+#[cfg(test)]
+impl FakeRegistry {
+    fn key(hive: RegHive, path: &str) -> (RegHive, String) {
+        (hive, path.to_lowercase())
+    }
+
+    pub fn with_string(mut self, hive: RegHive, path: &str, name: &str, value: &str) -> Self {
+        self.set_string(hive, path, name, value).unwrap();
+        self
+    }
+}
+
+#[cfg(test)]
+impl RegistryReader for FakeRegistry {
+    fn get_string(&self, hive: RegHive, path: &str, name: &str) -> Result<String, RegistryError> {
+        let values = self
+            .keys
+            .get(&Self::key(hive, path))
+            .ok_or_else(|| RegistryError::KeyNotFound(path.to_string()))?;
+        values
+            .get(&name.to_lowercase())
+            .cloned()
+            .ok_or_else(|| RegistryError::ValueNotFound(path.to_string(), name.to_string()))
+    }
+}
+
+#[cfg(test)]
+impl RegistryWriter for FakeRegistry {
+    fn set_string(
+        &mut self,
+        hive: RegHive,
+        path: &str,
+        name: &str,
+        value: &str,
+    ) -> Result<(), RegistryError> {
+        self.keys
+            .entry(Self::key(hive, path))
+            .or_default()
+            .insert(name.to_lowercase(), value.to_string());
+        Ok(())
+    }
+
+    fn delete_key(&mut self, hive: RegHive, path: &str) -> Result<(), RegistryError> {
+        let (hive, path) = Self::key(hive, path);
+        let subkey_prefix = format!("{path}\\");
+        let count_before = self.keys.len();
+        self.keys.retain(|(key_hive, key_path), _| {
+            *key_hive != hive || (*key_path != path && !key_path.starts_with(&subkey_prefix))
+        });
+
+        if self.keys.len() == count_before {
+            return Err(RegistryError::KeyNotFound(path));
+        }
+        Ok(())
+    }
+
+    fn delete_value(&mut self, hive: RegHive, path: &str, name: &str) -> Result<(), RegistryError> {
+        let values = self
+            .keys
+            .get_mut(&Self::key(hive, path))
+            .ok_or_else(|| RegistryError::KeyNotFound(path.to_string()))?;
+        values
+            .remove(&name.to_lowercase())
+            .map(|_| ())
+            .ok_or_else(|| RegistryError::ValueNotFound(path.to_string(), name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHROME_APP_PATH: &str = r"SOFTWARE\Microsoft\Windows\CurrentVersion\App Paths\chrome.exe";
+
+    fn with_user_choice(prog_id: &str) -> FakeRegistry {
+        FakeRegistry::default().with_string(
+            RegHive::CurrentUser,
+            USER_CHOICE_HTTP_KEY,
+            "ProgID",
+            prog_id,
+        )
+    }
+
+    #[test]
+    fn browser_exe_missing_key() {
+        let registry = FakeRegistry::default();
+        assert!(matches!(
+            get_browser_exe_from(&registry, &Browser::Chrome),
+            Err(RegistryError::KeyNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn browser_exe_from_hklm() {
+        let registry = FakeRegistry::default().with_string(
+            RegHive::LocalMachine,
+            CHROME_APP_PATH,
+            "",
+            r"C:\Program Files\Google\Chrome\Application\chrome.exe",
+        );
+        assert_eq!(
+            get_browser_exe_from(&registry, &Browser::Chrome).unwrap(),
+            r"C:\Program Files\Google\Chrome\Application\chrome.exe"
+        );
+    }
+
+    #[test]
+    fn browser_exe_prefers_hkcu() {
+        let registry = FakeRegistry::default()
+            .with_string(
+                RegHive::LocalMachine,
+                CHROME_APP_PATH,
+                "",
+                r"C:\system\chrome.exe",
+            )
+            .with_string(
+                RegHive::CurrentUser,
+                CHROME_APP_PATH,
+                "",
+                r"C:\user\chrome.exe",
+            );
+        assert_eq!(
+            get_browser_exe_from(&registry, &Browser::Chrome).unwrap(),
+            r"C:\user\chrome.exe"
+        );
+    }
+
+    #[test]
+    fn browser_exe_key_without_default_value_falls_back_to_hklm() {
+        let registry = FakeRegistry::default()
+            .with_string(RegHive::CurrentUser, CHROME_APP_PATH, "Path", r"C:\user")
+            .with_string(
+                RegHive::LocalMachine,
+                CHROME_APP_PATH,
+                "",
+                r"C:\system\chrome.exe",
+            );
+        assert_eq!(
+            get_browser_exe_from(&registry, &Browser::Chrome).unwrap(),
+            r"C:\system\chrome.exe"
+        );
+    }
+
+    #[test]
+    fn browser_exe_for_brave() {
+        let registry = FakeRegistry::default().with_string(
+            RegHive::LocalMachine,
+            r"SOFTWARE\Microsoft\Windows\CurrentVersion\App Paths\brave.exe",
+            "",
+            r"C:\brave.exe",
+        );
+        assert_eq!(
+            get_browser_exe_from(&registry, &Browser::Brave).unwrap(),
+            r"C:\brave.exe"
+        );
+        assert!(get_browser_exe_from(&registry, &Browser::Chrome).is_err());
+    }
+
+    #[test]
+    fn default_browser_missing_user_choice() {
+        let registry = FakeRegistry::default();
+        assert!(matches!(
+            is_default_browser_from(&registry),
+            Err(RegistryError::KeyNotFound(_))
+        ));
+        assert!(get_default_browser_from(&registry).is_err());
+    }
+
+    #[test]
+    fn default_browser_is_chrome_valet() {
+        assert!(is_default_browser_from(&with_user_choice(URL_PROG_ID)).unwrap());
+        assert!(!is_default_browser_from(&with_user_choice("ChromeHTML")).unwrap());
+        assert_eq!(
+            get_default_browser_from(&with_user_choice(URL_PROG_ID)).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn default_browser_prog_ids() {
+        let cases = [
+            ("ChromeHTML", Some(Browser::Chrome)),
+            ("chromehtml", Some(Browser::Chrome)),
+            ("ChromeHTML.PR3C1S3WYTJ5", Some(Browser::Chrome)),
+            ("BraveHTML", Some(Browser::Brave)),
+            ("MSEdgeHTM", None),
+            ("FirefoxURL-308046B0AF4A39CB", None),
+            ("", None),
+        ];
+
+        for (prog_id, expected) in cases {
+            assert_eq!(
+                get_default_browser_from(&with_user_choice(prog_id)).unwrap(),
+                expected,
+                "{prog_id}"
+            );
+        }
+    }
+
+    #[test]
+    fn register_then_unregister() {
+        let mut registry = FakeRegistry::default();
+        register_url_handler(&mut registry, r"C:\valet\chrome_valet.exe", &[]).unwrap();
+
+        assert_eq!(
+            get_url_association_from(&registry, "https").unwrap(),
+            URL_PROG_ID
+        );
+        assert_eq!(
+            get_url_handler_command_from(&registry).unwrap(),
+            r#""C:\valet\chrome_valet.exe" --url "%1""#
+        );
+
+        unregister_url_handler(&mut registry).unwrap();
+        assert!(get_url_association_from(&registry, "https").is_err());
+        assert!(get_url_handler_command_from(&registry).is_err());
+
+        // nothing left to remove is fine
+        unregister_url_handler(&mut registry).unwrap();
+    }
+
+    #[test]
+    fn register_writes_capabilities_and_overwrites_the_exe() {
+        let mut registry = FakeRegistry::default();
+        register_url_handler(&mut registry, r"C:\old\chrome_valet.exe", &[]).unwrap();
+        register_url_handler(&mut registry, r"C:\valet\chrome_valet.exe", &[]).unwrap();
+
+        let hkcu = RegHive::CurrentUser;
+        let capabilities_key = format!("{}\\Capabilities", start_menu_internet_key());
+        assert_eq!(
+            registry
+                .get_string(
+                    hkcu,
+                    "Software\\RegisteredApplications",
+                    REGISTERED_APP_NAME
+                )
+                .unwrap(),
+            capabilities_key
+        );
+        assert_eq!(
+            registry
+                .get_string(hkcu, &capabilities_key, "ApplicationIcon")
+                .unwrap(),
+            r"C:\valet\chrome_valet.exe,0"
+        );
+        for scheme in ["http", "https", "ftp"] {
+            assert_eq!(
+                get_url_association_from(&registry, scheme).unwrap(),
+                URL_PROG_ID
+            );
+        }
+        assert_eq!(
+            get_url_handler_command_from(&registry).unwrap(),
+            r#""C:\valet\chrome_valet.exe" --url "%1""#
+        );
+    }
+
+    #[test]
+    fn unregister_leaves_other_applications_alone() {
+        let hkcu = RegHive::CurrentUser;
+        let mut registry = FakeRegistry::default()
+            .with_string(
+                hkcu,
+                "Software\\RegisteredApplications",
+                "Firefox",
+                r"Software\Clients\Firefox",
+            )
+            .with_string(
+                hkcu,
+                "Software\\Classes\\ChromeValetURLOther",
+                "",
+                "another app",
+            );
+        register_url_handler(&mut registry, r"C:\valet\chrome_valet.exe", &[]).unwrap();
+
+        unregister_url_handler(&mut registry).unwrap();
+
+        assert!(registry
+            .get_string(
+                hkcu,
+                "Software\\RegisteredApplications",
+                REGISTERED_APP_NAME
+            )
+            .is_err());
+        assert_eq!(
+            registry
+                .get_string(hkcu, "Software\\RegisteredApplications", "Firefox")
+                .unwrap(),
+            r"Software\Clients\Firefox"
+        );
+        assert_eq!(
+            registry
+                .get_string(hkcu, "Software\\Classes\\ChromeValetURLOther", "")
+                .unwrap(),
+            "another app"
+        );
+    }
+
+    #[test]
+    fn command_exe_is_the_first_argument() {
+        assert_eq!(
+            command_exe(r#""C:\Program Files\Chrome Valet\chrome_valet.exe" --url "%1""#),
+            r"C:\Program Files\Chrome Valet\chrome_valet.exe"
+        );
+        assert_eq!(command_exe(r"C:\valet.exe --url %1"), r"C:\valet.exe");
+    }
+
+    #[test]
+    fn command_exe_unquoted_with_spaces() {
+        // what the installer writes
+        assert_eq!(
+            command_exe(r#"C:\Program Files\Chrome Valet\chrome_valet.exe --url "%1""#),
+            r"C:\Program Files\Chrome Valet\chrome_valet.exe"
+        );
+        assert_eq!(
+            command_exe(r"C:\Program Files\Chrome Valet\chrome_valet.EXE"),
+            r"C:\Program Files\Chrome Valet\chrome_valet.EXE"
+        );
+        assert_eq!(
+            command_exe("/opt/chrome valet/chrome_valet --url %u"),
+            "/opt/chrome valet/chrome_valet"
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn unregister_removes_mime_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let applications_dir = dir.path().join("applications");
+        let mimeapps_list = dir.path().join("mimeapps.list");
+        std::fs::create_dir_all(&applications_dir).unwrap();
+        std::fs::write(applications_dir.join(DESKTOP_FILE_NAME), "").unwrap();
+        std::fs::write(
+            &mimeapps_list,
+            "[Default Applications]\n\
+             x-scheme-handler/http=chrome-valet.desktop\n\
+             x-scheme-handler/https=chrome-valet.desktop;firefox.desktop;\n\
+             text/html=firefox.desktop\n\
+             \n\
+             [Added Associations]\n\
+             x-scheme-handler/https=chrome-valet.desktop;\n",
+        )
+        .unwrap();
+
+        unregister_desktop_entry(&applications_dir, &mimeapps_list).unwrap();
+
+        assert!(!applications_dir.join(DESKTOP_FILE_NAME).exists());
+        assert_eq!(
+            std::fs::read_to_string(&mimeapps_list).unwrap(),
+            "[Default Applications]\n\
+             x-scheme-handler/https=firefox.desktop;\n\
+             text/html=firefox.desktop\n\
+             \n\
+             [Added Associations]\n"
+        );
+
+        // nothing left to remove is fine
+        unregister_desktop_entry(&applications_dir, &dir.path().join("missing.list")).unwrap();
+    }
+
+    #[test]
+    fn register_passes_args_before_the_url() {
+        let mut registry = FakeRegistry::default();
+        let args = ["--data-root".to_string(), r"D:\test data".to_string()];
+        register_url_handler(&mut registry, r"C:\valet\chrome_valet.exe", &args).unwrap();
+
+        let command = get_url_handler_command_from(&registry).unwrap();
+        assert_eq!(
+            command,
+            r#""C:\valet\chrome_valet.exe" "--data-root" "D:\test data" --url "%1""#
+        );
+        assert_eq!(command_exe(&command), r"C:\valet\chrome_valet.exe");
+
+        // a trailing backslash would otherwise escape the closing quote and swallow --url
+        let args = ["--data-root".to_string(), r"D:\portable\".to_string()];
+        register_url_handler(&mut registry, r"C:\valet\chrome_valet.exe", &args).unwrap();
+        assert_eq!(
+            get_url_handler_command_from(&registry).unwrap(),
+            r#""C:\valet\chrome_valet.exe" "--data-root" "D:\portable\\" --url "%1""#
+        );
+    }
+
+    #[test]
+    fn quote_arg_escapes_quotes_and_trailing_backslashes() {
+        assert_eq!(quote_arg(r"D:\test data"), r#""D:\test data""#);
+        assert_eq!(quote_arg(r"D:\portable\"), r#""D:\portable\\""#);
+        assert_eq!(quote_arg(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(quote_arg(r#"a\"b"#), r#""a\\\"b""#);
+        assert_eq!(quote_arg(""), r#""""#);
     }
 }
@@ -0,0 +1,405 @@
+use chrono::{DateTime, Utc};
+use log::warn;
+
+use crate::chrome_interface::ChromeInterface;
+use crate::registry_utils::Browser;
+use crate::rules::Link;
+
+///
+/// what chrome valet does with a link when it isn't asked for the picker
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    /// None opens the browser's last used profile
+    Open {
+        browser: Browser,
+        profile: Option<String>,
+        reason: String,
+    },
+    ShowPicker {
+        reason: String,
+        /// a rule asks for the picker, nothing went wrong
+        asked: bool,
+    },
+}
+
+///
+/// the first matching rule, then the context's profile, then the first scheduled preferred profile that's on now,
+/// then the preferred profile
+/// `profiles_known` is false if the browser's profiles couldn't be read, then profiles aren't checked
+///
+pub fn decide(
+    chrome: &ChromeInterface,
+    link: &Link,
+    default_browser: Browser,
+    profiles_known: bool,
+) -> Decision {
+    decide_at(chrome, link, default_browser, profiles_known, Utc::now())
+}
+
+/// `decide`, with `now` for the schedules and the context's expiry
+pub fn decide_at(
+    chrome: &ChromeInterface,
+    link: &Link,
+    default_browser: Browser,
+    profiles_known: bool,
+    now: DateTime<Utc>,
+) -> Decision {
+    if let Some(rule) = chrome.find_rule_at(link, now) {
+        if rule.ask {
+            return Decision::ShowPicker {
+                reason: format!("matched rule {rule}"),
+                asked: true,
+            };
+        }
+
+        if profiles_known && !chrome.has_profile(&rule.profile) {
+            return Decision::ShowPicker {
+                reason: format!("rule targets missing profile \"{}\"", rule.profile),
+                asked: false,
+            };
+        }
+
+        let browser = match &rule.browser {
+            Some(name) => Browser::try_from(name).unwrap_or(default_browser),
+            None => default_browser,
+        };
+        return Decision::Open {
+            browser,
+            profile: Some(rule.profile.clone()),
+            reason: format!("matched rule {rule}"),
+        };
+    }
+
+    if let Some((name, context)) = chrome.current_context_at(now) {
+        if let Some(profile) = &context.profile {
+            if profiles_known && !chrome.has_profile(profile) {
+                return Decision::ShowPicker {
+                    reason: format!(
+                        "context \"{name}\" opens links in missing profile \"{profile}\""
+                    ),
+                    asked: false,
+                };
+            }
+
+            return Decision::Open {
+                browser: default_browser,
+                profile: Some(profile.clone()),
+                reason: format!("no rule matched, using context \"{name}\""),
+            };
+        }
+    }
+
+    for scheduled in &chrome.config().preferred {
+        let check = match scheduled.schedule.check(now) {
+            Ok(check) => check,
+            Err(err) => {
+                warn!(
+                    "preferred profile \"{}\" has an invalid schedule: {err}",
+                    scheduled.profile
+                );
+                continue;
+            }
+        };
+        if !check.active {
+            continue;
+        }
+
+        if profiles_known && !chrome.has_profile(&scheduled.profile) {
+            return Decision::ShowPicker {
+                reason: format!(
+                    "scheduled preferred profile \"{}\" doesn't exist",
+                    scheduled.profile
+                ),
+                asked: false,
+            };
+        }
+
+        return Decision::Open {
+            browser: default_browser,
+            profile: Some(scheduled.profile.clone()),
+            reason: format!(
+                "no rule matched, {} is within {}",
+                check.local_time, scheduled.schedule
+            ),
+        };
+    }
+
+    let preferred_profile = chrome.prefs().get_preferred_profile();
+    if preferred_profile.is_empty() {
+        return Decision::Open {
+            browser: default_browser,
+            profile: None,
+            reason: "no rule matched and there's no preferred profile".to_string(),
+        };
+    }
+
+    if profiles_known && !chrome.has_profile(&preferred_profile) {
+        return Decision::ShowPicker {
+            reason: format!("preferred profile \"{preferred_profile}\" no longer exists"),
+            asked: false,
+        };
+    }
+
+    Decision::Open {
+        browser: default_browser,
+        profile: Some(preferred_profile),
+        reason: "no rule matched, using the preferred profile".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chrome_interface::ProgramPrefs;
+    use crate::config::{ContextConfig, ScheduledProfile, UserConfig};
+    use crate::rules::{host_rule, RoutingRule};
+    use crate::schedule::Schedule;
+    use chrono::TimeZone;
+
+    const URL: &str = "https://jira.example.com/browse/X-1";
+
+    /// a wednesday
+    fn noon() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 3, 4, 12, 0, 0).unwrap()
+    }
+
+    fn weekdays_in_utc() -> Schedule {
+        Schedule {
+            days: vec!["weekdays".to_string()],
+            timezone: Some("UTC".to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// a rule, a context, a scheduled preferred profile and a preferred profile, all for different profiles
+    fn everything() -> (UserConfig, ProgramPrefs) {
+        let mut config = UserConfig {
+            rules: vec![host_rule("*.example.com", "Rule")],
+            preferred: vec![ScheduledProfile {
+                profile: "Scheduled".to_string(),
+                schedule: weekdays_in_utc(),
+            }],
+            ..Default::default()
+        };
+        config.contexts.insert(
+            "Work".to_string(),
+            ContextConfig {
+                profile: Some("Context".to_string()),
+                rules: Vec::new(),
+            },
+        );
+        let prefs = ProgramPrefs {
+            preferred_profile: "Preferred".to_string(),
+            active_context: Some("Work".to_string()),
+            ..Default::default()
+        };
+        (config, prefs)
+    }
+
+    const PROFILES: [&str; 4] = ["Rule", "Context", "Scheduled", "Preferred"];
+
+    fn opened_profile(chrome: &ChromeInterface, url: &str) -> Option<String> {
+        match decide_at(chrome, &Link::new(url), Browser::Chrome, true, noon()) {
+            Decision::Open { profile, .. } => profile,
+            Decision::ShowPicker { reason, .. } => panic!("showed the picker: {reason}"),
+        }
+    }
+
+    #[test]
+    fn rule_wins_over_everything() {
+        let (config, prefs) = everything();
+        let chrome = ChromeInterface::with_profiles(config, prefs, &PROFILES);
+        assert_eq!(opened_profile(&chrome, URL).as_deref(), Some("Rule"));
+    }
+
+    #[test]
+    fn context_profile_is_next() {
+        let (config, prefs) = everything();
+        let chrome = ChromeInterface::with_profiles(config, prefs, &PROFILES);
+        assert_eq!(
+            opened_profile(&chrome, "https://other.org/").as_deref(),
+            Some("Context")
+        );
+    }
+
+    #[test]
+    fn scheduled_preferred_profile_is_next() {
+        let (config, mut prefs) = everything();
+        prefs.active_context = None;
+        let chrome = ChromeInterface::with_profiles(config, prefs, &PROFILES);
+        assert_eq!(
+            opened_profile(&chrome, "https://other.org/").as_deref(),
+            Some("Scheduled")
+        );
+    }
+
+    fn scheduled(profile: &str, from: &str, until: &str) -> ScheduledProfile {
+        ScheduledProfile {
+            profile: profile.to_string(),
+            schedule: Schedule {
+                from: Some(from.to_string()),
+                until: Some(until.to_string()),
+                ..weekdays_in_utc()
+            },
+        }
+    }
+
+    #[test]
+    fn scheduled_preferred_profile_switches_with_its_schedule() {
+        let config = UserConfig {
+            preferred: vec![
+                scheduled("Scheduled", "09:00", "17:00"),
+                scheduled("Context", "17:00", "20:00"),
+            ],
+            ..Default::default()
+        };
+        let prefs = ProgramPrefs {
+            preferred_profile: "Preferred".to_string(),
+            ..Default::default()
+        };
+        let chrome = ChromeInterface::with_profiles(config, prefs, &PROFILES);
+        let opened_at = |now| match decide_at(
+            &chrome,
+            &Link::new("https://other.org/"),
+            Browser::Chrome,
+            true,
+            now,
+        ) {
+            Decision::Open { profile, .. } => profile,
+            Decision::ShowPicker { reason, .. } => panic!("showed the picker: {reason}"),
+        };
+
+        let wednesday = |hour, minute| Utc.with_ymd_and_hms(2026, 3, 4, hour, minute, 0).unwrap();
+        let saturday = Utc.with_ymd_and_hms(2026, 3, 7, 12, 0, 0).unwrap();
+        assert_eq!(opened_at(wednesday(8, 59)).as_deref(), Some("Preferred"));
+        assert_eq!(opened_at(wednesday(9, 0)).as_deref(), Some("Scheduled"));
+        assert_eq!(opened_at(wednesday(16, 59)).as_deref(), Some("Scheduled"));
+        assert_eq!(opened_at(wednesday(17, 0)).as_deref(), Some("Context"));
+        assert_eq!(opened_at(wednesday(20, 0)).as_deref(), Some("Preferred"));
+        assert_eq!(opened_at(saturday).as_deref(), Some("Preferred"));
+    }
+
+    #[test]
+    fn scheduled_rule_switches_with_its_schedule() {
+        let rule = RoutingRule {
+            schedule: Some(Schedule {
+                from: Some("09:00".to_string()),
+                until: Some("17:00".to_string()),
+                ..weekdays_in_utc()
+            }),
+            ..host_rule("*.example.com", "Rule")
+        };
+        let config = UserConfig {
+            rules: vec![rule],
+            ..Default::default()
+        };
+        let prefs = ProgramPrefs {
+            preferred_profile: "Preferred".to_string(),
+            ..Default::default()
+        };
+        let chrome = ChromeInterface::with_profiles(config, prefs, &PROFILES);
+        let decision_at = |hour| {
+            let now = Utc.with_ymd_and_hms(2026, 3, 4, hour, 0, 0).unwrap();
+            decide_at(&chrome, &Link::new(URL), Browser::Chrome, true, now)
+        };
+
+        assert!(matches!(
+            decision_at(10),
+            Decision::Open { profile: Some(profile), .. } if profile == "Rule"
+        ));
+        assert!(matches!(
+            decision_at(18),
+            Decision::Open { profile: Some(profile), .. } if profile == "Preferred"
+        ));
+    }
+
+    #[test]
+    fn preferred_profile_is_last() {
+        let (mut config, mut prefs) = everything();
+        prefs.active_context = None;
+        config.preferred.clear();
+        let chrome = ChromeInterface::with_profiles(config, prefs, &PROFILES);
+        assert_eq!(
+            opened_profile(&chrome, "https://other.org/").as_deref(),
+            Some("Preferred")
+        );
+
+        let chrome = ChromeInterface::with_profiles(
+            UserConfig::default(),
+            ProgramPrefs::default(),
+            &PROFILES,
+        );
+        assert_eq!(opened_profile(&chrome, "https://other.org/"), None);
+    }
+
+    #[test]
+    fn rule_browser_overrides_the_default() {
+        let mut rule = host_rule("*.example.com", "Rule");
+        rule.browser = Some("brave".to_string());
+        let config = UserConfig {
+            rules: vec![rule],
+            ..Default::default()
+        };
+        let chrome = ChromeInterface::with_profiles(config, ProgramPrefs::default(), &PROFILES);
+        assert!(matches!(
+            decide_at(&chrome, &Link::new(URL), Browser::Chrome, true, noon()),
+            Decision::Open {
+                browser: Browser::Brave,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn missing_profiles_show_the_picker() {
+        let shows_picker = |config: UserConfig, prefs: ProgramPrefs, url: &str| {
+            let chrome = ChromeInterface::with_profiles(config, prefs, &["Other"]);
+            matches!(
+                decide_at(&chrome, &Link::new(url), Browser::Chrome, true, noon()),
+                Decision::ShowPicker { asked: false, .. }
+            )
+        };
+
+        let (config, prefs) = everything();
+        assert!(shows_picker(config, prefs, URL));
+        let (config, prefs) = everything();
+        assert!(shows_picker(config, prefs, "https://other.org/"));
+
+        let (config, mut prefs) = everything();
+        prefs.active_context = None;
+        assert!(shows_picker(config, prefs, "https://other.org/"));
+
+        let (mut config, mut prefs) = everything();
+        prefs.active_context = None;
+        config.preferred.clear();
+        assert!(shows_picker(config, prefs, "https://other.org/"));
+    }
+
+    #[test]
+    fn unknown_profiles_are_not_checked() {
+        let (config, prefs) = everything();
+        let chrome = ChromeInterface::with_profiles(config, prefs, &[]);
+        assert!(matches!(
+            decide_at(&chrome, &Link::new(URL), Browser::Chrome, false, noon()),
+            Decision::Open { .. }
+        ));
+    }
+
+    #[test]
+    fn ask_rule_shows_the_picker() {
+        let rule = RoutingRule {
+            ask: true,
+            ..host_rule("*.example.com", "")
+        };
+        let config = UserConfig {
+            rules: vec![rule],
+            ..Default::default()
+        };
+        let chrome = ChromeInterface::with_profiles(config, ProgramPrefs::default(), &PROFILES);
+        assert!(matches!(
+            decide_at(&chrome, &Link::new(URL), Browser::Chrome, true, noon()),
+            Decision::ShowPicker { asked: true, .. }
+        ));
+    }
+}
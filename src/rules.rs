@@ -0,0 +1,720 @@
+use chrono::{DateTime, Utc};
+use log::warn;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use url::Url;
+
+use crate::domains;
+use crate::network::{NetworkCondition, NetworkSnapshot};
+use crate::schedule::Schedule;
+use crate::source_app;
+
+///
+/// a routing rule, urls matching `host`, `domain` and/or `regex`, or clicked in `source_app`, are opened in `profile`
+/// `scheme`, `port`, `schedule` and `network` narrow it further
+///
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoutingRule {
+    /// host glob, eg "*.example.com", `*` matches any run of characters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+
+    /// a domain, eg "example.co.uk", matched with the public suffix list instead of a glob
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+
+    /// whether `domain` takes in its subdomains
+    #[serde(default, skip_serializing_if = "DomainMatch::is_default")]
+    pub domain_match: DomainMatch,
+
+    /// regex matched against the whole url
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+
+    /// eg "https", any scheme if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<String>,
+
+    /// port, the scheme's default port counts, eg 443 for https
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+
+    /// executable name of the program the link was clicked in, eg "slack", ".exe" is optional, `*` matches any run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_app: Option<String>,
+
+    /// profile directory to open matching urls in, eg "Profile 1", can be left out with `ask`
+    #[serde(default)]
+    pub profile: String,
+
+    /// show the picker for matching urls instead of opening them
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub ask: bool,
+
+    /// browser to open matching urls in, defaults to the default browser
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub browser: Option<String>,
+
+    /// only match during these days and hours
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Schedule>,
+
+    /// only match on this network, eg. while the vpn is connected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkCondition>,
+}
+
+///
+/// a rule sending `host` to `profile`, for tests
+///
+#[cfg(test)]
+pub(crate) fn host_rule(host: &str, profile: &str) -> RoutingRule {
+    RoutingRule {
+        host: Some(host.to_string()),
+        profile: profile.to_string(),
+        ..Default::default()
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DomainMatch {
+    /// the domain and any host under it
+    #[default]
+    Subdomains,
+    /// only the domain itself
+    Exact,
+}
+
+impl DomainMatch {
+    fn is_default(&self) -> bool {
+        *self == DomainMatch::default()
+    }
+}
+
+///
+/// a link being routed, the program it was clicked in if that's known,
+/// and the network if any rule needed it read
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Link<'a> {
+    pub url: &'a str,
+    pub source_app: Option<&'a str>,
+    pub network: Option<&'a NetworkSnapshot>,
+}
+
+impl<'a> Link<'a> {
+    /// a link from an unknown program, on an unknown network
+    pub fn new(url: &'a str) -> Self {
+        Link {
+            url,
+            source_app: None,
+            network: None,
+        }
+    }
+}
+
+impl RoutingRule {
+    ///
+    /// a rule sending everything on `url`'s host to `profile`, None if the url has no host
+    ///
+    pub fn for_host(url: &str, profile: &str) -> Option<Self> {
+        let host = Url::parse(url).ok()?.host_str()?.to_lowercase();
+        Some(RoutingRule {
+            host: Some(host),
+            profile: profile.to_string(),
+            ..Default::default()
+        })
+    }
+
+    /// whether `other` matches the same links, wherever it opens them
+    pub fn same_conditions(&self, other: &RoutingRule) -> bool {
+        let conditions = |rule: &RoutingRule| RoutingRule {
+            profile: String::new(),
+            ask: false,
+            browser: None,
+            ..rule.clone()
+        };
+        conditions(self) == conditions(other)
+    }
+
+    /// rules without a host, domain, regex or source app never match
+    pub fn has_pattern(&self) -> bool {
+        self.host.is_some()
+            || self.domain.is_some()
+            || self.regex.is_some()
+            || self.source_app.is_some()
+    }
+
+    ///
+    /// whether the rule matches `link` at `now`, and why, for `explain`
+    ///
+    pub fn evaluate_at(&self, link: &Link, now: DateTime<Utc>) -> RuleEvaluation {
+        if !self.has_pattern() {
+            return RuleEvaluation::no_match(
+                "rule has no host, domain, regex or source app".to_string(),
+            );
+        }
+
+        let url = link.url;
+        let mut reasons = Vec::new();
+        if let Some(pattern) = &self.source_app {
+            match link.source_app {
+                Some(app) if source_app::app_matches(pattern, app) => {
+                    reasons.push(format!("opened from \"{app}\""))
+                }
+                Some(app) => {
+                    return RuleEvaluation::no_match(format!(
+                        "opened from \"{app}\", not \"{pattern}\""
+                    ))
+                }
+                None => {
+                    return RuleEvaluation::no_match(format!(
+                        "the program it was opened from isn't known, wanted \"{pattern}\""
+                    ))
+                }
+            }
+        }
+
+        let needs_parsed = self.host.is_some()
+            || self.domain.is_some()
+            || self.scheme.is_some()
+            || self.port.is_some();
+        if needs_parsed {
+            let parsed = match Url::parse(url) {
+                Ok(parsed) => parsed,
+                Err(err) => return RuleEvaluation::no_match(format!("url doesn't parse: {err}")),
+            };
+            // the url crate has already lowercased the host and converted it to punycode
+            let host = parsed.host_str().unwrap_or_default();
+
+            if let Some(host_glob) = &self.host {
+                if !glob_matches(&domains::normalize_host(host_glob), host) {
+                    return RuleEvaluation::no_match(format!(
+                        "host \"{host}\" doesn't match \"{host_glob}\""
+                    ));
+                }
+                reasons.push(format!("host \"{host}\" matches \"{host_glob}\""));
+            }
+
+            if let Some(domain) = &self.domain {
+                let subdomains = self.domain_match == DomainMatch::Subdomains;
+                let scope = if subdomains {
+                    "in domain"
+                } else {
+                    "is exactly"
+                };
+                if domains::is_public_suffix(domain) {
+                    return RuleEvaluation::no_match(format!(
+                        "\"{domain}\" is a public suffix, it can't be a rule's domain"
+                    ));
+                }
+                if !domains::domain_matches(domain, host, subdomains) {
+                    return RuleEvaluation::no_match(format!(
+                        "host \"{host}\" isn't {scope} \"{domain}\""
+                    ));
+                }
+                reasons.push(format!("host \"{host}\" {scope} \"{domain}\""));
+            }
+
+            if let Some(scheme) = &self.scheme {
+                if !parsed.scheme().eq_ignore_ascii_case(scheme) {
+                    return RuleEvaluation::no_match(format!(
+                        "scheme \"{}\" isn't \"{scheme}\"",
+                        parsed.scheme()
+                    ));
+                }
+                reasons.push(format!("scheme is \"{scheme}\""));
+            }
+
+            if let Some(port) = self.port {
+                match parsed.port_or_known_default() {
+                    Some(url_port) if url_port == port => reasons.push(format!("port is {port}")),
+                    Some(url_port) => {
+                        return RuleEvaluation::no_match(format!("port {url_port} isn't {port}"))
+                    }
+                    None => {
+                        return RuleEvaluation::no_match(format!("url has no port, wanted {port}"))
+                    }
+                }
+            }
+        }
+
+        if let Some(pattern) = &self.regex {
+            match Regex::new(pattern) {
+                Ok(re) => {
+                    if !re.is_match(url) {
+                        return RuleEvaluation::no_match(format!(
+                            "url doesn't match regex \"{pattern}\""
+                        ));
+                    }
+                    reasons.push(format!("url matches regex \"{pattern}\""));
+                }
+                Err(err) => {
+                    warn!("invalid rule regex \"{pattern}\": {err}");
+                    return RuleEvaluation::no_match(format!("invalid regex \"{pattern}\": {err}"));
+                }
+            }
+        }
+
+        if let Some(schedule) = &self.schedule {
+            match schedule.check(now) {
+                Ok(check) if check.active => {
+                    reasons.push(format!("{} is within {schedule}", check.local_time))
+                }
+                Ok(check) => {
+                    return RuleEvaluation::no_match(format!(
+                        "{} is outside {schedule}",
+                        check.local_time
+                    ))
+                }
+                Err(err) => return RuleEvaluation::no_match(format!("invalid schedule: {err}")),
+            }
+        }
+
+        if let Some(condition) = &self.network {
+            let Some(network) = link.network else {
+                return RuleEvaluation::no_match("the network wasn't read".to_string());
+            };
+            match condition.check(network) {
+                Ok(reason) => reasons.push(reason),
+                Err(reason) => return RuleEvaluation::no_match(reason),
+            }
+        }
+
+        RuleEvaluation {
+            matched: true,
+            reason: reasons.join(", "),
+        }
+    }
+}
+
+impl fmt::Display for RoutingRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(host) = &self.host {
+            write!(f, "host \"{host}\" ")?;
+        }
+        if let Some(domain) = &self.domain {
+            match self.domain_match {
+                DomainMatch::Subdomains => write!(f, "domain \"{domain}\" ")?,
+                DomainMatch::Exact => write!(f, "exactly \"{domain}\" ")?,
+            }
+        }
+        if let Some(regex) = &self.regex {
+            write!(f, "regex \"{regex}\" ")?;
+        }
+        if let Some(scheme) = &self.scheme {
+            write!(f, "scheme {scheme} ")?;
+        }
+        if let Some(port) = self.port {
+            write!(f, "port {port} ")?;
+        }
+        if let Some(app) = &self.source_app {
+            write!(f, "from \"{app}\" ")?;
+        }
+        if self.ask {
+            write!(f, "-> picker")?;
+        } else {
+            write!(f, "-> \"{}\"", self.profile)?;
+        }
+        if let Some(browser) = &self.browser {
+            write!(f, " in {browser}")?;
+        }
+        if let Some(schedule) = &self.schedule {
+            write!(f, " during {schedule}")?;
+        }
+        if let Some(network) = &self.network {
+            write!(f, " on {network}")?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// where a rule was written, the context in use is checked first, then chromevalet.toml
+///
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleLayer {
+    /// [contexts.<name>] in chromevalet.toml
+    Context,
+    Config,
+    /// remembered from choices made in chrome valet
+    Prefs,
+    /// [[preferred]] in chromevalet.toml, not rules but linted with them
+    Preferred,
+}
+
+impl fmt::Display for RuleLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleLayer::Context => write!(f, "chromevalet.toml context"),
+            RuleLayer::Config => write!(f, "chromevalet.toml"),
+            RuleLayer::Prefs => write!(f, "prefs.json"),
+            RuleLayer::Preferred => write!(f, "chromevalet.toml preferred"),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RuleEvaluation {
+    pub matched: bool,
+    /// the condition that failed, or every condition that held
+    pub reason: String,
+}
+
+impl RuleEvaluation {
+    fn no_match(reason: String) -> Self {
+        RuleEvaluation {
+            matched: false,
+            reason,
+        }
+    }
+}
+
+///
+/// first rule matching the link at `now`, rules are evaluated in order
+///
+pub fn find_matching_rule<'a>(
+    rules: impl IntoIterator<Item = &'a RoutingRule>,
+    link: &Link,
+    now: DateTime<Utc>,
+) -> Option<&'a RoutingRule> {
+    rules
+        .into_iter()
+        .find(|rule| rule.evaluate_at(link, now).matched)
+}
+
+pub fn glob_matches(glob: &str, text: &str) -> bool {
+    match glob.split_once('*') {
+        None => glob == text,
+        Some((prefix, rest)) => {
+            if !text.starts_with(prefix) {
+                return false;
+            }
+
+            let text = &text[prefix.len()..];
+            (0..=text.len())
+                .filter(|i| text.is_char_boundary(*i))
+                .any(|i| glob_matches(rest, &text[i..]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::NetworkInterface;
+    use chrono::TimeZone;
+
+    fn matches(rule: &RoutingRule, url: &str) -> bool {
+        rule.evaluate_at(&Link::new(url), Utc::now()).matched
+    }
+
+    #[test]
+    fn glob_leading_star_dot_needs_a_subdomain() {
+        assert!(glob_matches("*.example.com", "www.example.com"));
+        assert!(glob_matches("*.example.com", "a.b.example.com"));
+        assert!(!glob_matches("*.example.com", "example.com"));
+        assert!(!glob_matches("*.example.com", "badexample.com"));
+        assert!(!glob_matches("*.example.com", "example.com.evil.org"));
+    }
+
+    #[test]
+    fn glob_without_a_star_is_exact() {
+        assert!(glob_matches("example.com", "example.com"));
+        assert!(!glob_matches("example.com", "www.example.com"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("jira*.corp", "jira-eu.corp"));
+        assert!(glob_matches("*é*", "café"));
+    }
+
+    #[test]
+    fn glob_is_case_sensitive_but_rules_are_not() {
+        assert!(!glob_matches("*.example.com", "WWW.EXAMPLE.COM"));
+
+        let rule = host_rule("*.Example.COM", "Default");
+        assert!(matches(&rule, "https://WWW.example.com/Path"));
+        assert!(!matches(&rule, "https://EXAMPLE.com/"));
+        assert!(matches(
+            &host_rule("Example.com", "Default"),
+            "https://example.COM/"
+        ));
+    }
+
+    #[test]
+    fn rule_without_a_pattern_never_matches() {
+        let rule = RoutingRule {
+            profile: "Default".to_string(),
+            ..Default::default()
+        };
+        assert!(!matches(&rule, "https://example.com/"));
+    }
+
+    fn domain_rule(domain: &str, domain_match: DomainMatch) -> RoutingRule {
+        RoutingRule {
+            domain: Some(domain.to_string()),
+            domain_match,
+            profile: "Default".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn domain_rule_takes_in_subdomains_by_default() {
+        let rule = domain_rule("example.co.uk", DomainMatch::Subdomains);
+        assert!(matches(&rule, "https://example.co.uk/"));
+        assert!(matches(&rule, "https://mail.example.co.uk/inbox"));
+        assert!(matches(&rule, "https://a.b.EXAMPLE.co.uk/"));
+        assert!(!matches(&rule, "https://badexample.co.uk/"));
+        assert!(!matches(&rule, "https://example.co.uk.evil.net/"));
+        assert!(!matches(&rule, "https://co.uk/"));
+    }
+
+    #[test]
+    fn exact_domain_rule_skips_subdomains() {
+        let rule = domain_rule("dev.example.co.uk", DomainMatch::Exact);
+        assert!(matches(&rule, "https://dev.example.co.uk:8443/"));
+        assert!(!matches(&rule, "https://www.dev.example.co.uk/"));
+        assert!(!matches(&rule, "https://example.co.uk/"));
+    }
+
+    #[test]
+    fn public_suffix_domain_never_matches() {
+        for suffix in ["co.uk", "com", "github.io"] {
+            for domain_match in [DomainMatch::Subdomains, DomainMatch::Exact] {
+                let rule = domain_rule(suffix, domain_match);
+                assert!(!matches(&rule, &format!("https://example.{suffix}/")));
+                assert!(!matches(&rule, &format!("https://{suffix}/")));
+            }
+        }
+
+        let evaluation = domain_rule("github.io", DomainMatch::Subdomains)
+            .evaluate_at(&Link::new("https://someone.github.io/"), Utc::now());
+        assert!(evaluation.reason.contains("public suffix"));
+    }
+
+    #[test]
+    fn domain_rule_matches_internationalised_names() {
+        let rule = domain_rule("bücher.example", DomainMatch::Subdomains);
+        assert!(matches(&rule, "https://www.bücher.example/"));
+        assert!(matches(&rule, "https://www.xn--bcher-kva.example/"));
+    }
+
+    fn utc(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        // june 2024, london is utc+1, the 3rd is a monday
+        Utc.with_ymd_and_hms(2024, 6, day, hour, minute, 0).unwrap()
+    }
+
+    fn work_hours() -> Schedule {
+        Schedule {
+            days: vec!["weekdays".to_string()],
+            from: Some("09:00".to_string()),
+            until: Some("17:30".to_string()),
+            timezone: Some("Europe/London".to_string()),
+        }
+    }
+
+    #[test]
+    fn scheduled_rule_only_matches_during_its_schedule() {
+        let rule = RoutingRule {
+            schedule: Some(work_hours()),
+            ..host_rule("*.example.com", "Default")
+        };
+        let link = Link::new("https://jira.example.com/");
+
+        let evaluation = rule.evaluate_at(&link, utc(3, 8, 0));
+        assert!(evaluation.matched);
+        assert!(evaluation
+            .reason
+            .contains("Mon 09:00 Europe/London is within"));
+
+        let evaluation = rule.evaluate_at(&link, utc(3, 16, 30));
+        assert!(!evaluation.matched);
+        assert!(evaluation
+            .reason
+            .contains("Mon 17:30 Europe/London is outside"));
+
+        // saturday
+        assert!(!rule.evaluate_at(&link, utc(8, 10, 0)).matched);
+        // still has to match the host
+        let other = Link::new("https://example.org/");
+        assert!(!rule.evaluate_at(&other, utc(3, 10, 0)).matched);
+    }
+
+    #[test]
+    fn rule_with_an_invalid_schedule_never_matches() {
+        let rule = RoutingRule {
+            schedule: Some(Schedule {
+                timezone: Some("Mars/Olympus".to_string()),
+                ..Default::default()
+            }),
+            ..host_rule("*.example.com", "Default")
+        };
+        let evaluation = rule.evaluate_at(&Link::new("https://jira.example.com/"), utc(3, 10, 0));
+        assert!(!evaluation.matched);
+        assert!(evaluation.reason.starts_with("invalid schedule"));
+    }
+
+    #[test]
+    fn scheduled_rule_gives_way_to_the_next_outside_its_schedule() {
+        let rules = [
+            RoutingRule {
+                profile: "Profile 1".to_string(),
+                schedule: Some(work_hours()),
+                ..host_rule("*.example.com", "Default")
+            },
+            host_rule("*.example.com", "Default"),
+        ];
+        let link = Link::new("https://jira.example.com/");
+        let found = |now| find_matching_rule(&rules, &link, now).map(|rule| rule.profile.as_str());
+        assert_eq!(found(utc(4, 12, 0)), Some("Profile 1"));
+        assert_eq!(found(utc(4, 18, 0)), Some("Default"));
+    }
+
+    fn network(interfaces: &[(&str, &str)]) -> NetworkSnapshot {
+        let interfaces = interfaces
+            .iter()
+            .map(|(name, address)| NetworkInterface {
+                name: name.to_string(),
+                addresses: vec![address.parse().unwrap()],
+            })
+            .collect();
+        NetworkSnapshot {
+            interfaces,
+            dns_suffixes: Vec::new(),
+        }
+    }
+
+    fn network_rule(condition: NetworkCondition) -> RoutingRule {
+        RoutingRule {
+            network: Some(condition),
+            ..host_rule("intranet.example.com", "Default")
+        }
+    }
+
+    fn evaluate_on(rule: &RoutingRule, network: &NetworkSnapshot) -> RuleEvaluation {
+        let link = Link {
+            network: Some(network),
+            ..Link::new("https://intranet.example.com/")
+        };
+        rule.evaluate_at(&link, Utc::now())
+    }
+
+    #[test]
+    fn network_interface_glob() {
+        let rule = network_rule(NetworkCondition {
+            interface: Some("tun*".to_string()),
+            ..Default::default()
+        });
+        let on_vpn = network(&[("eth0", "192.168.1.20"), ("TUN0", "10.20.3.4")]);
+        let evaluation = evaluate_on(&rule, &on_vpn);
+        assert!(evaluation.matched);
+        assert!(evaluation.reason.contains("interface \"TUN0\" is up"));
+
+        let off_vpn = network(&[("eth0", "192.168.1.20"), ("stun", "10.0.0.1")]);
+        assert!(!evaluate_on(&rule, &off_vpn).matched);
+
+        let rule = network_rule(NetworkCondition {
+            interface: Some("Corp VPN".to_string()),
+            ..Default::default()
+        });
+        assert!(evaluate_on(&rule, &network(&[("corp vpn", "10.0.0.1")])).matched);
+        assert!(!evaluate_on(&rule, &network(&[("Corp VPN 2", "10.0.0.1")])).matched);
+    }
+
+    #[test]
+    fn network_cidr() {
+        let rule = network_rule(NetworkCondition {
+            cidr: Some("10.20.0.0/16".to_string()),
+            ..Default::default()
+        });
+        let evaluation = evaluate_on(&rule, &network(&[("eth0", "10.20.255.1")]));
+        assert!(evaluation.matched);
+        assert!(evaluation.reason.contains("10.20.255.1 is in 10.20.0.0/16"));
+        assert!(!evaluate_on(&rule, &network(&[("eth0", "10.21.0.1")])).matched);
+        assert!(!evaluate_on(&rule, &network(&[("eth0", "fd00::1")])).matched);
+
+        let ipv6 = network_rule(NetworkCondition {
+            cidr: Some("fd00::/8".to_string()),
+            ..Default::default()
+        });
+        assert!(evaluate_on(&ipv6, &network(&[("tun0", "fd12::1")])).matched);
+
+        let invalid = network_rule(NetworkCondition {
+            cidr: Some("intranet".to_string()),
+            ..Default::default()
+        });
+        assert!(!evaluate_on(&invalid, &network(&[("eth0", "10.20.0.1")])).matched);
+    }
+
+    #[test]
+    fn network_interface_and_cidr_both_hold() {
+        let rule = network_rule(NetworkCondition {
+            interface: Some("tun*".to_string()),
+            cidr: Some("10.20.0.0/16".to_string()),
+            ..Default::default()
+        });
+        assert!(evaluate_on(&rule, &network(&[("tun0", "10.20.3.4")])).matched);
+        // the address is on another interface, that still counts
+        assert!(
+            evaluate_on(
+                &rule,
+                &network(&[("tun0", "10.99.0.1"), ("eth0", "10.20.3.4")])
+            )
+            .matched
+        );
+        assert!(!evaluate_on(&rule, &network(&[("tun0", "10.99.0.1")])).matched);
+        assert!(!evaluate_on(&rule, &network(&[("eth0", "10.20.3.4")])).matched);
+
+        let other_host = Link {
+            network: Some(&network(&[("tun0", "10.20.3.4")])),
+            ..Link::new("https://example.org/")
+        };
+        assert!(!rule.evaluate_at(&other_host, Utc::now()).matched);
+    }
+
+    #[test]
+    fn network_rule_without_the_network_read_never_matches() {
+        let rule = network_rule(NetworkCondition {
+            interface: Some("*".to_string()),
+            ..Default::default()
+        });
+        let evaluation = rule.evaluate_at(&Link::new("https://intranet.example.com/"), Utc::now());
+        assert!(!evaluation.matched);
+        assert_eq!(evaluation.reason, "the network wasn't read");
+    }
+
+    #[test]
+    fn scheme_and_port_narrow_a_host() {
+        let rule = RoutingRule {
+            scheme: Some("https".to_string()),
+            port: Some(443),
+            ..host_rule("example.com", "Default")
+        };
+        assert!(matches(&rule, "https://example.com/"));
+        assert!(!matches(&rule, "http://example.com/"));
+        assert!(!matches(&rule, "https://example.com:8443/"));
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rules = [
+            host_rule("docs.example.com", "Default"),
+            RoutingRule {
+                profile: "Profile 1".to_string(),
+                ..host_rule("*.example.com", "Default")
+            },
+        ];
+        let found = |url| find_matching_rule(&rules, &Link::new(url), Utc::now());
+        assert_eq!(found("https://docs.example.com/"), Some(&rules[0]));
+        assert_eq!(found("https://jira.example.com/"), Some(&rules[1]));
+        assert_eq!(found("https://example.org/"), None);
+    }
+}
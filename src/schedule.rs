@@ -0,0 +1,223 @@
+use chrono::{DateTime, Datelike, Local, NaiveTime, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+const TIME_FORMAT: &str = "%H:%M";
+
+///
+/// when a rule or preferred profile applies, eg. weekdays 09:00 to 17:30 in Europe/London
+///
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schedule {
+    /// "mon" to "sun", "weekdays" or "weekends", every day if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub days: Vec<String>,
+
+    /// "09:00", from midnight if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+
+    /// "17:30", up to but not including, until midnight if unset
+    /// earlier than `from` runs past midnight, eg. 22:00 to 06:00, into the day after one in `days`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<String>,
+
+    /// tz database name, eg. "Europe/London", the computer's timezone if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+}
+
+///
+/// a schedule checked against a point in time
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduleCheck {
+    pub active: bool,
+    /// the time it was checked at, in the schedule's timezone, eg. "Tue 18:05 Europe/London"
+    pub local_time: String,
+}
+
+impl Schedule {
+    ///
+    /// whether `now` falls in the schedule, Err if a day, time or timezone doesn't parse
+    ///
+    pub fn check(&self, now: DateTime<Utc>) -> Result<ScheduleCheck, String> {
+        let days = self.parsed_days()?;
+        let from = parse_time(self.from.as_deref())?;
+        let until = parse_time(self.until.as_deref())?;
+
+        let (weekday, time, zone_name) = match &self.timezone {
+            Some(timezone) => {
+                let tz = Tz::from_str(timezone)
+                    .map_err(|err| format!("unknown timezone \"{timezone}\": {err}"))?;
+                let local = now.with_timezone(&tz);
+                (local.weekday(), local.time(), timezone.clone())
+            }
+            None => {
+                let local = now.with_timezone(&Local);
+                (local.weekday(), local.time(), "local time".to_string())
+            }
+        };
+
+        let on = |day: Weekday| days.is_empty() || days.contains(&day);
+        let from = from.unwrap_or(NaiveTime::MIN);
+        let active = match until {
+            None => on(weekday) && time >= from,
+            Some(until) if from <= until => on(weekday) && from <= time && time < until,
+            // after midnight it's the tail of the range that started the day before
+            Some(until) => (on(weekday) && time >= from) || (on(weekday.pred()) && time < until),
+        };
+
+        Ok(ScheduleCheck {
+            active,
+            local_time: format!(
+                "{weekday} {:02}:{:02} {zone_name}",
+                time.hour(),
+                time.minute()
+            ),
+        })
+    }
+
+    /// checks every day, time and timezone parses
+    pub fn validate(&self) -> Result<(), String> {
+        self.check(Utc::now()).map(|_| ())
+    }
+
+    fn parsed_days(&self) -> Result<Vec<Weekday>, String> {
+        let mut days = Vec::new();
+        for day in &self.days {
+            match day.to_lowercase().as_str() {
+                "weekdays" => days.extend([
+                    Weekday::Mon,
+                    Weekday::Tue,
+                    Weekday::Wed,
+                    Weekday::Thu,
+                    Weekday::Fri,
+                ]),
+                "weekends" => days.extend([Weekday::Sat, Weekday::Sun]),
+                name => days
+                    .push(Weekday::from_str(name).map_err(|_| format!("unknown day \"{day}\""))?),
+            }
+        }
+        Ok(days)
+    }
+}
+
+fn parse_time(time: Option<&str>) -> Result<Option<NaiveTime>, String> {
+    time.map(|time| {
+        NaiveTime::parse_from_str(time, TIME_FORMAT)
+            .map_err(|_| format!("time \"{time}\" isn't HH:MM"))
+    })
+    .transpose()
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.days.is_empty() {
+            write!(f, "every day")?;
+        } else {
+            write!(f, "{}", self.days.join(", "))?;
+        }
+        if self.from.is_some() || self.until.is_some() {
+            write!(
+                f,
+                " {}-{}",
+                self.from.as_deref().unwrap_or("00:00"),
+                self.until.as_deref().unwrap_or("24:00")
+            )?;
+        }
+        if let Some(timezone) = &self.timezone {
+            write!(f, " {timezone}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn work_hours() -> Schedule {
+        Schedule {
+            days: vec!["weekdays".to_string()],
+            from: Some("09:00".to_string()),
+            until: Some("17:30".to_string()),
+            timezone: Some("Europe/London".to_string()),
+        }
+    }
+
+    fn utc(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        // june 2024, london is utc+1, the 3rd is a monday
+        Utc.with_ymd_and_hms(2024, 6, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn weekday_hours_in_a_timezone() {
+        let schedule = work_hours();
+        let check = schedule.check(utc(3, 8, 0)).unwrap();
+        assert!(check.active);
+        assert_eq!(check.local_time, "Mon 09:00 Europe/London");
+
+        assert!(!schedule.check(utc(3, 7, 59)).unwrap().active);
+        assert!(!schedule.check(utc(3, 16, 30)).unwrap().active);
+        // saturday
+        assert!(!schedule.check(utc(8, 10, 0)).unwrap().active);
+    }
+
+    #[test]
+    fn range_past_midnight() {
+        let schedule = Schedule {
+            from: Some("22:00".to_string()),
+            until: Some("06:00".to_string()),
+            timezone: Some("UTC".to_string()),
+            ..Default::default()
+        };
+        assert!(schedule.check(utc(3, 23, 0)).unwrap().active);
+        assert!(schedule.check(utc(4, 5, 59)).unwrap().active);
+        assert!(!schedule.check(utc(4, 6, 0)).unwrap().active);
+    }
+
+    #[test]
+    fn range_past_midnight_belongs_to_the_day_it_starts() {
+        let schedule = Schedule {
+            days: vec!["fri".to_string()],
+            from: Some("22:00".to_string()),
+            until: Some("02:00".to_string()),
+            timezone: Some("UTC".to_string()),
+        };
+        // friday night, and the early hours of saturday
+        assert!(schedule.check(utc(7, 23, 0)).unwrap().active);
+        assert!(schedule.check(utc(8, 1, 0)).unwrap().active);
+        // the early hours of friday belong to thursday night
+        assert!(!schedule.check(utc(7, 1, 0)).unwrap().active);
+        assert!(!schedule.check(utc(8, 23, 0)).unwrap().active);
+    }
+
+    #[test]
+    fn invalid_fields_are_errors() {
+        let mut schedule = work_hours();
+        schedule.days.push("someday".to_string());
+        assert!(schedule.validate().is_err());
+
+        let mut schedule = work_hours();
+        schedule.until = Some("5pm".to_string());
+        assert!(schedule.validate().is_err());
+
+        let mut schedule = work_hours();
+        schedule.timezone = Some("Mars/Olympus_Mons".to_string());
+        assert!(schedule.validate().is_err());
+    }
+
+    #[test]
+    fn misspelt_fields_are_errors() {
+        let parsed = toml::from_str::<Schedule>("from = \"09:00\"\nto = \"18:00\"\n");
+        assert!(parsed
+            .unwrap_err()
+            .to_string()
+            .contains("unknown field `to`"));
+    }
+}
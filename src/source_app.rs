@@ -0,0 +1,252 @@
+#[cfg(target_os = "linux")]
+use std::fs;
+#[cfg(target_os = "linux")]
+use std::path::Path;
+
+use crate::rules::glob_matches;
+
+/// shells and launchers that sit between the program a link was clicked in and chrome valet
+const INTERMEDIARIES: &[&str] = &[
+    "chrome_valet",
+    // windows
+    "explorer",
+    "openwith",
+    "rundll32",
+    "cmd",
+    "powershell",
+    "pwsh",
+    "conhost",
+    // linux
+    "xdg-open",
+    "gio",
+    "gio-launch-desktop",
+    "kde-open",
+    "kde-open5",
+    "kioclient5",
+    "exo-open",
+    "sh",
+    "dash",
+    "bash",
+    "zsh",
+    "fish",
+    "env",
+];
+
+/// services and the system itself, once the walk gets here no program opened the link
+const SYSTEM_PROCESSES: &[&str] = &[
+    // windows
+    "system", "smss", "csrss", "wininit", "services", "svchost", // linux
+    "systemd", "init",
+];
+
+/// how far up the process tree to look before giving up
+const MAX_DEPTH: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Process {
+    parent_pid: u32,
+    /// executable name, without .exe
+    name: String,
+}
+
+///
+/// the program the link was clicked in, the nearest ancestor process that isn't a shell or launcher
+/// None if it can't be found, or on platforms we can't read the process tree on
+///
+pub fn detect() -> Option<String> {
+    #[cfg(windows)]
+    {
+        let processes = windows_processes();
+        find_source(std::process::id(), |pid| processes.get(&pid).cloned())
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        find_source(std::process::id(), linux_process)
+    }
+
+    #[cfg(not(any(windows, target_os = "linux")))]
+    {
+        None
+    }
+}
+
+///
+/// whether `app` is the program `pattern` names, ignoring case and ".exe"
+///
+pub fn app_matches(pattern: &str, app: &str) -> bool {
+    glob_matches(&normalize(pattern), &normalize(app))
+}
+
+/// lowercase, without .exe
+pub fn normalize(app: &str) -> String {
+    let app = app.to_lowercase();
+    app.strip_suffix(".exe").unwrap_or(&app).to_string()
+}
+
+fn is_intermediary(name: &str) -> bool {
+    INTERMEDIARIES.contains(&normalize(name).as_str())
+}
+
+fn is_system_process(name: &str) -> bool {
+    SYSTEM_PROCESSES.contains(&normalize(name).as_str())
+}
+
+fn find_source(pid: u32, process: impl Fn(u32) -> Option<Process>) -> Option<String> {
+    let mut pid = process(pid)?.parent_pid;
+    for _ in 0..MAX_DEPTH {
+        if pid == 0 {
+            return None;
+        }
+
+        let parent = process(pid)?;
+        if is_system_process(&parent.name) {
+            return None;
+        }
+        if !is_intermediary(&parent.name) {
+            return Some(parent.name);
+        }
+        pid = parent.parent_pid;
+    }
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn linux_process(pid: u32) -> Option<Process> {
+    let proc_dir = Path::new("/proc").join(pid.to_string());
+    let stat = fs::read_to_string(proc_dir.join("stat")).ok()?;
+    let parent_pid = parse_parent_pid(&stat)?;
+
+    // exe can't be read for other users' processes, comm is cut to 15 characters but always readable
+    let name = fs::read_link(proc_dir.join("exe"))
+        .ok()
+        .and_then(|exe| Some(exe.file_name()?.to_string_lossy().to_string()))
+        .or_else(|| fs::read_to_string(proc_dir.join("comm")).ok())?;
+
+    Some(Process {
+        parent_pid,
+        name: name.trim().to_string(),
+    })
+}
+
+/// the 4th field of /proc/<pid>/stat, after a command name that can hold spaces and brackets
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_parent_pid(stat: &str) -> Option<u32> {
+    let after_name = &stat[stat.rfind(')')? + 1..];
+    after_name.split_whitespace().nth(1)?.parse().ok()
+}
+
+#[cfg(windows)]
+fn windows_processes() -> std::collections::HashMap<u32, Process> {
+    use std::collections::HashMap;
+    use std::mem;
+    use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+    use winapi::um::tlhelp32::{
+        CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W,
+        TH32CS_SNAPPROCESS,
+    };
+
+    let mut processes = HashMap::new();
+    // a parent that has exited can have its pid reused, the tree is only a best guess
+    unsafe {
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0);
+        if snapshot == INVALID_HANDLE_VALUE {
+            return processes;
+        }
+
+        let mut entry: PROCESSENTRY32W = mem::zeroed();
+        entry.dwSize = mem::size_of::<PROCESSENTRY32W>() as u32;
+        let mut more = Process32FirstW(snapshot, &mut entry) != 0;
+        while more {
+            let len = entry
+                .szExeFile
+                .iter()
+                .position(|c| *c == 0)
+                .unwrap_or(entry.szExeFile.len());
+            let exe = String::from_utf16_lossy(&entry.szExeFile[..len]);
+            let name = match exe.rsplit_once('.') {
+                Some((stem, extension)) if extension.eq_ignore_ascii_case("exe") => {
+                    stem.to_string()
+                }
+                _ => exe.clone(),
+            };
+            processes.insert(
+                entry.th32ProcessID,
+                Process {
+                    parent_pid: entry.th32ParentProcessID,
+                    name,
+                },
+            );
+            more = Process32NextW(snapshot, &mut entry) != 0;
+        }
+        CloseHandle(snapshot);
+    }
+    processes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree<'a>(processes: &'a [(u32, u32, &'a str)]) -> impl Fn(u32) -> Option<Process> + 'a {
+        |pid| {
+            processes
+                .iter()
+                .find(|(process_pid, _, _)| *process_pid == pid)
+                .map(|(_, parent_pid, name)| Process {
+                    parent_pid: *parent_pid,
+                    name: name.to_string(),
+                })
+        }
+    }
+
+    #[test]
+    fn skips_shells_and_launchers() {
+        let processes = [
+            (40, 30, "chrome_valet"),
+            (30, 20, "xdg-open"),
+            (20, 10, "sh"),
+            (10, 1, "slack"),
+            (1, 0, "systemd"),
+        ];
+        assert_eq!(find_source(40, tree(&processes)).as_deref(), Some("slack"));
+
+        let processes = [(40, 30, "chrome_valet"), (30, 1, "bash"), (1, 0, "init")];
+        assert_eq!(find_source(40, tree(&processes)), None);
+
+        // started by a service, not clicked in a program
+        let processes = [
+            (40, 30, "chrome_valet.exe"),
+            (30, 20, "svchost.exe"),
+            (20, 10, "services.exe"),
+            (10, 0, "wininit.exe"),
+        ];
+        assert_eq!(find_source(40, tree(&processes)), None);
+
+        // the parent has already exited
+        let processes = [(40, 30, "chrome_valet")];
+        assert_eq!(find_source(40, tree(&processes)), None);
+    }
+
+    #[test]
+    fn app_names_ignore_case_and_exe() {
+        assert!(app_matches("slack", "Slack.exe"));
+        assert!(app_matches("Discord.exe", "discord"));
+        assert!(app_matches("*teams*", "ms-teams"));
+        assert!(!app_matches("slack", "slack-helper"));
+        assert!(is_intermediary("Explorer.EXE"));
+    }
+
+    #[test]
+    fn parent_pid_from_proc_stat() {
+        assert_eq!(
+            parse_parent_pid("1234 (slack) S 987 1234 1234 0"),
+            Some(987)
+        );
+        assert_eq!(
+            parse_parent_pid("1234 (Web Content (2)) S 55 1 1"),
+            Some(55)
+        );
+        assert_eq!(parse_parent_pid("garbage"), None);
+    }
+}
@@ -0,0 +1,168 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use url::Url;
+
+use crate::config::SuggestionsConfig;
+use crate::history::{HistoryEntry, Routing};
+use crate::rules::RoutingRule;
+
+///
+/// a host that's usually opened in the same profile by hand, and could have a rule instead
+///
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RuleSuggestion {
+    pub host: String,
+
+    /// profile directory most of the host's links were opened in
+    pub profile: String,
+
+    /// links to the host opened in `profile`
+    pub count: usize,
+
+    /// links to the host picked by hand, in any profile
+    pub total: usize,
+}
+
+impl RuleSuggestion {
+    pub fn confidence(&self) -> f64 {
+        self.count as f64 / self.total as f64
+    }
+
+    pub fn rule(&self) -> RoutingRule {
+        RoutingRule {
+            host: Some(self.host.clone()),
+            profile: self.profile.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Default)]
+struct HostPicks<'a> {
+    profiles: BTreeMap<&'a str, usize>,
+    total: usize,
+    last_url: &'a str,
+}
+
+///
+/// suggestions from links picked in the picker, most picked first
+/// `is_routed` says whether a rule already covers a url, those hosts are skipped
+///
+pub fn suggest(
+    history: &[HistoryEntry],
+    config: &SuggestionsConfig,
+    is_routed: impl Fn(&str) -> bool,
+) -> Vec<RuleSuggestion> {
+    let mut hosts: BTreeMap<String, HostPicks> = BTreeMap::new();
+    for entry in history
+        .iter()
+        .filter(|entry| entry.routing == Routing::Manual)
+    {
+        let Some(profile) = &entry.profile else {
+            continue;
+        };
+        let Some(host) = Url::parse(&entry.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_lowercase))
+        else {
+            continue;
+        };
+
+        let picks = hosts.entry(host).or_default();
+        *picks.profiles.entry(profile).or_default() += 1;
+        picks.total += 1;
+        picks.last_url = &entry.url;
+    }
+
+    let mut suggestions: Vec<RuleSuggestion> = hosts
+        .into_iter()
+        .filter_map(|(host, picks)| {
+            // ties go to the first profile by name, so suggestions don't flip between runs
+            let (profile, count) = picks
+                .profiles
+                .iter()
+                .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))?;
+            let suggestion = RuleSuggestion {
+                host,
+                profile: profile.to_string(),
+                count: *count,
+                total: picks.total,
+            };
+
+            let confident = suggestion.count >= config.min_links
+                && suggestion.confidence() >= config.min_confidence;
+            (confident && !is_routed(picks.last_url)).then_some(suggestion)
+        })
+        .collect();
+
+    suggestions.sort_by(|a, b| b.count.cmp(&a.count).then(a.host.cmp(&b.host)));
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn picked(url: &str, profile: &str) -> HistoryEntry {
+        HistoryEntry {
+            time: 0,
+            original_url: url.to_string(),
+            url: url.to_string(),
+            browser: "chrome".to_string(),
+            profile: Some(profile.to_string()),
+            routing: Routing::Manual,
+            source_app: None,
+        }
+    }
+
+    fn config() -> SuggestionsConfig {
+        SuggestionsConfig {
+            enabled: true,
+            min_links: 3,
+            min_confidence: 0.75,
+        }
+    }
+
+    #[test]
+    fn suggests_host_mostly_opened_in_one_profile() {
+        let mut history: Vec<HistoryEntry> = (0..4)
+            .map(|i| picked(&format!("https://GitLab.corp/issues/{i}"), "Profile 1"))
+            .collect();
+        history.push(picked("https://gitlab.corp/", "Default"));
+
+        let suggestions = suggest(&history, &config(), |_| false);
+        assert_eq!(
+            suggestions,
+            vec![RuleSuggestion {
+                host: "gitlab.corp".to_string(),
+                profile: "Profile 1".to_string(),
+                count: 4,
+                total: 5,
+            }]
+        );
+        assert_eq!(suggestions[0].rule().host.as_deref(), Some("gitlab.corp"));
+    }
+
+    #[test]
+    fn below_thresholds_is_not_suggested() {
+        // too few links
+        let history = vec![picked("https://a.example/", "Default"); 2];
+        assert!(suggest(&history, &config(), |_| false).is_empty());
+
+        // split between profiles
+        let mut history = vec![picked("https://b.example/", "Default"); 3];
+        history.extend(vec![picked("https://b.example/", "Profile 1"); 2]);
+        assert!(suggest(&history, &config(), |_| false).is_empty());
+    }
+
+    #[test]
+    fn automatic_and_already_routed_links_are_ignored() {
+        let mut automatic = picked("https://c.example/", "Default");
+        automatic.routing = Routing::Automatic;
+        let history = vec![automatic; 5];
+        assert!(suggest(&history, &config(), |_| false).is_empty());
+
+        let history = vec![picked("https://d.example/", "Default"); 5];
+        assert!(suggest(&history, &config(), |url| url.contains("d.example")).is_empty());
+    }
+}
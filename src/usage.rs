@@ -0,0 +1,102 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io::{ErrorKind as IoErrorKind, Result as IoResult};
+use std::path::{Path, PathBuf};
+
+use crate::paths::app_data_dir;
+
+///
+/// how often chrome valet has opened links in each profile, kept in usage.json
+///
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileUsage {
+    /// keyed by profile directory
+    #[serde(default)]
+    pub open_counts: BTreeMap<String, u64>,
+}
+
+impl ProfileUsage {
+    pub fn usage_path() -> PathBuf {
+        app_data_dir().join("usage.json")
+    }
+
+    pub fn read() -> IoResult<Self> {
+        Self::read_at(&Self::usage_path())
+    }
+
+    fn read_at(usage_path: &Path) -> IoResult<Self> {
+        match File::open(usage_path) {
+            Ok(reader) => Ok(serde_json::from_reader(reader)?),
+            Err(e) => match e.kind() {
+                IoErrorKind::NotFound => Ok(ProfileUsage::default()),
+                _ => Err(e),
+            },
+        }
+    }
+
+    pub fn open_count(&self, profile_dir: &str) -> u64 {
+        self.open_counts
+            .get(profile_dir)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    ///
+    /// bumps the open count for `profile_dir` in usage.json
+    /// a usage.json that can't be read is left alone rather than starting the counts again
+    ///
+    pub fn record_open(profile_dir: &str) -> IoResult<()> {
+        Self::record_open_at(&Self::usage_path(), profile_dir)
+    }
+
+    fn record_open_at(usage_path: &Path, profile_dir: &str) -> IoResult<()> {
+        let mut usage = Self::read_at(usage_path)?;
+        *usage
+            .open_counts
+            .entry(profile_dir.to_string())
+            .or_default() += 1;
+
+        if let Some(p) = usage_path.parent() {
+            fs::create_dir_all(p)?
+        };
+        fs::write(usage_path, serde_json::to_string_pretty(&usage)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_are_counted_per_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let usage_path = dir.path().join("ChromeValet").join("usage.json");
+        assert_eq!(
+            ProfileUsage::read_at(&usage_path)
+                .unwrap()
+                .open_count("Default"),
+            0
+        );
+
+        ProfileUsage::record_open_at(&usage_path, "Default").unwrap();
+        ProfileUsage::record_open_at(&usage_path, "Profile 1").unwrap();
+        ProfileUsage::record_open_at(&usage_path, "Default").unwrap();
+
+        let usage = ProfileUsage::read_at(&usage_path).unwrap();
+        assert_eq!(usage.open_count("Default"), 2);
+        assert_eq!(usage.open_count("Profile 1"), 1);
+        assert_eq!(usage.open_count("Profile 2"), 0);
+    }
+
+    #[test]
+    fn unreadable_usage_is_left_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let usage_path = dir.path().join("usage.json");
+        fs::write(&usage_path, "{ not json").unwrap();
+
+        assert!(ProfileUsage::record_open_at(&usage_path, "Default").is_err());
+        assert_eq!(fs::read_to_string(&usage_path).unwrap(), "{ not json");
+    }
+}
@@ -0,0 +1,429 @@
+//!
+//! runs the chrome_valet binary against a fake user data dir and a stub browser
+//! the stub writes the arguments it was launched with to argv.txt
+//!
+#![cfg(unix)]
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+const URL: &str = "https://docs.example.com/page?q=1";
+
+/// how long to wait for the stub, chrome valet exits without waiting for the browser
+const LAUNCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+const LOCAL_STATE: &str = r#"{
+    "profile": {
+        "info_cache": {
+            "Default": {
+                "shortcut_name": "Personal",
+                "user_name": "me@example.com",
+                "active_time": 1700000000.0
+            },
+            "Profile 1": {
+                "shortcut_name": "Work",
+                "user_name": "me@work.example",
+                "active_time": 1700000100.0
+            }
+        }
+    }
+}"#;
+
+struct Fixture {
+    data_root: TempDir,
+}
+
+impl Fixture {
+    ///
+    /// a data root with chrome's Local State, and chromevalet.toml pointing chrome at the stub
+    /// `rules` is appended to chromevalet.toml
+    ///
+    fn new(rules: &str) -> Self {
+        let data_root = tempfile::tempdir().unwrap();
+        let fixture = Fixture { data_root };
+
+        let user_data_dir = fixture
+            .root()
+            .join("Google")
+            .join("Chrome")
+            .join("User Data");
+        fs::create_dir_all(&user_data_dir).unwrap();
+        fs::write(user_data_dir.join("Local State"), LOCAL_STATE).unwrap();
+
+        let stub = fixture.root().join("fake-browser.sh");
+        fs::write(
+            &stub,
+            "#!/bin/sh\n\
+             out=\"$(dirname \"$0\")/argv.txt\"\n\
+             printf '%s\\n' \"$@\" > \"$out.tmp\"\n\
+             mv \"$out.tmp\" \"$out\"\n",
+        )
+        .unwrap();
+        fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+
+        fs::create_dir_all(fixture.app_data_dir()).unwrap();
+        let config = format!("{rules}\n[browsers.chrome]\nexe = \"{}\"\n", stub.display());
+        fs::write(fixture.app_data_dir().join("chromevalet.toml"), config).unwrap();
+
+        fixture.write_prefs("");
+        fixture
+    }
+
+    fn root(&self) -> &Path {
+        self.data_root.path()
+    }
+
+    fn app_data_dir(&self) -> PathBuf {
+        self.root().join("ChromeValet")
+    }
+
+    fn argv_path(&self) -> PathBuf {
+        self.root().join("argv.txt")
+    }
+
+    fn write_prefs(&self, preferred_profile: &str) {
+        let prefs = format!(
+            r#"{{ "preferred_profile": "{preferred_profile}", "default_browser": "chrome" }}"#
+        );
+        fs::write(self.app_data_dir().join("prefs.json"), prefs).unwrap();
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_chrome_valet"))
+            .arg("--data-root")
+            .arg(self.root())
+            .arg("--no-picker")
+            .args(args)
+            .env_remove("CHROME_VALET_HOME")
+            .output()
+            .unwrap()
+    }
+
+    /// the stub's argv, one argument per line, once it has run
+    fn launched_args(&self) -> Vec<String> {
+        let begin = Instant::now();
+        while begin.elapsed() < LAUNCH_TIMEOUT {
+            if let Ok(argv) = fs::read_to_string(self.argv_path()) {
+                return argv.lines().map(String::from).collect();
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        panic!("the stub browser wasn't launched within {LAUNCH_TIMEOUT:?}");
+    }
+
+    fn assert_not_launched(&self) {
+        assert!(
+            !self.argv_path().exists(),
+            "the stub browser was launched with {:?}",
+            fs::read_to_string(self.argv_path()).unwrap_or_default()
+        );
+    }
+}
+
+fn assert_success(output: &Output) {
+    assert!(
+        output.status.success(),
+        "chrome_valet exited with {}\nstdout:\n{}\nstderr:\n{}",
+        output.status,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn opens_in_preferred_profile() {
+    let fixture = Fixture::new("");
+    fixture.write_prefs("Default");
+
+    let output = fixture.run(&["--url", URL]);
+
+    assert_success(&output);
+    assert_eq!(
+        fixture.launched_args(),
+        ["--profile-directory=Default", "--single-argument", URL]
+    );
+}
+
+#[test]
+fn data_root_fallback_is_logged() {
+    let fixture = Fixture::new("");
+    fixture.write_prefs("Default");
+
+    // without --data-root or CHROME_VALET_HOME, XDG_DATA_HOME stands in for %LOCALAPPDATA%
+    let output = Command::new(env!("CARGO_BIN_EXE_chrome_valet"))
+        .args(["--no-picker", "--url", URL])
+        .env_remove("CHROME_VALET_HOME")
+        .env_remove("LOCALAPPDATA")
+        .env("XDG_DATA_HOME", fixture.root())
+        .output()
+        .unwrap();
+
+    assert_success(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "LOCALAPPDATA not set, using {}",
+            fixture.root().display()
+        )),
+        "{stderr}"
+    );
+    assert_eq!(
+        fixture.launched_args(),
+        ["--profile-directory=Default", "--single-argument", URL]
+    );
+}
+
+#[test]
+fn rule_match_overrides_preferred_profile() {
+    let fixture = Fixture::new("[[rules]]\nhost = \"*.example.com\"\nprofile = \"Profile 1\"\n");
+    fixture.write_prefs("Default");
+
+    let output = fixture.run(&["--url", URL]);
+
+    assert_success(&output);
+    assert_eq!(
+        fixture.launched_args(),
+        ["--profile-directory=Profile 1", "--single-argument", URL]
+    );
+}
+
+#[test]
+fn unmatched_rule_falls_back_to_preferred_profile() {
+    let fixture = Fixture::new("[[rules]]\nhost = \"*.example.org\"\nprofile = \"Profile 1\"\n");
+    fixture.write_prefs("Default");
+
+    let output = fixture.run(&["--url", URL]);
+
+    assert_success(&output);
+    assert_eq!(
+        fixture.launched_args(),
+        ["--profile-directory=Default", "--single-argument", URL]
+    );
+}
+
+#[test]
+fn no_preferred_profile_bypasses_picker() {
+    // before a profile is picked, links go to the browser's last used profile
+    let fixture = Fixture::new("");
+
+    let output = fixture.run(&["--url", URL]);
+
+    assert_success(&output);
+    assert_eq!(fixture.launched_args(), ["--single-argument", URL]);
+}
+
+#[test]
+fn missing_preferred_profile_needs_picker() {
+    let fixture = Fixture::new("");
+    fixture.write_prefs("Profile 9");
+
+    let output = fixture.run(&["--url", URL]);
+
+    assert_eq!(output.status.code(), Some(2));
+    fixture.assert_not_launched();
+}
+
+#[test]
+fn rule_targeting_missing_profile_needs_picker() {
+    let fixture = Fixture::new("[[rules]]\nhost = \"*.example.com\"\nprofile = \"Profile 9\"\n");
+    fixture.write_prefs("Default");
+
+    let output = fixture.run(&["--url", URL]);
+
+    assert_eq!(output.status.code(), Some(2));
+    fixture.assert_not_launched();
+}
+
+#[test]
+fn force_ui_needs_picker() {
+    let fixture = Fixture::new("");
+    fixture.write_prefs("Default");
+
+    let output = fixture.run(&["--url", URL, "--force-ui"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    fixture.assert_not_launched();
+}
+
+#[test]
+fn explain_shows_winning_rule_without_launching() {
+    let fixture = Fixture::new(
+        "[[rules]]\nhost = \"*.example.org\"\nprofile = \"Default\"\n\
+         [[rules]]\nhost = \"*.example.com\"\nprofile = \"Profile 1\"\n",
+    );
+    fixture.write_prefs("Default");
+
+    let output = fixture.run(&["explain", URL, "--json"]);
+
+    assert_success(&output);
+    let explanation: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(explanation["original_url"], URL);
+    assert_eq!(explanation["url"], URL);
+    assert_eq!(explanation["rules"][0]["matched"], false);
+    assert_eq!(explanation["rules"][1]["winner"], true);
+    assert_eq!(explanation["rules"][1]["layer"], "config");
+    assert_eq!(explanation["profile"], "Profile 1");
+    assert_eq!(
+        explanation["command_line"].as_array().unwrap()[1..],
+        ["--profile-directory=Profile 1", "--single-argument", URL]
+    );
+    fixture.assert_not_launched();
+}
+
+#[test]
+fn timed_context_routes_with_its_rules_and_profile() {
+    let fixture = Fixture::new(
+        "[[rules]]\nhost = \"*.example.com\"\nprofile = \"Profile 1\"\n\
+         [contexts.Personal]\nprofile = \"Default\"\n\
+         [[contexts.Personal.rules]]\nhost = \"docs.example.com\"\nprofile = \"Default\"\n\
+         [contexts.Work]\nprofile = \"Profile 1\"\n",
+    );
+    fixture.write_prefs("Profile 1");
+
+    assert_success(&fixture.run(&["context", "use", "work"]));
+    assert_success(&fixture.run(&["context", "use", "Personal", "--for", "1h"]));
+
+    let output = fixture.run(&["explain", URL, "--json"]);
+    assert_success(&output);
+    let explanation: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(explanation["context"], "Personal");
+    assert_eq!(explanation["rules"][0]["layer"], "context");
+    assert_eq!(explanation["rules"][0]["winner"], true);
+    assert_eq!(explanation["profile"], "Default");
+
+    assert_success(&fixture.run(&["context", "off"]));
+    let output = fixture.run(&["explain", "https://other.test/", "--json"]);
+    let explanation: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(explanation["context"], "Work");
+    assert_eq!(explanation["profile"], "Profile 1");
+
+    let output = fixture.run(&["context", "use", "Client A"]);
+    assert_eq!(output.status.code(), Some(1));
+    fixture.assert_not_launched();
+}
+
+#[test]
+fn source_app_rule_routes_and_is_recorded() {
+    let fixture = Fixture::new(
+        "[[rules]]\nsource_app = \"slack\"\nprofile = \"Profile 1\"\n\
+         [[rules]]\nsource_app = \"discord\"\nprofile = \"Default\"\n",
+    );
+    fixture.write_prefs("Default");
+
+    let output = fixture.run(&["--url", URL, "--source-app", "Slack.exe"]);
+
+    assert_success(&output);
+    assert_eq!(
+        fixture.launched_args(),
+        ["--profile-directory=Profile 1", "--single-argument", URL]
+    );
+
+    let output = fixture.run(&["history", "--json"]);
+    assert_success(&output);
+    let entry: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(entry["source_app"], "Slack.exe");
+
+    let output = fixture.run(&["explain", URL, "--source-app", "discord", "--json"]);
+    let explanation: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(explanation["rules"][1]["winner"], true);
+    assert_eq!(explanation["profile"], "Default");
+}
+
+#[test]
+fn off_network_rule_falls_through_to_asking() {
+    // every linux machine has a loopback address, no interface has this name
+    let fixture = Fixture::new(
+        "[[rules]]\nhost = \"*.example.com\"\nprofile = \"Profile 1\"\n\
+         network = { interface = \"no-such-vpn*\" }\n\
+         [[rules]]\nhost = \"*.example.com\"\nask = true\n\
+         network = { cidr = \"127.0.0.0/8\" }\n",
+    );
+    fixture.write_prefs("Default");
+
+    let output = fixture.run(&["explain", URL, "--json"]);
+
+    assert_success(&output);
+    let explanation: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(explanation["rules"][0]["matched"], false);
+    assert_eq!(explanation["rules"][1]["winner"], true);
+    assert_eq!(explanation["action"], "show_picker");
+
+    let output = fixture.run(&["--url", URL]);
+    assert_eq!(output.status.code(), Some(2));
+    fixture.assert_not_launched();
+}
+
+#[test]
+fn routed_links_are_appended_and_pruned_to_the_limit() {
+    let fixture = Fixture::new("[history]\nmax_entries = 2\n");
+    fixture.write_prefs("Default");
+
+    for page in 1..=4 {
+        let url = format!("https://docs.example.com/{page}");
+        assert_success(&fixture.run(&["--url", &url]));
+    }
+
+    let urls = || -> Vec<String> {
+        let history = fs::read_to_string(fixture.app_data_dir().join("history.jsonl")).unwrap();
+        history
+            .lines()
+            .map(|line| {
+                let entry: serde_json::Value = serde_json::from_str(line).unwrap();
+                assert_eq!(entry["original_url"], entry["url"]);
+                entry["url"].as_str().unwrap().to_string()
+            })
+            .collect()
+    };
+    assert_eq!(urls().len(), 4);
+
+    assert_success(&fixture.run(&["history", "prune"]));
+    assert_eq!(
+        urls(),
+        ["https://docs.example.com/3", "https://docs.example.com/4"]
+    );
+}
+
+#[test]
+fn launches_count_towards_most_used() {
+    let fixture = Fixture::new("[[rules]]\nhost = \"*.work.example\"\nprofile = \"Profile 1\"\n");
+    fixture.write_prefs("Default");
+
+    for url in [URL, "https://jira.work.example/", URL] {
+        assert_success(&fixture.run(&["--url", url]));
+    }
+
+    let usage = fs::read_to_string(fixture.app_data_dir().join("usage.json")).unwrap();
+    let usage: serde_json::Value = serde_json::from_str(&usage).unwrap();
+    assert_eq!(usage["open_counts"]["Default"], 2);
+    assert_eq!(usage["open_counts"]["Profile 1"], 1);
+}
+
+#[test]
+fn closed_stdout_is_not_reported_as_a_crash() {
+    let fixture = Fixture::new("");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_chrome_valet"))
+        .arg("--data-root")
+        .arg(fixture.root())
+        .args(["config", "export", "--format", "json"])
+        .env_remove("CHROME_VALET_HOME")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // like `chrome_valet config export | head -0`
+    drop(child.stdout.take());
+    let output = child.wait_with_output().unwrap();
+
+    // std's message for the failed println! has to match crash::CLOSED_OUTPUT_MESSAGES
+    assert!(!output.status.success());
+    assert!(
+        !fixture.app_data_dir().join("crash_report.txt").exists(),
+        "closing stdout left a crash report, stderr:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}